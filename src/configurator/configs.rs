@@ -1,12 +1,27 @@
 use std::collections::{HashMap, HashSet as Set};
+use std::ffi::OsString;
 use std::io::{self, Error, ErrorKind};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use serde::Deserialize;
 
+use crate::configurator::drop_in::DropIn;
+use crate::configurator::{
+    DedupPolicy, MatchField, PathDisplay, Profile, QuarantineMode, Root, SourceOrder, TieBreak,
+    TraversalOrder,
+};
+use crate::query::MAX_SYMLINK_DEPTH;
 use crate::utils;
 use crate::utils::serde::deserialize_from_bytes;
 
+/// Variable of the environment replacing the configured roots, as paths separated by `:`
+pub const ROOTS_VAR: &str = "LAUNCH_SERVICE_ROOTS";
+/// Variable of the environment with the path of the settings file, read with its drop-ins
+pub const SETTINGS_FILE_VAR: &str = "SETTINGS_FILE";
+/// Variable of the environment with the settings, if no settings file is given
+const SETTINGS_VAR: &str = "SETTINGS";
+
 #[derive(Deserialize)]
 pub struct Configs {
     internal: Internal,
@@ -15,7 +30,27 @@ pub struct Configs {
 
 #[derive(Deserialize)]
 struct Internal {
-    paths: Vec<PathBuf>,
+    paths: Vec<Root>,
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+    #[serde(rename = "cacheDir", default)]
+    cache_dir: Option<PathBuf>,
+    #[serde(rename = "maxEntriesPerFolder", default)]
+    max_entries_per_folder: Option<usize>,
+    #[serde(rename = "queryCacheSize", default)]
+    query_cache_size: usize,
+    #[serde(rename = "outputCacheSize", default)]
+    output_cache_size: usize,
+    #[serde(rename = "maxOutputBytes", default)]
+    max_output_bytes: Option<usize>,
+    #[serde(rename = "startupCanaryQuery", default)]
+    startup_canary_query: Option<String>,
+    #[serde(rename = "enrichConcurrency", default)]
+    enrich_concurrency: Option<usize>,
+    #[serde(rename = "perRootTimeoutMillis", default)]
+    per_root_timeout_millis: Option<u64>,
+    #[serde(rename = "maxSymlinkDepth", default)]
+    max_symlink_depth: Option<usize>,
     #[serde(rename = "prefNames")]
     preferred_names: HashMap<String, String>,
 }
@@ -23,12 +58,121 @@ struct Internal {
 #[derive(Deserialize)]
 struct Configurable {
     #[serde(rename = "ignorePaths")]
-    ignored_paths: ConfigurableValue,
+    ignored_paths: ConfigurableValue<Set<PathBuf>>,
+    #[serde(rename = "allowOverridesIgnore", default)]
+    ignore_overrides: ConfigurableValue<Set<PathBuf>>,
+    #[serde(rename = "ignoreExtensions", default)]
+    ignored_extensions: ConfigurableValue<Vec<String>>,
+    #[serde(rename = "pinnedApps", default)]
+    pinned_paths: ConfigurableValue<Vec<PathBuf>>,
+    #[serde(rename = "stripVersions", default)]
+    versions_stripped: ConfigurableValue<bool>,
+    #[serde(rename = "stripBundleExtension", default = "default_true")]
+    bundle_extension_stripped: ConfigurableValue<bool>,
+    #[serde(rename = "symlinkedApps", default)]
+    symlinked_bundles: ConfigurableValue<bool>,
+    #[serde(rename = "stayOnFilesystem", default)]
+    filesystem_kept: ConfigurableValue<bool>,
+    #[serde(rename = "modifiedWithinDays", default)]
+    modified_within_days: ConfigurableValue<Option<u64>>,
+    #[serde(rename = "hideQuarantined", default)]
+    quarantine_mode: ConfigurableValue<QuarantineMode>,
+    #[serde(rename = "requireExecutable", default)]
+    executable_required: ConfigurableValue<bool>,
+    #[serde(rename = "strictBundles", default)]
+    strict_bundles: ConfigurableValue<bool>,
+    #[serde(rename = "checkCompatibility", default)]
+    compatibility_checked: ConfigurableValue<bool>,
+    #[serde(default)]
+    abbreviations: ConfigurableValue<HashMap<String, Vec<String>>>,
+    #[serde(default)]
+    corrections: ConfigurableValue<HashMap<String, String>>,
+    #[serde(default)]
+    synonyms: ConfigurableValue<Vec<Vec<String>>>,
+    #[serde(rename = "maxEditDistance", default)]
+    max_edit_distance: ConfigurableValue<usize>,
+    #[serde(rename = "explicitServices", default)]
+    explicit_services: ConfigurableValue<Vec<PathBuf>>,
+    #[serde(rename = "transliterateNames", default)]
+    names_transliterated: ConfigurableValue<bool>,
+    #[serde(rename = "spotlightFallback", default)]
+    spotlight_threshold: ConfigurableValue<usize>,
+    #[serde(rename = "pathDisplay", default)]
+    path_display: ConfigurableValue<PathDisplay>,
+    #[serde(rename = "emitUids", default)]
+    uids_emitted: ConfigurableValue<bool>,
+    #[serde(rename = "tieBreak", default)]
+    tie_break: ConfigurableValue<TieBreak>,
+    #[serde(rename = "preferNativeArch", default)]
+    native_arch_preferred: ConfigurableValue<bool>,
+    #[serde(rename = "dedupPolicy", default)]
+    dedup_policy: ConfigurableValue<DedupPolicy>,
+    #[serde(rename = "traversalOrder", default)]
+    traversal_order: ConfigurableValue<TraversalOrder>,
+    #[serde(rename = "sourceOrder", default)]
+    source_order: ConfigurableValue<SourceOrder>,
+    #[serde(rename = "verifyExists", default)]
+    existence_verified: ConfigurableValue<bool>,
+    #[serde(rename = "lossyCache", default)]
+    cache_lossy: ConfigurableValue<bool>,
+    #[serde(rename = "anchoredFirstChar", default)]
+    first_char_anchored: ConfigurableValue<bool>,
+    #[serde(rename = "pathFallback", default)]
+    path_fallback: ConfigurableValue<bool>,
+    #[serde(rename = "pathFragments", default = "default_true")]
+    path_fragments: ConfigurableValue<bool>,
+    #[serde(rename = "collapseWhitespace", default = "default_true")]
+    whitespace_collapsed: ConfigurableValue<bool>,
+    #[serde(rename = "matchFields", default = "default_match_fields")]
+    match_fields: ConfigurableValue<Vec<MatchField>>,
+    #[serde(
+        rename = "internalHelperFolders",
+        default = "default_internal_helper_folders"
+    )]
+    internal_helper_folders: ConfigurableValue<Vec<String>>,
+    #[serde(rename = "lengthWeight", default = "default_length_weight")]
+    length_weight: ConfigurableValue<u32>,
+    #[serde(rename = "recencyWeight", default)]
+    recency_weight: ConfigurableValue<u32>,
+    #[serde(rename = "checkers", default = "default_checkers")]
+    checkers: ConfigurableValue<Vec<String>>,
 }
 
-#[derive(Deserialize)]
-struct ConfigurableValue {
-    value: Set<PathBuf>,
+fn default_true() -> ConfigurableValue<bool> {
+    ConfigurableValue { value: true }
+}
+
+fn default_match_fields() -> ConfigurableValue<Vec<MatchField>> {
+    ConfigurableValue {
+        value: vec![MatchField::Name],
+    }
+}
+
+fn default_length_weight() -> ConfigurableValue<u32> {
+    ConfigurableValue { value: 10 }
+}
+
+fn default_checkers() -> ConfigurableValue<Vec<String>> {
+    ConfigurableValue {
+        value: ["hidden", "ignore", "symlink"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    }
+}
+
+fn default_internal_helper_folders() -> ConfigurableValue<Vec<String>> {
+    ConfigurableValue {
+        value: ["Contents", "Frameworks", "PlugIns", "XPCServices"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct ConfigurableValue<T> {
+    value: T,
 }
 
 macro_rules! expand_path {
@@ -44,10 +188,109 @@ macro_rules! expand_path {
 impl Configs {
     /// Construct config from given yaml file
     pub fn from<S: AsRef<str>>(content: S) -> io::Result<Self> {
-        let mut configs: Configs = deserialize_from_bytes(content.as_ref().as_bytes())
-            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
-        expand_path!(&mut configs.internal.paths);
+        Self::normalize(Self::parse(content.as_ref())?)
+    }
+
+    /// Construct config from the file, merged with roots and ignored paths
+    /// from `*.yaml` files in the sibling `settings.d` folder
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut configs = Self::parse(&std::fs::read_to_string(path)?)?;
+        for drop_in in DropIn::read_dir(path.with_file_name("settings.d"))? {
+            configs.internal.paths.extend(drop_in.paths);
+            configs
+                .configurable
+                .ignored_paths
+                .value
+                .extend(drop_in.ignored_paths);
+        }
+        Self::normalize(configs)
+    }
+
+    /// Construct config from the file at the path in `SETTINGS_FILE_VAR` along with its
+    /// drop-ins, or from the content of the `SETTINGS` variable if no file is given
+    pub fn from_env() -> io::Result<Self> {
+        if let Some(path) = std::env::var_os(SETTINGS_FILE_VAR) {
+            return Self::from_file(path);
+        }
+        let content = std::env::var(SETTINGS_VAR).map_err(|error| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("Failed to get {} from environment: {}", SETTINGS_VAR, error),
+            )
+        })?;
+        Self::from(content)
+    }
+
+    /// Replace the roots with the paths in `ROOTS_VAR` if it has any, so the environment
+    /// takes precedence over the settings and their drop-ins. Tildes in the paths are expanded
+    pub fn with_env_roots(self) -> Self {
+        self.with_roots_from(std::env::var_os(ROOTS_VAR))
+    }
+
+    /// Replace the roots with the paths in the value of `ROOTS_VAR`, like `with_env_roots`
+    fn with_roots_from(mut self, value: Option<OsString>) -> Self {
+        let value = match value {
+            Some(value) => value,
+            None => return self,
+        };
+        let roots = std::env::split_paths(&value)
+            .filter(|path| !path.as_os_str().is_empty())
+            .map(|path| match path.to_str() {
+                Some(path) => Root::new(utils::expand_tilde(path)),
+                None => Root::new(path),
+            })
+            .collect::<Vec<_>>();
+        if !roots.is_empty() {
+            self.internal.paths = roots;
+        }
+        self
+    }
+
+    fn parse(content: &str) -> io::Result<Self> {
+        deserialize_from_bytes(content.as_bytes())
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))
+    }
+
+    /// Expand tildes in paths, lowercase abbreviations, corrections and synonyms,
+    /// and validate profiles of roots
+    fn normalize(mut configs: Configs) -> io::Result<Self> {
+        for root in configs.internal.paths.iter_mut() {
+            if let Some(path) = root.path().to_str() {
+                *root.path_mut() = utils::expand_tilde(path);
+            }
+            if let Some(profile) = root.profile() {
+                if !configs.internal.profiles.contains_key(profile) {
+                    let message = format!("Unknown profile: {}", profile);
+                    return Err(Error::new(ErrorKind::InvalidData, message));
+                }
+            }
+        }
+        if let Some(dir) = configs
+            .internal
+            .cache_dir
+            .as_ref()
+            .and_then(|dir| dir.to_str())
+        {
+            configs.internal.cache_dir = Some(utils::expand_tilde(dir));
+        }
+        let abbreviations = &mut configs.configurable.abbreviations.value;
+        *abbreviations = abbreviations
+            .drain()
+            .map(|(abbreviation, expansions)| (abbreviation.to_lowercase(), expansions))
+            .collect();
+        let corrections = &mut configs.configurable.corrections.value;
+        *corrections = corrections
+            .drain()
+            .map(|(misspelling, correction)| (misspelling.to_lowercase(), correction))
+            .collect();
+        for synonym in configs.configurable.synonyms.value.iter_mut().flatten() {
+            *synonym = synonym.to_lowercase();
+        }
         expand_path!(&mut configs.configurable.ignored_paths.value);
+        expand_path!(&mut configs.configurable.ignore_overrides.value);
+        expand_path!(&mut configs.configurable.pinned_paths.value);
+        expand_path!(&mut configs.configurable.explicit_services.value);
         Ok(configs)
     }
 
@@ -56,11 +299,265 @@ impl Configs {
         &self.configurable.ignored_paths.value
     }
 
+    /// Get paths kept even if they are or are under ignored paths
+    pub fn get_ignore_overrides(&self) -> &Set<PathBuf> {
+        &self.configurable.ignore_overrides.value
+    }
+
+    /// Get extensions of files to ignore
+    pub fn get_ignore_extensions(&self) -> &Vec<String> {
+        &self.configurable.ignored_extensions.value
+    }
+
+    /// Get paths of apps that always appear first
+    pub fn get_pinned_paths(&self) -> &Vec<PathBuf> {
+        &self.configurable.pinned_paths.value
+    }
+
+    /// Get if trailing versions should be stripped from names
+    pub fn is_versions_stripped(&self) -> bool {
+        self.configurable.versions_stripped.value
+    }
+
+    /// Get if names leave out bundle extensions like `.app`, so requests may end with them or not
+    pub fn is_bundle_extension_stripped(&self) -> bool {
+        self.configurable.bundle_extension_stripped.value
+    }
+
+    /// Get if symlinks pointing to bundles should be included
+    pub fn is_symlinked_bundles_included(&self) -> bool {
+        self.configurable.symlinked_bundles.value
+    }
+
+    /// Get if walking should stay on the filesystem of each root
+    pub fn is_filesystem_kept(&self) -> bool {
+        self.configurable.filesystem_kept.value
+    }
+
+    /// Get how recently bundles must be modified to be included, none to include all
+    pub fn get_modified_within(&self) -> Option<Duration> {
+        self.configurable
+            .modified_within_days
+            .value
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60))
+    }
+
+    /// Get how bundles not yet approved after downloading are treated
+    pub fn get_quarantine_mode(&self) -> QuarantineMode {
+        self.configurable.quarantine_mode.value
+    }
+
+    /// Get if bundles without an executable should be excluded
+    pub fn is_executable_required(&self) -> bool {
+        self.configurable.executable_required.value
+    }
+
+    /// Get if folders named like bundles are walked into unless they have bundle contents
+    pub fn is_strict_bundles(&self) -> bool {
+        self.configurable.strict_bundles.value
+    }
+
+    /// Get if bundles requiring a newer system should be excluded
+    pub fn is_compatibility_checked(&self) -> bool {
+        self.configurable.compatibility_checked.value
+    }
+
+    /// Get fields of services that queries are matched against
+    pub fn get_match_fields(&self) -> &Vec<MatchField> {
+        &self.configurable.match_fields.value
+    }
+
+    /// Get the largest score bonus for matching the whole name, 0 to ignore name lengths
+    pub fn get_length_weight(&self) -> u32 {
+        self.configurable.length_weight.value
+    }
+
+    /// Get the largest score bonus for bundles installed just now, 0 to ignore install times
+    pub fn get_recency_weight(&self) -> u32 {
+        self.configurable.recency_weight.value
+    }
+
+    /// Get names of the enabled checkers, both built in and registered
+    pub fn get_checkers(&self) -> &Vec<String> {
+        &self.configurable.checkers.value
+    }
+
+    /// Get if copies of the same app built for another architecture give way to
+    /// universal or native ones when deduplicated
+    pub fn is_native_arch_preferred(&self) -> bool {
+        self.configurable.native_arch_preferred.value
+    }
+
+    /// Get if cached services are checked to still exist before they are returned
+    pub fn is_existence_verified(&self) -> bool {
+        self.configurable.existence_verified.value
+    }
+
+    /// Get if invalid UTF-8 in the cache is replaced, so a corrupted byte does not
+    /// drop its service, rather than failing the read
+    pub fn is_cache_lossy(&self) -> bool {
+        self.configurable.cache_lossy.value
+    }
+
+    /// Get if the first character of requests must be the first letter of matched names
+    pub fn is_first_char_anchored(&self) -> bool {
+        self.configurable.first_char_anchored.value
+    }
+
+    /// Get if requests matching no configured field are matched against full paths
+    pub fn is_path_fallback_enabled(&self) -> bool {
+        self.configurable.path_fallback.value
+    }
+
+    /// Get if requests containing `/` are matched as folders leading to bundles, in order
+    pub fn is_path_fragments_enabled(&self) -> bool {
+        self.configurable.path_fragments.value
+    }
+
+    /// Get if requests are trimmed, with runs of whitespace in them collapsed to single spaces
+    pub fn is_whitespace_collapsed(&self) -> bool {
+        self.configurable.whitespace_collapsed.value
+    }
+
+    /// Get names of folders holding internal helpers, which are excluded with their contents
+    pub fn get_internal_helper_folders(&self) -> &Vec<String> {
+        &self.configurable.internal_helper_folders.value
+    }
+
+    /// Get if non ASCII names should also be matched by their transliterations
+    pub fn is_names_transliterated(&self) -> bool {
+        self.configurable.names_transliterated.value
+    }
+
+    /// Get the number of results below which Spotlight is also searched, 0 to never search
+    pub fn get_spotlight_threshold(&self) -> usize {
+        self.configurable.spotlight_threshold.value
+    }
+
+    /// Get how paths of services are displayed
+    pub fn get_path_display(&self) -> PathDisplay {
+        self.configurable.path_display.value
+    }
+
+    /// Get if each output service should carry an id that stays the same across queries
+    pub fn is_uids_emitted(&self) -> bool {
+        self.configurable.uids_emitted.value
+    }
+
+    /// Get how services ranked the same are ordered
+    pub fn get_tie_break(&self) -> TieBreak {
+        self.configurable.tie_break.value
+    }
+
+    /// Get which one of services sharing the same dedup key is kept
+    pub fn get_dedup_policy(&self) -> DedupPolicy {
+        self.configurable.dedup_policy.value
+    }
+
+    /// Get the order folders under the roots are walked in
+    pub fn get_traversal_order(&self) -> TraversalOrder {
+        self.configurable.traversal_order.value
+    }
+
+    /// Get whether services read from the cache or read fresh are listed first
+    pub fn get_source_order(&self) -> SourceOrder {
+        self.configurable.source_order.value
+    }
+
+    /// Get paths of services to include without walking
+    pub fn get_explicit_services(&self) -> &Vec<PathBuf> {
+        &self.configurable.explicit_services.value
+    }
+
+    /// Get abbreviations in lowercase, and the names they expand to
+    pub fn get_abbreviations(&self) -> &HashMap<String, Vec<String>> {
+        &self.configurable.abbreviations.value
+    }
+
+    /// Get misspelled terms in lowercase, and the terms replacing them in requests
+    pub fn get_corrections(&self) -> &HashMap<String, String> {
+        &self.configurable.corrections.value
+    }
+
+    /// Get configured groups of synonyms in lowercase, matched in addition to the built-in ones
+    pub fn get_synonyms(&self) -> &[Vec<String>] {
+        &self.configurable.synonyms.value
+    }
+
+    /// Get the most edits a misspelled query term may be away from a name, 0 to disable
+    pub fn get_max_edit_distance(&self) -> usize {
+        self.configurable.max_edit_distance.value
+    }
+
     /// Get paths need to be cached
-    pub fn get_paths(&self) -> &Vec<PathBuf> {
+    pub fn get_paths(&self) -> Vec<&Path> {
+        self.internal.paths.iter().map(Root::path).collect()
+    }
+
+    /// Get roots need to be cached
+    pub fn get_roots(&self) -> &Vec<Root> {
         &self.internal.paths
     }
 
+    /// Get checker profile of the root. Roots without a profile use the strict one
+    pub fn get_profile(&self, root: &Root) -> Profile {
+        root.profile()
+            .and_then(|profile| self.internal.profiles.get(profile))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Get folder to store the cache of discovered services. No cache is used if missing
+    pub fn get_cache_dir(&self) -> Option<&Path> {
+        self.internal.cache_dir.as_deref()
+    }
+
+    /// Get maximum number of entries read in each folder, none if unlimited
+    pub fn get_max_entries_per_folder(&self) -> Option<usize> {
+        self.internal.max_entries_per_folder
+    }
+
+    /// Get the longest time walking each root may take, none if unlimited
+    pub fn get_per_root_timeout(&self) -> Option<Duration> {
+        self.internal
+            .per_root_timeout_millis
+            .map(Duration::from_millis)
+    }
+
+    /// Get number of chained symlinks followed to find a bundle, `MAX_SYMLINK_DEPTH` if unset
+    pub fn get_max_symlink_depth(&self) -> usize {
+        self.internal.max_symlink_depth.unwrap_or(MAX_SYMLINK_DEPTH)
+    }
+
+    /// Get number of bundle infos read at the same time, one per CPU if unset
+    pub fn get_enrich_concurrency(&self) -> usize {
+        self.internal
+            .enrich_concurrency
+            .filter(|concurrency| *concurrency > 0)
+            .or_else(|| std::thread::available_parallelism().ok().map(usize::from))
+            .unwrap_or(1)
+    }
+
+    /// Get number of recent query results kept in memory
+    pub fn get_query_cache_size(&self) -> usize {
+        self.internal.query_cache_size
+    }
+
+    /// Get number of recent serialized outputs of `query` kept in memory
+    pub fn get_output_cache_size(&self) -> usize {
+        self.internal.output_cache_size
+    }
+
+    /// Get maximum number of bytes `query` writes for a request, none if unlimited
+    pub fn get_max_output_bytes(&self) -> Option<usize> {
+        self.internal.max_output_bytes
+    }
+
+    /// Get the request run once to verify queries work, none to skip verifying
+    pub fn get_startup_canary_query(&self) -> Option<&str> {
+        self.internal.startup_canary_query.as_deref()
+    }
+
     pub fn get_pref_names(&self) -> &HashMap<String, String> {
         &self.internal.preferred_names
     }
@@ -68,7 +565,107 @@ impl Configs {
 
 #[cfg(test)]
 pub mod configs_test {
+    use std::ffi::OsString;
+    use std::path::Path;
+
+    use serde_json::{json, Value};
+
     use crate::configurator::configs::Configs;
+    use crate::utils;
+    use crate::utils::fixture;
+
+    /// Get content after applying `edit` to the parsed default settings
+    pub fn get_content_with<F: FnOnce(&mut Value)>(edit: F) -> String {
+        let mut value: Value = serde_json::from_str(&get_content()).expect("Invalid settings");
+        edit(&mut value);
+        value.to_string()
+    }
+
+    #[test]
+    fn test_from_file_with_drop_ins() {
+        let dir = fixture::temp_dir("configs_drop_ins");
+        let settings = dir.join("settings.json");
+        let content =
+            get_content_with(|value| value["internal"]["paths"] = json!(["/Applications"]));
+        std::fs::write(&settings, content).unwrap();
+        std::fs::create_dir_all(dir.join("settings.d")).unwrap();
+        std::fs::write(
+            dir.join("settings.d/10-homebrew.yaml"),
+            "paths:\n  - /opt/homebrew/Caskroom\nignorePaths:\n  - /opt/homebrew/Caskroom/old\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("settings.d/20-nix.yaml"),
+            "paths:\n  - path: ~/Applications/Nix Apps\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("settings.d/README"), "not a drop-in").unwrap();
+        let configs = Configs::from_file(&settings).expect("Invalid settings");
+        let paths = configs.get_paths();
+        assert_eq!(paths.len(), 3);
+        assert_eq!(paths[0], Path::new("/Applications"));
+        assert_eq!(paths[1], Path::new("/opt/homebrew/Caskroom"));
+        assert_eq!(paths[2], utils::expand_tilde("~/Applications/Nix Apps"));
+        assert!(configs
+            .get_ignore_paths()
+            .contains(Path::new("/opt/homebrew/Caskroom/old")));
+    }
+
+    #[test]
+    fn test_with_roots_from() {
+        let configs = Configs::from(get_content()).expect("Invalid settings");
+        let configured = configs.get_paths().len();
+        let configs = configs.with_roots_from(None);
+        assert_eq!(configs.get_paths().len(), configured);
+        let configs = configs.with_roots_from(Some(OsString::new()));
+        assert_eq!(configs.get_paths().len(), configured);
+        let configs = Configs::from(get_content())
+            .expect("Invalid settings")
+            .with_roots_from(Some(OsString::from("/opt/apps:~/Applications")));
+        let paths = configs.get_paths();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0], Path::new("/opt/apps"));
+        assert_eq!(paths[1], utils::expand_tilde("~/Applications"));
+        assert!(configs
+            .get_roots()
+            .iter()
+            .all(|root| root.profile().is_none()));
+    }
+
+    #[test]
+    fn test_from_settings_file() {
+        let dir = fixture::temp_dir("configs_settings_file");
+        let settings = dir.join("settings.json");
+        let content =
+            get_content_with(|value| value["internal"]["paths"] = json!(["/Applications"]));
+        std::fs::write(&settings, content).unwrap();
+        std::fs::create_dir_all(dir.join("settings.d")).unwrap();
+        std::fs::write(
+            dir.join("settings.d/10-homebrew.yaml"),
+            "paths:\n  - /opt/homebrew/Caskroom\n",
+        )
+        .unwrap();
+        let configs = Configs::from_file(&settings).expect("Invalid settings");
+        assert_eq!(
+            configs.get_paths(),
+            [
+                Path::new("/Applications"),
+                Path::new("/opt/homebrew/Caskroom")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_file_without_drop_ins() {
+        let dir = fixture::temp_dir("configs_no_drop_ins");
+        let settings = dir.join("settings.json");
+        std::fs::write(&settings, get_content()).unwrap();
+        let configs = Configs::from_file(&settings).expect("Invalid settings");
+        assert_eq!(
+            configs.get_paths().len(),
+            Configs::from(get_content()).unwrap().get_paths().len()
+        );
+    }
 
     pub fn get_content() -> String {
         r#"
@@ -77,6 +674,154 @@ pub mod configs_test {
     "ignorePaths": {
       "displayName": "Paths to Ignore",
       "value": []
+    },
+    "allowOverridesIgnore": {
+      "displayName": "Paths Kept Despite Ignoring",
+      "value": []
+    },
+    "ignoreExtensions": {
+      "displayName": "File Extensions to Ignore",
+      "value": []
+    },
+    "pinnedApps": {
+      "displayName": "Pinned Apps",
+      "value": []
+    },
+    "stripVersions": {
+      "displayName": "Hide Versions in Names",
+      "value": false
+    },
+    "stripBundleExtension": {
+      "displayName": "Hide App Extensions in Names",
+      "value": true
+    },
+    "symlinkedApps": {
+      "displayName": "Include Symlinked Apps",
+      "value": false
+    },
+    "stayOnFilesystem": {
+      "displayName": "Skip Other Volumes",
+      "value": false
+    },
+    "modifiedWithinDays": {
+      "displayName": "Only Apps Added Within Days",
+      "value": null
+    },
+    "hideQuarantined": {
+      "displayName": "Hide Unapproved Downloads",
+      "value": false
+    },
+    "requireExecutable": {
+      "displayName": "Hide Broken Apps",
+      "value": false
+    },
+    "strictBundles": {
+      "displayName": "Skip Folders Named Like Apps",
+      "value": false
+    },
+    "checkCompatibility": {
+      "displayName": "Hide Incompatible Apps",
+      "value": false
+    },
+    "abbreviations": {
+      "displayName": "Abbreviations",
+      "value": {}
+    },
+    "corrections": {
+      "displayName": "Corrected Misspellings",
+      "value": {}
+    },
+    "synonyms": {
+      "displayName": "Synonyms",
+      "value": []
+    },
+    "maxEditDistance": {
+      "displayName": "Typo Tolerance",
+      "value": 0
+    },
+    "explicitServices": {
+      "displayName": "Additional Apps",
+      "value": []
+    },
+    "transliterateNames": {
+      "displayName": "Match Romanized Names",
+      "value": false
+    },
+    "spotlightFallback": {
+      "displayName": "Search Spotlight Below Results",
+      "value": 0
+    },
+    "pathDisplay": {
+      "displayName": "Show Paths",
+      "value": "absolute"
+    },
+    "emitUids": {
+      "displayName": "Output Stable App IDs",
+      "value": false
+    },
+    "tieBreak": {
+      "displayName": "Order of Equally Ranked Apps",
+      "value": "none"
+    },
+    "preferNativeArch": {
+      "displayName": "Prefer Native Copies of Apps",
+      "value": false
+    },
+    "dedupPolicy": {
+      "displayName": "Copy Kept of Duplicate Apps",
+      "value": "preferUpdated"
+    },
+    "traversalOrder": {
+      "displayName": "Order Folders Are Searched In",
+      "value": "depthFirst"
+    },
+    "sourceOrder": {
+      "displayName": "Apps Listed First",
+      "value": "cachedFirst"
+    },
+    "verifyExists": {
+      "displayName": "Hide Removed Apps Still Cached",
+      "value": false
+    },
+    "lossyCache": {
+      "displayName": "Keep Cached Apps With Corrupted Names",
+      "value": false
+    },
+    "anchoredFirstChar": {
+      "displayName": "Match First Letters Only",
+      "value": false
+    },
+    "pathFallback": {
+      "displayName": "Match Paths When Nothing Is Found",
+      "value": false
+    },
+    "pathFragments": {
+      "displayName": "Match Folders Separated by /",
+      "value": true
+    },
+    "collapseWhitespace": {
+      "displayName": "Ignore Extra Spaces",
+      "value": true
+    },
+    "matchFields": {
+      "displayName": "Match Against",
+      "value": ["name"]
+    },
+    "internalHelperFolders": {
+      "displayName": "Folders of Internal Helpers",
+      "value": ["Contents", "Frameworks", "PlugIns", "XPCServices"]
+    },
+    "lengthWeight": {
+      "displayName": "Preference for Short Names",
+      "value": 10
+    },
+    "recencyWeight": {
+      "displayName": "Preference for New Apps",
+      "value": 0
+    },
+    "checkers": {
+      "displayName": "Enabled Checkers",
+      "value": ["hidden", "ignore", "symlink"]
     }
   },
   "internal": {
@@ -127,6 +872,18 @@ pub mod configs_test {
         assert!(res.is_ok(), res.err().unwrap().to_string());
     }
 
+    #[test]
+    fn test_default_length_weight() {
+        let content = get_content_with(|value| {
+            value["configurable"]
+                .as_object_mut()
+                .unwrap()
+                .remove("lengthWeight");
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        assert_eq!(configs.get_length_weight(), 10);
+    }
+
     #[test]
     fn test_get_ignore_paths() {
         let res = Configs::from(get_content()).unwrap();
@@ -134,6 +891,39 @@ pub mod configs_test {
         assert!(ignore_path.is_empty())
     }
 
+    #[test]
+    fn test_get_pinned_paths() {
+        let res = Configs::from(get_content()).unwrap();
+        assert!(res.get_pinned_paths().is_empty())
+    }
+
+    #[test]
+    fn test_get_abbreviations() {
+        let content = get_content_with(|value| {
+            value["configurable"]["abbreviations"]["value"] = json!({"Chrome": ["Google Chrome"]});
+        });
+        let res = Configs::from(content).unwrap();
+        assert_eq!(res.get_abbreviations()["chrome"], vec!["Google Chrome"]);
+    }
+
+    #[test]
+    fn test_get_corrections() {
+        let content = get_content_with(|value| {
+            value["configurable"]["corrections"]["value"] = json!({"Chorme": "chrome"});
+        });
+        let res = Configs::from(content).unwrap();
+        assert_eq!(res.get_corrections()["chorme"], "chrome");
+    }
+
+    #[test]
+    fn test_get_synonyms() {
+        let content = get_content_with(|value| {
+            value["configurable"]["synonyms"]["value"] = json!([["Editor", "TextEdit"]]);
+        });
+        let res = Configs::from(content).unwrap();
+        assert_eq!(res.get_synonyms(), [vec!["editor", "textedit"]]);
+    }
+
     #[test]
     fn test_get_system_paths() {
         let res = Configs::from(get_content()).unwrap();
@@ -141,6 +931,29 @@ pub mod configs_test {
         assert_eq!(cached_path.len(), 6);
     }
 
+    #[test]
+    fn test_get_profile() {
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] =
+                json!(["/Applications", {"path": "~/Apps", "profile": "permissive"}]);
+            value["internal"]["profiles"] = json!({"permissive": {"checkSymlink": false}});
+        });
+        let res = Configs::from(content).unwrap();
+        let roots = res.get_roots();
+        assert!(res.get_profile(&roots[0]).symlink_checked);
+        assert!(!res.get_profile(&roots[1]).symlink_checked);
+        assert!(res.get_profile(&roots[1]).hidden_checked);
+        assert!(roots[1].path().is_absolute());
+    }
+
+    #[test]
+    fn test_unknown_profile() {
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([{"path": "/Applications", "profile": "unknown"}]);
+        });
+        assert!(Configs::from(content).is_err());
+    }
+
     #[test]
     fn test_get_pref_names() {
         let res = Configs::from(get_content()).unwrap();
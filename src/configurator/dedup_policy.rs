@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+/// Which one of services sharing the same dedup key is kept
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum DedupPolicy {
+    /// Keep the one read from the cache over one found by a fresh read
+    PreferCached,
+    /// Keep the one found by a fresh read, whose metadata is current
+    #[default]
+    PreferUpdated,
+    /// Keep the one with the newer version, wherever it is found
+    PreferNewest,
+}
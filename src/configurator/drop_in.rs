@@ -0,0 +1,43 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::configurator::Root;
+
+/// Extra settings contributed by a file in the drop-in folder
+#[derive(Deserialize, Default)]
+pub struct DropIn {
+    #[serde(default)]
+    pub paths: Vec<Root>,
+    #[serde(rename = "ignorePaths", default)]
+    pub ignored_paths: HashSet<PathBuf>,
+}
+
+impl DropIn {
+    /// Read all `*.yaml` files in the folder in the order of their names.
+    /// A missing folder contributes nothing
+    pub fn read_dir<P: AsRef<Path>>(dir: P) -> io::Result<Vec<DropIn>> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let mut files = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "yaml") && path.is_file())
+            .collect::<Vec<_>>();
+        files.sort();
+        files.iter().map(DropIn::read).collect()
+    }
+
+    fn read(path: &PathBuf) -> io::Result<DropIn> {
+        let content = fs::read(path)?;
+        serde_yaml::from_slice(&content).map_err(|error| {
+            let message = format!("Invalid drop-in {}: {}", path.display(), error);
+            Error::new(ErrorKind::InvalidData, message)
+        })
+    }
+}
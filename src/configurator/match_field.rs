@@ -0,0 +1,19 @@
+use serde::Deserialize;
+
+/// Field of a service that queries are matched against
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchField {
+    /// The displayed name
+    Name,
+    /// The name shown to users if it differs from the displayed one, e.g. `Calculatrice`.
+    /// Matched along with `Name` rather than configured on its own
+    #[serde(skip)]
+    LocalizedName,
+    /// The bundle identifier, e.g. `com.apple.calculator`
+    BundleId,
+    /// The name of the binary in `Contents/MacOS`, e.g. `Electron`
+    Executable,
+    /// The full path to the service
+    FullPath,
+}
@@ -1,5 +1,26 @@
 #[cfg(test)]
-pub use configs::configs_test::get_content;
-pub use configs::Configs;
+pub use configs::configs_test::{get_content, get_content_with};
+pub use configs::{Configs, ROOTS_VAR, SETTINGS_FILE_VAR};
+pub use dedup_policy::DedupPolicy;
+pub use match_field::MatchField;
+pub use path_display::PathDisplay;
+pub use profile::Profile;
+pub use quarantine_mode::QuarantineMode;
+pub use root::Root;
+pub use source_order::SourceOrder;
+pub use tie_break::TieBreak;
+pub use traversal_order::TraversalOrder;
+pub use watcher::ConfigWatcher;
 
 mod configs;
+mod dedup_policy;
+mod drop_in;
+mod match_field;
+mod path_display;
+mod profile;
+mod quarantine_mode;
+mod root;
+mod source_order;
+mod tie_break;
+mod traversal_order;
+mod watcher;
@@ -0,0 +1,12 @@
+use serde::Deserialize;
+
+/// How paths of services are displayed
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PathDisplay {
+    /// The full path
+    #[default]
+    Absolute,
+    /// The path relative to the root containing the service
+    Relative,
+}
@@ -0,0 +1,27 @@
+use serde::Deserialize;
+
+/// Set of checks applied when walking a root
+#[derive(Deserialize, Clone, Copy)]
+pub struct Profile {
+    #[serde(rename = "checkHidden", default = "enabled")]
+    pub hidden_checked: bool,
+    #[serde(rename = "checkIgnored", default = "enabled")]
+    pub ignored_checked: bool,
+    #[serde(rename = "checkSymlink", default = "enabled")]
+    pub symlink_checked: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for Profile {
+    /// The strict profile with all checks enabled
+    fn default() -> Self {
+        Profile {
+            hidden_checked: true,
+            ignored_checked: true,
+            symlink_checked: true,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use serde::Deserialize;
+
+/// How bundles carrying the quarantine attribute of downloads are treated,
+/// configured as `true` or `false` to hide them or not, or by the name of the mode
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(from = "QuarantineEntry")]
+pub enum QuarantineMode {
+    /// Keep bundles whether they carry the attribute or not
+    #[default]
+    Shown,
+    /// Exclude bundles found carrying the attribute
+    Hidden,
+    /// Keep only bundles found without the attribute, so bundles whose
+    /// attributes cannot be read are excluded as well
+    OnlyApproved,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum QuarantineEntry {
+    Hidden(bool),
+    Named(NamedMode),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum NamedMode {
+    Shown,
+    Hidden,
+    OnlyApproved,
+}
+
+impl From<QuarantineEntry> for QuarantineMode {
+    fn from(entry: QuarantineEntry) -> Self {
+        match entry {
+            QuarantineEntry::Hidden(false) | QuarantineEntry::Named(NamedMode::Shown) => {
+                QuarantineMode::Shown
+            }
+            QuarantineEntry::Hidden(true) | QuarantineEntry::Named(NamedMode::Hidden) => {
+                QuarantineMode::Hidden
+            }
+            QuarantineEntry::Named(NamedMode::OnlyApproved) => QuarantineMode::OnlyApproved,
+        }
+    }
+}
+
+#[cfg(test)]
+mod quarantine_mode_test {
+    use crate::configurator::quarantine_mode::QuarantineMode;
+
+    #[test]
+    fn test_deserialize() {
+        let mode = |source: &str| serde_json::from_str::<QuarantineMode>(source).ok();
+        assert_eq!(mode("false"), Some(QuarantineMode::Shown));
+        assert_eq!(mode("true"), Some(QuarantineMode::Hidden));
+        assert_eq!(
+            mode(r#""onlyApproved""#),
+            Some(QuarantineMode::OnlyApproved)
+        );
+        assert_eq!(mode(r#""hidden""#), Some(QuarantineMode::Hidden));
+        assert_eq!(mode(r#""strict""#), None);
+    }
+}
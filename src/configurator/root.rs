@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// A root folder to walk, configured as either a path or a path with options
+#[derive(Deserialize)]
+#[serde(from = "RootEntry")]
+pub struct Root {
+    path: PathBuf,
+    options: RootOptions,
+}
+
+#[derive(Deserialize, Default)]
+struct RootOptions {
+    profile: Option<String>,
+    recursive: Option<bool>,
+    label: Option<String>,
+    priority: Option<i32>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RootEntry {
+    Path(PathBuf),
+    Detailed {
+        path: PathBuf,
+        #[serde(flatten)]
+        options: RootOptions,
+    },
+}
+
+impl From<RootEntry> for Root {
+    fn from(entry: RootEntry) -> Self {
+        match entry {
+            RootEntry::Path(path) => Root::new(path),
+            RootEntry::Detailed { path, options } => Root { path, options },
+        }
+    }
+}
+
+impl Root {
+    /// Create a root at the path without options
+    pub(super) fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Root {
+            path: path.into(),
+            options: RootOptions::default(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(super) fn path_mut(&mut self) -> &mut PathBuf {
+        &mut self.path
+    }
+
+    /// Get name of the checker profile used for this root
+    pub fn profile(&self) -> Option<&str> {
+        self.options.profile.as_deref()
+    }
+
+    /// Check if folders below this root are walked into, or only its own entries are read
+    pub fn is_recursive(&self) -> bool {
+        self.options.recursive.unwrap_or(true)
+    }
+
+    /// Get the label shown with the services found under this root, e.g. `System`
+    pub fn label(&self) -> Option<&str> {
+        self.options.label.as_deref()
+    }
+
+    /// Get the priority of the services found under this root, listed before the services
+    /// of lower priority roots if scored the same, or always if the request is empty.
+    /// Defaults to 0
+    pub fn priority(&self) -> i32 {
+        self.options.priority.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod root_test {
+    use std::path::Path;
+
+    use crate::configurator::root::Root;
+
+    #[test]
+    fn test_deserialize_path() {
+        let root: Root = serde_json::from_str(r#""/Applications""#).expect("Invalid root");
+        assert_eq!(root.path(), Path::new("/Applications"));
+        assert_eq!(root.profile(), None);
+        assert!(root.is_recursive());
+        assert_eq!(root.label(), None);
+        assert_eq!(root.priority(), 0);
+    }
+
+    #[test]
+    fn test_deserialize_detailed() {
+        let source = r#"{"path": "/Applications", "profile": "permissive", "recursive": false,
+            "label": "System", "priority": -1}"#;
+        let root: Root = serde_json::from_str(source).expect("Invalid root");
+        assert_eq!(root.path(), Path::new("/Applications"));
+        assert_eq!(root.profile(), Some("permissive"));
+        assert!(!root.is_recursive());
+        assert_eq!(root.label(), Some("System"));
+        assert_eq!(root.priority(), -1);
+    }
+}
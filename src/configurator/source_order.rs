@@ -0,0 +1,13 @@
+use serde::Deserialize;
+
+/// Order services read from the cache and services read fresh are listed in,
+/// kept among services ranked the same
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SourceOrder {
+    /// List services read from the cache first
+    #[default]
+    CachedFirst,
+    /// List services read fresh first, e.g. explicit services and Spotlight results
+    UpdatedFirst,
+}
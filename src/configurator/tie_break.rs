@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+/// Order of services ranked the same
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum TieBreak {
+    /// Keep the order services are found in
+    #[default]
+    None,
+    /// Put the most recently used bundles first
+    LastUsed,
+    /// Put the largest bundles first
+    Size,
+}
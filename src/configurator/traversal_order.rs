@@ -0,0 +1,12 @@
+use serde::Deserialize;
+
+/// Order folders under the roots are walked in, which is the order services are found in
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum TraversalOrder {
+    /// Walk everything in a folder before its next sibling
+    #[default]
+    DepthFirst,
+    /// Walk all folders of the same depth before any deeper one
+    BreadthFirst,
+}
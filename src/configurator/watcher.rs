@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{Scope, ScopedJoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::configurator::Configs;
+
+/// Handle of a background thread reloading the settings file whenever it changes.
+/// The thread stops once the handle is stopped or dropped
+pub struct ConfigWatcher<'s> {
+    stop: Sender<()>,
+    handle: ScopedJoinHandle<'s, ()>,
+}
+
+impl<'s> ConfigWatcher<'s> {
+    /// Check the modified time of the settings file every `interval` in a thread of the scope,
+    /// and once it stays the same for an interval after changing, read the file again
+    /// like `Configs::from_file` and pass the configs to `on_change`, e.g. to build a new
+    /// query processor with new checkers. Invalid settings are logged and skipped,
+    /// so the configs passed before are kept
+    pub fn spawn<P, F>(
+        path: P,
+        scope: &'s Scope<'s, '_>,
+        interval: Duration,
+        mut on_change: F,
+    ) -> Self
+    where
+        P: Into<PathBuf>,
+        F: FnMut(Configs) + Send + 's,
+    {
+        let path = path.into();
+        let (stop, stopped) = mpsc::channel();
+        let handle = scope.spawn(move || {
+            let mut seen = modified(&path);
+            let mut changed = false;
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let current = modified(&path);
+                if current != seen {
+                    seen = current;
+                    changed = true;
+                } else if changed {
+                    changed = false;
+                    match Configs::from_file(&path) {
+                        Ok(configs) => on_change(configs.with_env_roots()),
+                        Err(error) => eprintln!("Failed to reload settings: {}", error),
+                    }
+                }
+            }
+        });
+        ConfigWatcher { stop, handle }
+    }
+
+    /// Stop watching, and wait for the ongoing reload to finish
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.handle.join();
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod watcher_test {
+    use std::fs::File;
+    use std::sync::mpsc;
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::json;
+
+    use crate::configurator::watcher::ConfigWatcher;
+    use crate::configurator::{get_content_with, Configs};
+    use crate::query::QueryProcessor;
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_reload_on_change() {
+        let dir = fixture::temp_dir("config_watcher");
+        fixture::create_bundles(&dir, &["apps/Old.app", "apps/Older.app"]);
+        let settings = dir.join("settings.json");
+        let content = |ignored: &[&str]| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir.join("apps")]);
+                value["internal"]["cacheDir"] = json!(null);
+                value["configurable"]["ignorePaths"]["value"] = json!(ignored
+                    .iter()
+                    .map(|name| dir.join("apps").join(name))
+                    .collect::<Vec<_>>());
+            })
+        };
+        let write = |content: String, seconds: u64| {
+            std::fs::write(&settings, content).unwrap();
+            File::options()
+                .write(true)
+                .open(&settings)
+                .and_then(|file| {
+                    file.set_modified(SystemTime::now() + Duration::from_secs(seconds))
+                })
+                .expect("Failed to set modified time");
+        };
+        let titles = |configs: &Configs| {
+            let mut titles = QueryProcessor::with_configs(configs, Vec::new())
+                .query_services("old")
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>();
+            titles.sort();
+            titles
+        };
+        write(content(&[]), 0);
+        let configs = Configs::from_file(&settings).expect("Invalid settings");
+        assert_eq!(titles(&configs), ["Old", "Older"]);
+        let (sender, received) = mpsc::channel();
+        std::thread::scope(|scope| {
+            let watcher =
+                ConfigWatcher::spawn(&settings, scope, Duration::from_millis(10), |configs| {
+                    sender.send(configs).unwrap()
+                });
+            write("{".to_owned(), 1);
+            assert!(received.recv_timeout(Duration::from_millis(200)).is_err());
+            write(content(&["Old.app"]), 2);
+            let configs = received
+                .recv_timeout(Duration::from_secs(5))
+                .expect("Settings are not reloaded");
+            assert_eq!(titles(&configs), ["Older"]);
+            watcher.stop();
+        });
+    }
+}
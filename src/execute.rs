@@ -1,14 +1,38 @@
+use std::ffi::OsStr;
 use std::process::Command;
 
 pub fn execute(id: &str, is_alter: bool) {
+    if let Err(error) = command(id, is_alter).spawn() {
+        println!("{}", error)
+    }
+}
+
+/// Command that opens the service with given id, or reveals it in Finder when `is_alter`
+pub fn command<S: AsRef<OsStr>>(id: S, is_alter: bool) -> Command {
     let mut command = Command::new("open");
-    let res = if is_alter {
-        command.arg("-R").arg(id)
-    } else {
-        command.arg(id)
+    if is_alter {
+        command.arg("-R");
     }
-    .spawn();
-    if let Err(error) = res {
-        println!("{}", error)
+    command.arg(id);
+    command
+}
+
+#[cfg(test)]
+mod execute_test {
+    use crate::execute::command;
+
+    #[test]
+    fn test_command() {
+        let command = command("/System/Applications/Books.app", false);
+        assert_eq!(command.get_program(), "open");
+        let args = command.get_args().collect::<Vec<_>>();
+        assert_eq!(args, vec!["/System/Applications/Books.app"]);
+    }
+
+    #[test]
+    fn test_command_alter() {
+        let command = command("/System/Applications/Books.app", true);
+        let args = command.get_args().collect::<Vec<_>>();
+        assert_eq!(args, vec!["-R", "/System/Applications/Books.app"]);
     }
 }
@@ -0,0 +1,21 @@
+#![cfg_attr(
+    test,
+    allow(
+        clippy::bool_assert_comparison,
+        clippy::redundant_static_lifetimes,
+        non_fmt_panics
+    )
+)]
+
+use lazy_static::lazy_static;
+
+pub mod configurator;
+pub mod execute;
+pub mod query;
+mod utils;
+
+lazy_static! {
+    pub static ref CONFIG: configurator::Configs = configurator::Configs::from_env()
+        .expect("settings is invalid")
+        .with_env_roots();
+}
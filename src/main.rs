@@ -1,16 +1,4 @@
-use lazy_static::lazy_static;
-
-mod configurator;
-mod execute;
-mod query;
-mod utils;
-
-lazy_static! {
-    pub static ref CONFIG: configurator::Configs = configurator::Configs::from(
-        std::env::var("SETTINGS").expect("Failed to get SETTINGS from environment")
-    )
-    .expect("settings is invalid");
-}
+use launch_service::{execute, query};
 
 fn main() {
     let mut args = std::env::args();
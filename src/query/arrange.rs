@@ -0,0 +1,109 @@
+use std::cmp::{Ordering, Reverse};
+use std::time::SystemTime;
+
+/// Key of the configured tie break, deciding between equally scored services
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub enum TieKey {
+    None,
+    /// The most recently used first
+    LastUsed(Reverse<Option<SystemTime>>),
+    /// The largest bundle first
+    Size(Reverse<u64>),
+}
+
+/// Where a service goes when matched services are arranged, ahead of the ones with greater
+/// keys. Services with equal keys are kept in the order their dedup keys are first found
+pub struct ArrangeKey {
+    /// Position among the pinned paths, or the number of them if not pinned
+    pub pin: usize,
+    /// Bonus of how often and how recently the service was selected
+    pub bonus: f64,
+    /// Priority of the innermost root containing the service
+    pub priority: i32,
+    /// Score of the service with the request, none if it is found otherwise, e.g. by its path
+    pub score: Option<u32>,
+    /// If the request is empty, so the root priority leads instead of breaking ties of scores
+    pub listing: bool,
+    pub tie: TieKey,
+    /// If the service is read from the cache while services read fresh go first
+    pub cached: bool,
+}
+
+impl Ord for ArrangeKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scores = Reverse(self.score).cmp(&Reverse(other.score));
+        let roots = Reverse(self.priority).cmp(&Reverse(other.priority));
+        let (leading, following) = match self.listing {
+            true => (roots, scores),
+            false => (scores, roots),
+        };
+        self.pin
+            .cmp(&other.pin)
+            .then_with(|| other.bonus.total_cmp(&self.bonus))
+            .then(leading)
+            .then(following)
+            .then_with(|| self.tie.cmp(&other.tie))
+            .then(self.cached.cmp(&other.cached))
+    }
+}
+
+impl PartialOrd for ArrangeKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ArrangeKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ArrangeKey {}
+
+#[cfg(test)]
+mod arrange_test {
+    use std::cmp::Reverse;
+
+    use crate::query::arrange::{ArrangeKey, TieKey};
+
+    fn key(score: Option<u32>, priority: i32, listing: bool) -> ArrangeKey {
+        ArrangeKey {
+            pin: 0,
+            bonus: 0.0,
+            priority,
+            score,
+            listing,
+            tie: TieKey::None,
+            cached: false,
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        assert!(key(Some(2), 0, false) < key(Some(1), 1, false));
+        assert!(key(Some(1), 1, true) < key(Some(2), 0, true));
+        assert!(key(Some(0), 0, false) < key(None, 0, false));
+        let pinned = ArrangeKey {
+            pin: 0,
+            ..key(None, 0, false)
+        };
+        let selected = ArrangeKey {
+            pin: 1,
+            bonus: 2.0,
+            ..key(Some(1), 0, false)
+        };
+        let scored = ArrangeKey {
+            pin: 1,
+            ..key(Some(9), 0, false)
+        };
+        assert!(pinned < selected);
+        assert!(selected < scored);
+        let tied = |size: u64| ArrangeKey {
+            tie: TieKey::Size(Reverse(size)),
+            ..key(Some(1), 0, false)
+        };
+        assert!(tied(2) < tied(1));
+        assert!(tied(1) == tied(1));
+    }
+}
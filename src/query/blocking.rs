@@ -0,0 +1,89 @@
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use futures::channel::oneshot;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads running blocking reads, e.g. of bundle infos and extended attributes,
+/// so they never block the async executor. The threads stop once the pool is dropped
+/// and the reads queued before are done
+pub struct BlockingPool {
+    jobs: Sender<Job>,
+}
+
+impl BlockingPool {
+    /// Start a pool of `width` threads, at least one
+    pub fn new(width: usize) -> Self {
+        let (jobs, queued) = mpsc::channel::<Job>();
+        let queued = Arc::new(Mutex::new(queued));
+        for _ in 0..width.max(1) {
+            let queued = Arc::clone(&queued);
+            thread::spawn(move || loop {
+                let job = match queued.lock() {
+                    Ok(queued) => queued.recv(),
+                    Err(_) => return,
+                };
+                // A panicking read resolves to none, and the thread runs the next one
+                match job {
+                    Ok(job) => {
+                        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                    }
+                    Err(_) => return,
+                }
+            });
+        }
+        BlockingPool { jobs }
+    }
+
+    /// Run the read on a thread of the pool, and resolve to its result once done.
+    /// Resolves to none if the read panics
+    pub fn run<T, F>(&self, read: F) -> impl Future<Output = Option<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.jobs.send(Box::new(move || {
+            let _ = sender.send(read());
+        }));
+        async { receiver.await.ok() }
+    }
+}
+
+#[cfg(test)]
+mod blocking_test {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use crate::query::blocking::BlockingPool;
+
+    #[test]
+    fn test_run_off_executor() {
+        let pool = BlockingPool::new(1);
+        let (release, released) = mpsc::channel();
+        let read = pool.run(move || released.recv_timeout(Duration::from_secs(5)));
+        let result = futures::executor::block_on(async {
+            release.send(7).unwrap();
+            read.await
+        });
+        assert_eq!(result, Some(Ok(7)));
+    }
+
+    #[test]
+    fn test_run_panicked() {
+        let pool = BlockingPool::new(2);
+        let panicked = pool.run(|| -> usize { panic!("Failed to read") });
+        assert_eq!(futures::executor::block_on(panicked), None);
+        let reads = (0..4).map(|index| pool.run(move || index * 2));
+        let results = futures::executor::block_on(futures::future::join_all(reads));
+        assert_eq!(results, [Some(0), Some(2), Some(4), Some(6)]);
+        let pool = BlockingPool::new(1);
+        let panicked = pool.run(|| -> usize { panic!("Failed to read") });
+        assert_eq!(futures::executor::block_on(panicked), None);
+        assert_eq!(futures::executor::block_on(pool.run(|| 7)), Some(7));
+    }
+}
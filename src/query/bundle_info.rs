@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use plist::{Dictionary, Value};
+
+use crate::utils::stable_hash;
+
+/// Link in iOS apps installed on Mac to the iOS bundle they wrap
+const WRAPPED_BUNDLE: &str = "WrappedBundle";
+
+/// Where the `Info.plist` and the executable of a bundle are
+pub struct Layout {
+    pub info_plist: PathBuf,
+    /// Folder holding the executable
+    pub executables: PathBuf,
+}
+
+impl Layout {
+    /// Get the layout of the bundle. Mac bundles keep both in `Contents`, while iOS apps
+    /// installed on Mac keep both at the top of the bundle behind `WrappedBundle`, and
+    /// iOS bundles at their top. Bundles with neither `Info.plist` are laid out for Mac
+    pub fn of<P: AsRef<Path>>(bundle: P) -> Self {
+        let bundle = bundle.as_ref();
+        let contents = bundle.join("Contents");
+        let flat = match contents.join("Info.plist").is_file() {
+            true => None,
+            false => vec![bundle.join(WRAPPED_BUNDLE), bundle.to_path_buf()]
+                .into_iter()
+                .find(|folder| folder.join("Info.plist").is_file()),
+        };
+        match flat {
+            Some(folder) => Layout {
+                info_plist: folder.join("Info.plist"),
+                executables: folder,
+            },
+            None => Layout {
+                info_plist: contents.join("Info.plist"),
+                executables: contents.join("MacOS"),
+            },
+        }
+    }
+}
+
+/// Properties read from the `Info.plist` of a bundle
+pub struct BundleInfo {
+    properties: Dictionary,
+}
+
+impl BundleInfo {
+    /// Read the `Info.plist` of the bundle in any layout, in either XML or binary format
+    pub fn read<P: AsRef<Path>>(bundle: P) -> Option<Self> {
+        let path = Layout::of(bundle).info_plist;
+        let properties = Value::from_file(path).ok()?.into_dictionary()?;
+        Some(BundleInfo { properties })
+    }
+
+    /// Get string value of the key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).and_then(Value::as_string)
+    }
+
+    pub fn bundle_id(&self) -> Option<&str> {
+        self.get("CFBundleIdentifier")
+    }
+
+    /// Get the name shown to users, which may be localized, e.g. `Calculatrice`
+    pub fn display_name(&self) -> Option<&str> {
+        self.get("CFBundleDisplayName")
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.get("CFBundleShortVersionString")
+    }
+
+    pub fn executable(&self) -> Option<&str> {
+        self.get("CFBundleExecutable")
+    }
+
+    pub fn category(&self) -> Option<&str> {
+        self.get("LSApplicationCategoryType")
+    }
+
+    pub fn minimum_system_version(&self) -> Option<&str> {
+        self.get("LSMinimumSystemVersion")
+    }
+}
+
+/// Get a cheap signature of the bundle changing whenever its `Info.plist` is modified or
+/// its executable changes size, none if it has no `Info.plist`
+pub fn signature<P: AsRef<Path>>(bundle: P, executable: Option<&str>) -> Option<u64> {
+    let layout = Layout::of(bundle);
+    let modified = layout.info_plist.metadata().ok()?.modified().ok()?;
+    let modified = modified.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    let size = executable
+        .and_then(|executable| layout.executables.join(executable).metadata().ok())
+        .map_or(0, |metadata| metadata.len());
+    let mut bytes = modified.to_le_bytes().to_vec();
+    bytes.extend_from_slice(&size.to_le_bytes());
+    Some(stable_hash(bytes))
+}
+
+#[cfg(test)]
+mod bundle_info_test {
+    use std::fs::{self, File};
+    use std::time::{Duration, SystemTime};
+
+    use crate::query::bundle_info::{self, BundleInfo, Layout};
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_read() {
+        let dir = fixture::temp_dir("bundle_info_read");
+        let bundle = dir.join("Books.app");
+        fixture::create_info_plist(&bundle, &[("LSMinimumSystemVersion", "10.15")]);
+        let info = BundleInfo::read(&bundle).expect("Failed to read");
+        assert_eq!(info.minimum_system_version(), Some("10.15"));
+        assert_eq!(info.get("CFBundleName"), None);
+    }
+
+    #[test]
+    fn test_read_wrapped() {
+        let dir = fixture::temp_dir("bundle_info_wrapped");
+        let bundle = dir.join("Notes.app");
+        let properties = [("CFBundleIdentifier", "com.example.notes")];
+        fixture::create_wrapped_bundle(&bundle, &properties);
+        let layout = Layout::of(&bundle);
+        assert_eq!(layout.info_plist, bundle.join("WrappedBundle/Info.plist"));
+        assert_eq!(layout.executables, bundle.join("WrappedBundle"));
+        let info = BundleInfo::read(&bundle).expect("Failed to read");
+        assert_eq!(info.bundle_id(), Some("com.example.notes"));
+        let mac = dir.join("Books.app");
+        fixture::create_info_plist(&mac, &properties);
+        assert_eq!(Layout::of(&mac).executables, mac.join("Contents/MacOS"));
+    }
+
+    #[test]
+    fn test_signature() {
+        let dir = fixture::temp_dir("bundle_info_signature");
+        let bundle = dir.join("Books.app");
+        assert_eq!(bundle_info::signature(&bundle, None), None);
+        fixture::create_info_plist(&bundle, &[("CFBundleExecutable", "Books")]);
+        let plist = bundle.join("Contents/Info.plist");
+        File::options()
+            .write(true)
+            .open(&plist)
+            .and_then(|plist| plist.set_modified(SystemTime::now() - Duration::from_secs(60)))
+            .expect("Failed to set modified time");
+        let signature = bundle_info::signature(&bundle, Some("Books"));
+        assert!(signature.is_some());
+        assert_eq!(bundle_info::signature(&bundle, Some("Books")), signature);
+        fs::create_dir_all(bundle.join("Contents/MacOS")).unwrap();
+        fs::write(bundle.join("Contents/MacOS/Books"), "binary").unwrap();
+        assert_ne!(bundle_info::signature(&bundle, Some("Books")), signature);
+    }
+
+    #[test]
+    fn test_read_xml_and_binary() {
+        let dir = fixture::temp_dir("bundle_info_xml_and_binary");
+        let properties = [
+            ("CFBundleDisplayName", "Books"),
+            ("CFBundleIdentifier", "com.apple.iBooksX"),
+        ];
+        fixture::create_info_plist(dir.join("Xml.app"), &properties);
+        fixture::create_binary_info_plist(dir.join("Binary.app"), &properties);
+        for bundle in ["Xml.app", "Binary.app"].iter() {
+            let info = BundleInfo::read(dir.join(bundle)).expect("Failed to read");
+            assert_eq!(info.get("CFBundleDisplayName"), Some("Books"));
+            assert_eq!(info.bundle_id(), Some("com.apple.iBooksX"));
+        }
+        let binary = std::fs::read(dir.join("Binary.app/Contents/Info.plist")).unwrap();
+        assert!(binary.starts_with(b"bplist00"));
+    }
+
+    #[test]
+    fn test_read_missing() {
+        let dir = fixture::temp_dir("bundle_info_missing");
+        fixture::create_bundles(&dir, &["Books.app"]);
+        assert!(BundleInfo::read(dir.join("Books.app")).is_none());
+    }
+}
@@ -0,0 +1,442 @@
+use std::io;
+#[cfg(not(feature = "bincode"))]
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::configurator::Configs;
+use crate::query::cache::store::{CacheStore, FileStore};
+use crate::query::service::Service;
+use crate::query::walk::EmptyFolders;
+#[cfg(feature = "bincode")]
+use crate::utils::serde::{decode_framed, encode_framed};
+#[cfg(not(feature = "bincode"))]
+use crate::utils::serde::{deserialize_from_bytes_with, serialize_to_string};
+use crate::utils::stable_hash;
+
+/// Cache of services discovered under the configured roots
+pub struct CacheManager<S: CacheStore = FileStore> {
+    store: S,
+    empty_store: Option<S>,
+    disabled: AtomicBool,
+    /// If invalid UTF-8 in cached records is replaced rather than failing the read
+    lossy: bool,
+}
+
+impl CacheManager<FileStore> {
+    /// New cache stored in `dir`, named after the configured roots, so that
+    /// different configs never share a cache file
+    pub fn new<P: AsRef<Path>>(dir: P, configs: &Configs) -> Self {
+        let roots = configs
+            .get_roots()
+            .iter()
+            .map(|root| {
+                format!(
+                    "{}:{}",
+                    root.path().to_string_lossy(),
+                    root.profile().unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let hash = stable_hash(roots);
+        let file_name = format!("services-{:016x}.{}", hash, EXTENSION);
+        let empty_name = format!("empty-{:016x}.json", hash);
+        CacheManager::with_store(FileStore::new(dir.as_ref().join(file_name)))
+            .with_empty_store(FileStore::new(dir.as_ref().join(empty_name)))
+            .with_lossy(configs.is_cache_lossy())
+    }
+
+    pub fn path(&self) -> &Path {
+        self.store.path()
+    }
+
+    /// Read cached services one by one from the memory mapped cache file,
+    /// without loading the whole file first
+    #[cfg(feature = "mmap")]
+    pub fn mapped_read<F: FnMut(Service)>(&self, mut on_service: F) -> io::Result<()> {
+        let file = std::fs::File::open(self.path())?;
+        // Safety: the cache file is only replaced as a whole or appended to,
+        // so mapped bytes never change, and the mapping is dropped before this function returns
+        let mapped = unsafe { memmap2::Mmap::map(&file)? };
+        for service in records(&mapped, self.lossy) {
+            on_service(service?);
+        }
+        Ok(())
+    }
+}
+
+impl<S: CacheStore> CacheManager<S> {
+    /// New cache saved to the store
+    pub fn with_store(store: S) -> Self {
+        CacheManager {
+            store,
+            empty_store: None,
+            disabled: AtomicBool::new(false),
+            lossy: false,
+        }
+    }
+
+    /// Replace invalid UTF-8 in cached records when reading them, rather than failing
+    pub fn with_lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Also save folders found without services to the store, so later walks skip them
+    pub fn with_empty_store(mut self, store: S) -> Self {
+        self.empty_store = Some(store);
+        self
+    }
+
+    /// Check if saving failed because the store is not writable, so the cache
+    /// should be bypassed from now on
+    pub fn is_disabled(&self) -> bool {
+        self.disabled.load(Ordering::Relaxed)
+    }
+
+    /// Read all cached services, in the order they were saved
+    pub fn bunch_read(&self) -> io::Result<Vec<Service>> {
+        records(&self.store.read()?, self.lossy).collect()
+    }
+
+    /// Replace cached services with `services`. Records are written in the order
+    /// of the slice, and read back in that same order
+    pub fn bunch_save(&self, services: &[Service]) -> io::Result<()> {
+        let mut content = Vec::new();
+        for service in services {
+            if let Err(error) = write_record(service, &mut content) {
+                eprintln!("Skipped caching {}: {}", service.id().display(), error);
+            }
+        }
+        let written = self.store.write(&content);
+        if let Err(error) = &written {
+            if is_unwritable(error) && !self.disabled.swap(true, Ordering::Relaxed) {
+                eprintln!("Cache is not writable, caching is disabled: {}", error);
+            }
+        }
+        written
+    }
+
+    /// Add a single service to the end of the cache without rewriting the others.
+    /// Fails with `NotFound` if nothing is cached yet
+    pub fn append_one(&self, service: &Service) -> io::Result<()> {
+        let mut record = Vec::new();
+        write_record(service, &mut record)?;
+        self.store.append(&record)
+    }
+
+    /// Read folders the last complete walk found no services under.
+    /// Fails with `NotFound` if none are saved
+    pub fn read_empty_folders(&self) -> io::Result<EmptyFolders> {
+        let store = self
+            .empty_store
+            .as_ref()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(serde_json::from_slice(&store.read()?)?)
+    }
+
+    /// Replace the saved folders found without services, if they are saved at all
+    pub fn save_empty_folders(&self, folders: &EmptyFolders) -> io::Result<()> {
+        match &self.empty_store {
+            Some(store) => store.write(&serde_json::to_vec(folders)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Remove cached services with the id, and return if any was removed
+    pub fn remove_one(&self, id: &Path) -> io::Result<bool> {
+        let services = self.bunch_read()?;
+        let kept = services
+            .iter()
+            .filter(|service| service.id() != id)
+            .cloned()
+            .collect::<Vec<_>>();
+        if kept.len() == services.len() {
+            return Ok(false);
+        }
+        self.bunch_save(&kept)?;
+        Ok(true)
+    }
+}
+
+#[cfg(not(feature = "bincode"))]
+const EXTENSION: &str = "json";
+#[cfg(feature = "bincode")]
+const EXTENSION: &str = "bin";
+
+/// Parse services from the cache content, one JSON object per non empty line.
+/// If `lossy`, invalid UTF-8 is replaced, so a corrupted byte does not drop its service
+#[cfg(not(feature = "bincode"))]
+fn records(bytes: &[u8], lossy: bool) -> impl Iterator<Item = io::Result<Service>> + '_ {
+    bytes
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(move |line| {
+            deserialize_from_bytes_with(line, lossy)
+                .map_err(|error| Error::new(ErrorKind::InvalidData, error))
+        })
+}
+
+#[cfg(not(feature = "bincode"))]
+fn write_record(service: &Service, content: &mut Vec<u8>) -> io::Result<()> {
+    let line =
+        serialize_to_string(service).map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+    content.extend_from_slice(line.as_bytes());
+    content.push(b'\n');
+    Ok(())
+}
+
+/// Parse services from the cache content, one length prefixed bincode record each.
+/// Records are always decoded strictly
+#[cfg(feature = "bincode")]
+fn records(bytes: &[u8], _lossy: bool) -> impl Iterator<Item = io::Result<Service>> + '_ {
+    decode_framed(bytes)
+}
+
+#[cfg(feature = "bincode")]
+fn write_record(service: &Service, content: &mut Vec<u8>) -> io::Result<()> {
+    encode_framed(service, content)
+}
+
+/// Check if the error means the store can never be written, rather than failing this once
+fn is_unwritable(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::PermissionDenied
+            | io::ErrorKind::ReadOnlyFilesystem
+            | io::ErrorKind::NotADirectory
+    )
+}
+
+#[cfg(test)]
+mod cache_test {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use crate::configurator::{get_content_with, Configs};
+    use crate::query::cache::{CacheManager, MemoryStore};
+    use crate::query::service::Service;
+    use crate::query::walk::{EmptyFolders, WalkedFolders};
+    use crate::utils::fixture;
+    use crate::utils::serde::MAX_FRAME_SIZE;
+
+    fn configs_with_paths(paths: serde_json::Value) -> Configs {
+        let content = get_content_with(|value| value["internal"]["paths"] = paths);
+        Configs::from(content).expect("Invalid settings")
+    }
+
+    #[test]
+    fn test_path_differs_by_roots() {
+        let dir = fixture::temp_dir("cache_path_differs");
+        let system = CacheManager::new(&dir, &configs_with_paths(json!(["/System/Applications"])));
+        let user = CacheManager::new(&dir, &configs_with_paths(json!(["/Applications"])));
+        assert_ne!(system.path(), user.path());
+        let again = CacheManager::new(&dir, &configs_with_paths(json!(["/Applications"])));
+        assert_eq!(user.path(), again.path());
+    }
+
+    #[test]
+    fn test_round_trip_isolated() {
+        let dir = fixture::temp_dir("cache_round_trip");
+        let system = CacheManager::new(&dir, &configs_with_paths(json!(["/System/Applications"])));
+        let user = CacheManager::new(&dir, &configs_with_paths(json!(["/Applications"])));
+        let services = vec![Service::new("/System/Applications/Books.app")];
+        system.bunch_save(&services).expect("Failed to save");
+        let read = system.bunch_read().expect("Failed to read");
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].id(), services[0].id());
+        assert!(user.bunch_read().is_err());
+    }
+
+    #[test]
+    fn test_round_trip_ordered() {
+        let cache = CacheManager::with_store(MemoryStore::default());
+        let mut services = ["Safari", "Books", "Xcode", "Calendar", "Notes"]
+            .iter()
+            .map(|name| Service::new(format!("/Applications/{}.app", name)))
+            .collect::<Vec<_>>();
+        services.sort_by(|lhs, rhs| lhs.title.cmp(&rhs.title));
+        cache.bunch_save(&services).expect("Failed to save");
+        let read = cache.bunch_read().expect("Failed to read");
+        let ids = |services: &[Service]| {
+            services
+                .iter()
+                .map(|service| service.id().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&read), ids(&services));
+    }
+
+    #[test]
+    fn test_empty_folders_round_trip() {
+        let dir = fixture::temp_dir("cache_empty_folders");
+        std::fs::create_dir_all(dir.join("empty")).unwrap();
+        let cache = CacheManager::new(dir.join("cache"), &configs_with_paths(json!([dir])));
+        assert!(cache.read_empty_folders().is_err());
+        let walked = WalkedFolders {
+            folders: vec![dir.join("empty")],
+            ..WalkedFolders::default()
+        };
+        let folders = EmptyFolders::from_walk(&walked, 0);
+        cache.save_empty_folders(&folders).expect("Failed to save");
+        assert_eq!(cache.read_empty_folders().expect("Failed to read"), folders);
+        let memory = CacheManager::with_store(MemoryStore::default());
+        memory.save_empty_folders(&folders).expect("Failed to save");
+        assert!(memory.read_empty_folders().is_err());
+    }
+
+    #[test]
+    fn test_oversized_skipped() {
+        let cache = CacheManager::with_store(MemoryStore::default());
+        let long_name = format!("/Applications/{}.app", "a".repeat(MAX_FRAME_SIZE));
+        let services = vec![
+            Service::new("/Applications/Books.app"),
+            Service::new(long_name),
+            Service::new("/Applications/Safari.app"),
+        ];
+        cache.bunch_save(&services).expect("Failed to save");
+        let read = cache.bunch_read().expect("Failed to read");
+        let titles = read
+            .iter()
+            .map(|service| service.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Books", "Safari"]);
+    }
+
+    #[cfg(not(feature = "bincode"))]
+    #[test]
+    fn test_invalid_utf8_replaced() {
+        let dir = fixture::temp_dir("cache_invalid_utf8");
+        let strict = CacheManager::new(&dir, &configs_with_paths(json!(["/Applications"])));
+        let mut content = b"{\"title\":\"Bo\xffks\",".to_vec();
+        content.extend_from_slice(
+            br#""subtitle":"/Applications/Books.app","id":"/Applications/Books.app"}"#,
+        );
+        std::fs::write(strict.path(), content).unwrap();
+        assert!(strict.bunch_read().is_err());
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!(["/Applications"]);
+            value["configurable"]["lossyCache"]["value"] = json!(true);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let lossy = CacheManager::new(&dir, &configs);
+        assert_eq!(lossy.path(), strict.path());
+        let read = lossy.bunch_read().expect("Failed to read");
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].title, "Bo\u{fffd}ks");
+    }
+
+    #[test]
+    fn test_unwritable_disabled() {
+        let dir = fixture::temp_dir("cache_unwritable_disabled");
+        std::fs::write(dir.join("file"), "").unwrap();
+        let cache = CacheManager::new(dir.join("file/cache"), &configs_with_paths(json!([])));
+        assert!(!cache.is_disabled());
+        let services = vec![Service::new("/Applications/Books.app")];
+        assert!(cache.bunch_save(&services).is_err());
+        assert!(cache.is_disabled());
+        let cache = CacheManager::new(&dir, &configs_with_paths(json!([])));
+        cache.bunch_save(&services).expect("Failed to save");
+        assert!(!cache.is_disabled());
+    }
+
+    #[test]
+    fn test_round_trip_memory() {
+        let cache = CacheManager::with_store(MemoryStore::default());
+        assert!(cache.bunch_read().is_err());
+        let services = vec![
+            Service::new("/System/Applications/Books.app"),
+            Service::new("/System/Applications/Safari.app"),
+        ];
+        cache.bunch_save(&services).expect("Failed to save");
+        let read = cache.bunch_read().expect("Failed to read");
+        let ids = read.iter().map(Service::id).collect::<Vec<_>>();
+        assert_eq!(ids, services.iter().map(Service::id).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_append_one() {
+        let cache = CacheManager::with_store(MemoryStore::default());
+        let books = Service::new("/Applications/Books.app");
+        assert!(cache.append_one(&books).is_err());
+        cache.bunch_save(&[books]).expect("Failed to save");
+        cache
+            .append_one(&Service::new("/Applications/Safari.app"))
+            .expect("Failed to append");
+        let read = cache.bunch_read().expect("Failed to read");
+        let titles = read
+            .iter()
+            .map(|service| service.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Books", "Safari"]);
+    }
+
+    #[test]
+    fn test_remove_one() {
+        let dir = fixture::temp_dir("cache_remove_one");
+        let cache = CacheManager::new(&dir, &configs_with_paths(json!(["/Applications"])));
+        let services = vec![
+            Service::new("/Applications/Books.app"),
+            Service::new("/Applications/Notes.app"),
+            Service::new("/Applications/Safari.app"),
+        ];
+        cache.bunch_save(&services).expect("Failed to save");
+        let removed = cache.remove_one(Path::new("/Applications/Notes.app"));
+        assert!(removed.expect("Failed to remove"));
+        let removed = cache.remove_one(Path::new("/Applications/Notes.app"));
+        assert!(!removed.expect("Failed to remove"));
+        let read = cache.bunch_read().expect("Failed to read");
+        let titles = read
+            .iter()
+            .map(|service| service.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Books", "Safari"]);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_round_trip_bincode() {
+        let dir = fixture::temp_dir("cache_round_trip_bincode");
+        let bundle = dir.join("Xcode-14.3.app");
+        fixture::create_info_plist(
+            &bundle,
+            &[
+                ("CFBundleIdentifier", "com.apple.dt.Xcode"),
+                ("CFBundleShortVersionString", "14.3"),
+            ],
+        );
+        let service = Service::new(&bundle).with_parent(bundle.parent());
+        let cache = CacheManager::with_store(MemoryStore::default());
+        cache
+            .bunch_save(std::slice::from_ref(&service))
+            .expect("Failed to save");
+        let read = cache.bunch_read().expect("Failed to read");
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].title, service.title);
+        assert_eq!(read[0].id(), service.id());
+        assert_eq!(read[0].parent(), Some(dir.as_path()));
+        assert_eq!(read[0].bundle_id(), Some("com.apple.dt.Xcode"));
+        assert_eq!(read[0].version(), Some("14.3"));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_read() {
+        let dir = fixture::temp_dir("cache_mapped_read");
+        let cache = CacheManager::new(&dir, &configs_with_paths(json!(["/Applications"])));
+        let services = vec![
+            Service::new("/Applications/Books.app"),
+            Service::new("/Applications/Safari.app"),
+        ];
+        cache.bunch_save(&services).expect("Failed to save");
+        let mut mapped = Vec::new();
+        cache
+            .mapped_read(|service| mapped.push(service))
+            .expect("Failed to read");
+        let read = cache.bunch_read().expect("Failed to read");
+        let mapped_ids = mapped.iter().map(Service::id).collect::<Vec<_>>();
+        assert_eq!(mapped_ids, read.iter().map(Service::id).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,5 @@
+pub use manager::CacheManager;
+pub use store::{CacheStore, FileStore, MemoryStore};
+
+mod manager;
+mod store;
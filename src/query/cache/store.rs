@@ -0,0 +1,146 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Number of files written by this process, so concurrent writes use different temporary files
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+/// Storage of the cache content
+pub trait CacheStore {
+    /// Read the stored content. Fails with `NotFound` if nothing is stored
+    fn read(&self) -> io::Result<Vec<u8>>;
+
+    /// Replace the stored content
+    fn write(&self, bytes: &[u8]) -> io::Result<()>;
+
+    /// Add to the end of the stored content. Fails with `NotFound` if nothing is stored
+    fn append(&self, bytes: &[u8]) -> io::Result<()> {
+        let mut content = self.read()?;
+        content.extend_from_slice(bytes);
+        self.write(&content)
+    }
+}
+
+/// Store the cache in a file
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        FileStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl CacheStore for FileStore {
+    fn read(&self) -> io::Result<Vec<u8>> {
+        fs::read(&self.path)
+    }
+
+    /// Write to a temporary file first and rename it, so readers never see a partial file
+    fn write(&self, bytes: &[u8]) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut temp_path = self.path.clone().into_os_string();
+        let write = WRITES.fetch_add(1, Ordering::Relaxed);
+        temp_path.push(format!(".{}.{}.tmp", std::process::id(), write));
+        fs::write(&temp_path, bytes)?;
+        fs::rename(&temp_path, &self.path)
+    }
+
+    /// Append to the file in place with a single write
+    fn append(&self, bytes: &[u8]) -> io::Result<()> {
+        OpenOptions::new()
+            .append(true)
+            .open(&self.path)?
+            .write_all(bytes)
+    }
+}
+
+/// Store the cache in memory
+#[derive(Default)]
+pub struct MemoryStore {
+    bytes: Mutex<Option<Vec<u8>>>,
+}
+
+impl CacheStore for MemoryStore {
+    fn read(&self) -> io::Result<Vec<u8>> {
+        let bytes = self.bytes.lock().unwrap_or_else(|error| error.into_inner());
+        bytes
+            .clone()
+            .ok_or_else(|| Error::from(ErrorKind::NotFound))
+    }
+
+    fn write(&self, bytes: &[u8]) -> io::Result<()> {
+        *self.bytes.lock().unwrap_or_else(|error| error.into_inner()) = Some(bytes.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod store_test {
+    use std::io::ErrorKind;
+
+    use crate::query::cache::store::{CacheStore, FileStore, MemoryStore};
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_memory_store() {
+        let store = MemoryStore::default();
+        assert_eq!(store.read().unwrap_err().kind(), ErrorKind::NotFound);
+        store.write(b"cached").expect("Failed to write");
+        assert_eq!(store.read().expect("Failed to read"), b"cached");
+    }
+
+    #[test]
+    fn test_append() {
+        let dir = fixture::temp_dir("store_append");
+        let stores: [Box<dyn CacheStore>; 2] = [
+            Box::new(MemoryStore::default()),
+            Box::new(FileStore::new(dir.join("services.json"))),
+        ];
+        for store in stores {
+            assert_eq!(
+                store.append(b"more").unwrap_err().kind(),
+                ErrorKind::NotFound
+            );
+            store.write(b"cached").expect("Failed to write");
+            store.append(b" more").expect("Failed to append");
+            assert_eq!(store.read().expect("Failed to read"), b"cached more");
+        }
+    }
+
+    #[test]
+    fn test_file_store() {
+        let dir = fixture::temp_dir("file_store");
+        let store = FileStore::new(dir.join("cache/services.json"));
+        assert_eq!(store.read().unwrap_err().kind(), ErrorKind::NotFound);
+        store.write(b"cached").expect("Failed to write");
+        assert_eq!(store.read().expect("Failed to read"), b"cached");
+    }
+
+    #[test]
+    fn test_file_store_concurrent() {
+        let dir = fixture::temp_dir("file_store_concurrent");
+        let store = FileStore::new(dir.join("services.json"));
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..20 {
+                        store.write(b"cached").expect("Failed to write");
+                    }
+                });
+            }
+        });
+        assert_eq!(store.read().expect("Failed to read"), b"cached");
+    }
+}
@@ -1,16 +1,61 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::ffi::{OsStr, OsString};
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::configurator::{Profile, QuarantineMode};
+use crate::query::bundle_info::{BundleInfo, Layout};
+use crate::query::registry::{CustomChecker, LeafChecker, BUILTIN_CHECKERS};
+use crate::utils::compare_versions;
+
+/// Extended attribute set on downloaded files until the user approves them
+#[cfg(target_os = "macos")]
+pub const QUARANTINE_ATTRIBUTE: &str = "com.apple.quarantine";
+#[cfg(not(target_os = "macos"))]
+pub const QUARANTINE_ATTRIBUTE: &str = "user.com.apple.quarantine";
+
+/// Number of chained symlinks followed unless configured otherwise
+pub const MAX_SYMLINK_DEPTH: usize = 8;
+
+#[allow(clippy::enum_variant_names)]
 pub enum Outcome {
     UnwantedPath,
     BundlePath,
     NormalPath,
 }
 
+/// What a path is to the checks, without walking into it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Classification {
+    /// A bundle or another path recognized as a service
+    Service,
+    /// Excluded by the hidden check
+    Hidden,
+    /// Excluded by any other check, e.g. ignored paths and extensions or custom checkers
+    Ignored,
+    /// Neither a service nor excluded, e.g. a folder holding services
+    Plain,
+}
+
 pub struct Checker<'a> {
     ignored_paths: &'a HashSet<PathBuf>,
+    ignore_overrides: Vec<&'a Path>,
     bundle_extensions: HashSet<OsString>,
+    system_version: Option<String>,
+    ignored_extensions: HashSet<String>,
+    symlinked_bundles: bool,
+    max_symlink_depth: usize,
+    quarantine_mode: QuarantineMode,
+    executable_required: bool,
+    strict_bundles: bool,
+    internal_folders: HashSet<OsString>,
+    roots: Vec<&'a Path>,
+    enabled: Profile,
+    custom_checkers: Vec<CustomChecker>,
+    filter_checkers: Vec<CustomChecker>,
+    leaf_checkers: Vec<LeafChecker>,
+    terminations: [AtomicUsize; BUILTIN_CHECKERS.len()],
 }
 
 impl<'a> Checker<'a> {
@@ -21,29 +66,241 @@ impl<'a> Checker<'a> {
             .collect();
         Checker {
             ignored_paths,
+            ignore_overrides: Vec::new(),
             bundle_extensions,
+            system_version: None,
+            ignored_extensions: HashSet::new(),
+            symlinked_bundles: false,
+            max_symlink_depth: MAX_SYMLINK_DEPTH,
+            quarantine_mode: QuarantineMode::Shown,
+            executable_required: false,
+            strict_bundles: false,
+            internal_folders: HashSet::new(),
+            roots: Vec::new(),
+            enabled: Profile::default(),
+            custom_checkers: Vec::new(),
+            filter_checkers: Vec::new(),
+            leaf_checkers: Vec::new(),
+            terminations: Default::default(),
         }
     }
 
-    pub fn check<P: AsRef<Path>>(&self, path: P) -> Outcome {
-        if self.is_symlink(path.as_ref())
-            || self.is_hidden(path.as_ref())
-            || self.is_ignored_path(path.as_ref())
+    /// Keep the paths even if they are or are under ignored paths. Ignored folders
+    /// holding any of them are walked into, excluding everything else in them
+    pub fn with_ignore_overrides(mut self, overrides: Vec<&'a Path>) -> Self {
+        self.ignore_overrides = overrides;
+        self
+    }
+
+    /// Exclude bundles requiring a newer system than `system_version`
+    pub fn with_system_version(mut self, system_version: Option<String>) -> Self {
+        self.system_version = system_version;
+        self
+    }
+
+    /// Exclude files with any of the extensions, compared case insensitively
+    pub fn with_ignored_extensions<S: AsRef<str>>(mut self, extensions: &[S]) -> Self {
+        self.ignored_extensions = extensions
+            .iter()
+            .map(|extension| extension.as_ref().trim_start_matches('.').to_lowercase())
+            .collect();
+        self
+    }
+
+    /// Keep symlinks pointing to existing bundles instead of excluding them as symlinks
+    pub fn with_symlinked_bundles(mut self, symlinked_bundles: bool) -> Self {
+        self.symlinked_bundles = symlinked_bundles;
+        self
+    }
+
+    /// Stop following a chain of symlinks after `max_symlink_depth` links, treating it as broken
+    pub fn with_max_symlink_depth(mut self, max_symlink_depth: usize) -> Self {
+        self.max_symlink_depth = max_symlink_depth;
+        self
+    }
+
+    /// Exclude bundles still carrying the quarantine attribute, or all bundles not found
+    /// without it, depending on the mode
+    pub fn with_quarantine_mode(mut self, quarantine_mode: QuarantineMode) -> Self {
+        self.quarantine_mode = quarantine_mode;
+        self
+    }
+
+    /// Exclude bundles without an executable where their layout keeps it, e.g. `Contents/MacOS`
+    pub fn with_executable_required(mut self, executable_required: bool) -> Self {
+        self.executable_required = executable_required;
+        self
+    }
+
+    /// Walk into folders named like bundles without a `Contents` folder or an `Info.plist`
+    /// of an iOS layout, instead of taking them as bundles
+    pub fn with_strict_bundles(mut self, strict_bundles: bool) -> Self {
+        self.strict_bundles = strict_bundles;
+        self
+    }
+
+    /// Exclude paths inside any folder with one of the names, e.g. helpers in `Contents/Frameworks`
+    pub fn with_internal_folders<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.internal_folders = names
+            .iter()
+            .map(|name| OsString::from(name.as_ref()))
+            .collect();
+        self
+    }
+
+    /// Never treat the roots as hidden or as symlinks, so hidden folders and symlinks
+    /// to folders can be configured as roots. Paths below a symlinked root stay under it
+    pub fn with_roots(mut self, roots: Vec<&'a Path>) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// Keep the built in checks only if `names` lists them, disabling them for every profile
+    pub fn with_enabled_checks(mut self, names: &[String]) -> Self {
+        let enabled = |name: &str| names.iter().any(|enabled| enabled == name);
+        self.enabled = Profile {
+            hidden_checked: enabled("hidden"),
+            ignored_checked: enabled("ignore"),
+            symlink_checked: enabled("symlink"),
+        };
+        self
+    }
+
+    /// Also exclude paths any of the checkers returns `true` for
+    pub fn with_custom_checkers(mut self, checkers: Vec<CustomChecker>) -> Self {
+        self.custom_checkers = checkers;
+        self
+    }
+
+    /// Also exclude paths any of the checkers returns `true` for from results,
+    /// while still walking into them, e.g. to find bundles inside a hidden bundle
+    pub fn with_filter_checkers(mut self, checkers: Vec<CustomChecker>) -> Self {
+        self.filter_checkers = checkers;
+        self
+    }
+
+    /// Also treat paths any of the checkers returns `true` for as services.
+    /// Bundle checks, e.g. the quarantine and executable checks, are not run on them
+    pub fn with_leaf_checkers(mut self, checkers: Vec<LeafChecker>) -> Self {
+        self.leaf_checkers = checkers;
+        self
+    }
+
+    /// Check the path with only the checks enabled in the profile, counting the path
+    /// against the built in checker excluding it
+    pub fn check<P: AsRef<Path>>(&self, path: P, profile: &Profile) -> Outcome {
+        self.evaluate(path.as_ref(), profile, true)
+    }
+
+    /// Number of paths each built in checker excluded, skipping checkers excluding none
+    pub fn terminations(&self) -> BTreeMap<String, usize> {
+        BUILTIN_CHECKERS
+            .iter()
+            .zip(&self.terminations)
+            .map(|(name, count)| (name.to_string(), count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+
+    fn evaluate(&self, path: &Path, profile: &Profile, counted: bool) -> Outcome {
+        let terminated = |name: &str| {
+            if let Some(index) = BUILTIN_CHECKERS.iter().position(|builtin| *builtin == name) {
+                if counted {
+                    self.terminations[index].fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            true
+        };
+        let profile = Profile {
+            hidden_checked: profile.hidden_checked && self.enabled.hidden_checked,
+            ignored_checked: profile.ignored_checked && self.enabled.ignored_checked,
+            symlink_checked: profile.symlink_checked && self.enabled.symlink_checked,
+        };
+        if (profile.symlink_checked
+            && self.is_symlink(path)
+            && !self.is_symlinked_bundle(path)
+            && !self.roots.contains(&path)
+            && terminated("symlink"))
+            || (profile.hidden_checked
+                && self.is_hidden(path)
+                && !self.roots.contains(&path)
+                && terminated("hidden"))
+            || (profile.ignored_checked && self.is_ignored_path(path) && terminated("ignore"))
+            || self.is_ignored_extension(path)
+            || self.is_internal_helper(path)
+            || self.custom_checkers.iter().any(|checker| checker(path))
         {
             Outcome::UnwantedPath
-        } else if self.is_bundle(path.as_ref()) {
+        } else if self.is_filtered(path) {
+            Outcome::NormalPath
+        } else if self.is_bundle(path) && (!self.strict_bundles || self.has_contents(path)) {
+            if self.is_incompatible(path)
+                || self.is_unapproved(path)
+                || (self.executable_required && !self.has_executable(path))
+            {
+                Outcome::UnwantedPath
+            } else {
+                Outcome::BundlePath
+            }
+        } else if self.is_leaf(path) {
             Outcome::BundlePath
         } else {
             Outcome::NormalPath
         }
     }
 
+    /// Check the path with only the checks enabled in the profile, and tell hidden paths
+    /// apart from paths excluded by other checks
+    pub fn classify<P: AsRef<Path>>(&self, path: P, profile: &Profile) -> Classification {
+        let path = path.as_ref();
+        match self.evaluate(path, profile, false) {
+            Outcome::BundlePath => Classification::Service,
+            Outcome::NormalPath if self.is_filtered(path) => Classification::Ignored,
+            Outcome::NormalPath => Classification::Plain,
+            Outcome::UnwantedPath
+                if profile.hidden_checked
+                    && self.enabled.hidden_checked
+                    && self.is_hidden(path)
+                    && !self.roots.contains(&path) =>
+            {
+                Classification::Hidden
+            }
+            Outcome::UnwantedPath => Classification::Ignored,
+        }
+    }
+
+    /// Check if bundles have the extension, compared case insensitively
+    pub fn is_bundle_extension(&self, extension: &str) -> bool {
+        self.bundle_extensions
+            .iter()
+            .any(|bundle_extension| bundle_extension.eq_ignore_ascii_case(extension))
+    }
+
+    /// Check if the path has a bundle extension or passes a leaf checker, so a walk may
+    /// find it under some settings
+    pub fn is_bundle_like(&self, path: &Path) -> bool {
+        self.is_bundle(path) || self.is_leaf(path)
+    }
+
     fn is_bundle(&self, path: &Path) -> bool {
         path.extension()
             .map(|ext| self.bundle_extensions.contains(ext))
             .unwrap_or(false)
     }
 
+    fn is_filtered(&self, path: &Path) -> bool {
+        self.filter_checkers.iter().any(|checker| checker(path))
+    }
+
+    /// Get the number of leaf checkers
+    pub fn leaf_checkers(&self) -> usize {
+        self.leaf_checkers.len()
+    }
+
+    fn is_leaf(&self, path: &Path) -> bool {
+        self.leaf_checkers.iter().any(|checker| checker(path))
+    }
+
     /// Checker that checks if a path is hidden by checking its prefix dot
     fn is_hidden(&self, path: &Path) -> bool {
         path.file_stem()
@@ -53,15 +310,128 @@ impl<'a> Checker<'a> {
     }
 
     fn is_symlink(&self, path: &Path) -> bool {
-        let path: &std::path::Path = path.into();
         path.symlink_metadata()
             .map(|metadata| metadata.file_type())
             .map(|file_type| file_type.is_symlink())
             .unwrap_or(false)
     }
 
+    /// Checker that checks if a path is an allowed symlink pointing to an existing bundle
+    fn is_symlinked_bundle(&self, path: &Path) -> bool {
+        self.symlinked_bundles
+            && self.is_bundle(path)
+            && self
+                .resolve_symlinks(path)
+                .map(|target| target.is_dir())
+                .unwrap_or(false)
+    }
+
+    /// Follow the chain of symlinks starting at `path`, none if it loops or exceeds the depth
+    fn resolve_symlinks(&self, path: &Path) -> Option<PathBuf> {
+        let mut visited = HashSet::new();
+        let mut current = path.to_path_buf();
+        while self.is_symlink(&current) {
+            if visited.len() >= self.max_symlink_depth || !visited.insert(current.clone()) {
+                return None;
+            }
+            let target = fs::read_link(&current).ok()?;
+            current = match current.parent() {
+                Some(parent) => parent.join(target),
+                None => target,
+            };
+        }
+        Some(current)
+    }
+
+    /// Checker that checks if a path is ignored. With overrides, ignored folders are
+    /// walked into towards the overrides, so paths under ignored folders are checked too
     fn is_ignored_path(&self, path: &Path) -> bool {
-        self.ignored_paths.contains(path)
+        if self.ignore_overrides.is_empty() {
+            return self.ignored_paths.contains(path);
+        }
+        let overridden = self
+            .ignore_overrides
+            .iter()
+            .any(|allowed| allowed.starts_with(path) || path.starts_with(allowed));
+        !overridden
+            && path
+                .ancestors()
+                .any(|ancestor| self.ignored_paths.contains(ancestor))
+    }
+
+    /// Checker that checks if a path is a file with an ignored extension
+    fn is_ignored_extension(&self, path: &Path) -> bool {
+        !self.ignored_extensions.is_empty()
+            && path
+                .extension()
+                .and_then(OsStr::to_str)
+                .map(|ext| self.ignored_extensions.contains(&ext.to_lowercase()))
+                .unwrap_or(false)
+            && path.is_file()
+    }
+
+    /// Checker that checks if any component of a path below its innermost root
+    /// is an internal folder
+    fn is_internal_helper(&self, path: &Path) -> bool {
+        if self.internal_folders.is_empty() {
+            return false;
+        }
+        let relative = self
+            .roots
+            .iter()
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .min_by_key(|relative| relative.components().count())
+            .unwrap_or(path);
+        relative
+            .components()
+            .any(|component| self.internal_folders.contains(component.as_os_str()))
+    }
+
+    /// Checker that checks if a bundle is excluded by the quarantine mode. Only bundles
+    /// whose attributes are read without the quarantine attribute are approved strictly
+    fn is_unapproved(&self, path: &Path) -> bool {
+        let quarantined = || xattr::get(path, QUARANTINE_ATTRIBUTE).map(|value| value.is_some());
+        match self.quarantine_mode {
+            QuarantineMode::Shown => false,
+            QuarantineMode::Hidden => quarantined().unwrap_or(false),
+            QuarantineMode::OnlyApproved => quarantined().unwrap_or(true),
+        }
+    }
+
+    /// Checker that checks if a bundle has a `Contents` folder, or an `Info.plist`
+    /// where its layout keeps it
+    fn has_contents(&self, path: &Path) -> bool {
+        path.join("Contents").is_dir() || Layout::of(path).info_plist.is_file()
+    }
+
+    /// Checker that checks if a bundle has the executable named by `CFBundleExecutable`,
+    /// or any file in the folder of executables if the key is missing
+    fn has_executable(&self, path: &Path) -> bool {
+        let folder = Layout::of(path).executables;
+        match BundleInfo::read(path).and_then(|info| info.executable().map(String::from)) {
+            Some(executable) => folder.join(executable).is_file(),
+            None => folder
+                .read_dir()
+                .map(|mut entries| {
+                    entries.any(|entry| entry.is_ok_and(|entry| entry.path().is_file()))
+                })
+                .unwrap_or(false),
+        }
+    }
+
+    /// Checker that checks if a bundle requires a newer system through `LSMinimumSystemVersion`.
+    /// Bundles without the key or a readable `Info.plist` are compatible
+    fn is_incompatible(&self, path: &Path) -> bool {
+        let system_version = match &self.system_version {
+            Some(system_version) => system_version,
+            None => return false,
+        };
+        BundleInfo::read(path)
+            .and_then(|info| {
+                info.minimum_system_version()
+                    .map(|version| compare_versions(version, system_version).is_gt())
+            })
+            .unwrap_or(false)
     }
 }
 
@@ -152,6 +522,76 @@ mod symlink_test {
     }
 }
 
+#[cfg(test)]
+mod symlinked_bundle_test {
+    use std::collections::HashSet;
+    use std::os::unix::fs::symlink;
+
+    use crate::configurator::Profile;
+    use crate::query::checker::{Checker, Outcome};
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_symlinked_bundle_kept() {
+        let dir = fixture::temp_dir("symlinked_bundle_kept");
+        fixture::create_bundles(&dir, &["versions/Tool 2.app"]);
+        symlink(dir.join("versions/Tool 2.app"), dir.join("Tool.app")).unwrap();
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths).with_symlinked_bundles(true);
+        let outcome = checker.check(dir.join("Tool.app"), &Profile::default());
+        assert!(matches!(outcome, Outcome::BundlePath));
+    }
+
+    #[test]
+    fn test_symlinked_bundle_disabled() {
+        let dir = fixture::temp_dir("symlinked_bundle_disabled");
+        fixture::create_bundles(&dir, &["versions/Tool 2.app"]);
+        symlink(dir.join("versions/Tool 2.app"), dir.join("Tool.app")).unwrap();
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths);
+        let outcome = checker.check(dir.join("Tool.app"), &Profile::default());
+        assert!(matches!(outcome, Outcome::UnwantedPath));
+    }
+
+    #[test]
+    fn test_symlinked_bundle_broken() {
+        let dir = fixture::temp_dir("symlinked_bundle_broken");
+        symlink(dir.join("Missing.app"), dir.join("Tool.app")).unwrap();
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths).with_symlinked_bundles(true);
+        let outcome = checker.check(dir.join("Tool.app"), &Profile::default());
+        assert!(matches!(outcome, Outcome::UnwantedPath));
+    }
+
+    #[test]
+    fn test_symlinked_bundle_depth() {
+        let dir = fixture::temp_dir("symlinked_bundle_depth");
+        fixture::create_bundles(&dir, &["versions/Tool 2.app"]);
+        symlink(dir.join("versions/Tool 2.app"), dir.join("Tool 1.app")).unwrap();
+        symlink("Tool 1.app", dir.join("Tool 0.app")).unwrap();
+        symlink(dir.join("Tool 0.app"), dir.join("Tool.app")).unwrap();
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths)
+            .with_symlinked_bundles(true)
+            .with_max_symlink_depth(2);
+        let outcome = checker.check(dir.join("Tool 0.app"), &Profile::default());
+        assert!(matches!(outcome, Outcome::BundlePath));
+        let outcome = checker.check(dir.join("Tool.app"), &Profile::default());
+        assert!(matches!(outcome, Outcome::UnwantedPath));
+    }
+
+    #[test]
+    fn test_symlinked_bundle_cycle() {
+        let dir = fixture::temp_dir("symlinked_bundle_cycle");
+        symlink(dir.join("Other.app"), dir.join("Tool.app")).unwrap();
+        symlink(dir.join("Tool.app"), dir.join("Other.app")).unwrap();
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths).with_symlinked_bundles(true);
+        let outcome = checker.check(dir.join("Tool.app"), &Profile::default());
+        assert!(matches!(outcome, Outcome::UnwantedPath));
+    }
+}
+
 #[cfg(test)]
 mod ignore_checker_test {
     use std::collections::HashSet;
@@ -181,4 +621,250 @@ mod ignore_checker_test {
             false
         )
     }
+
+    #[test]
+    fn test_overridden() {
+        let ignored_paths: HashSet<PathBuf> = vec!["/Users/cheng"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        let allowed = Path::new("/Users/cheng/Applications/Books.app");
+        let checker = Checker::new(&ignored_paths).with_ignore_overrides(vec![allowed]);
+        assert!(!checker.is_ignored_path(Path::new("/Users/cheng")));
+        assert!(!checker.is_ignored_path(Path::new("/Users/cheng/Applications")));
+        assert!(!checker.is_ignored_path(allowed));
+        assert!(checker.is_ignored_path(Path::new("/Users/cheng/Applications/Notes.app")));
+        assert!(checker.is_ignored_path(Path::new("/Users/cheng/Documents")));
+        assert!(!checker.is_ignored_path(Path::new("/Applications/Notes.app")));
+    }
+}
+
+#[cfg(test)]
+mod compatibility_checker_test {
+    use std::collections::HashSet;
+
+    use crate::query::checker::Checker;
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_is_incompatible() {
+        let dir = fixture::temp_dir("compatibility_incompatible");
+        let bundle = dir.join("Future.app");
+        fixture::create_info_plist(&bundle, &[("LSMinimumSystemVersion", "99.0")]);
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths).with_system_version(Some("10.15.7".into()));
+        assert!(checker.is_incompatible(&bundle));
+    }
+
+    #[test]
+    fn test_is_compatible_without_key() {
+        let dir = fixture::temp_dir("compatibility_without_key");
+        let bundle = dir.join("Books.app");
+        fixture::create_info_plist(&bundle, &[]);
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths).with_system_version(Some("10.15.7".into()));
+        assert!(!checker.is_incompatible(&bundle));
+    }
+}
+
+#[cfg(test)]
+mod extension_checker_test {
+    use std::collections::HashSet;
+    use std::fs;
+
+    use crate::query::checker::Checker;
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_is_ignored_extension() {
+        let dir = fixture::temp_dir("extension_ignored");
+        fs::write(dir.join("Installer.DMG"), "").unwrap();
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths).with_ignored_extensions(&["dmg", ".log"]);
+        assert!(checker.is_ignored_extension(&dir.join("Installer.DMG")));
+    }
+
+    #[test]
+    fn test_is_not_ignored_extension() {
+        let dir = fixture::temp_dir("extension_not_ignored");
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fixture::create_bundles(&dir, &["Books.app", "Folder.dmg"]);
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths).with_ignored_extensions(&["dmg"]);
+        assert!(!checker.is_ignored_extension(&dir.join("notes.txt")));
+        assert!(!checker.is_ignored_extension(&dir.join("Books.app")));
+        assert!(!checker.is_ignored_extension(&dir.join("Folder.dmg")));
+    }
+}
+
+#[cfg(test)]
+mod quarantine_checker_test {
+    use std::collections::HashSet;
+
+    use crate::configurator::{Profile, QuarantineMode};
+    use crate::query::checker::{Checker, Outcome, QUARANTINE_ATTRIBUTE};
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_is_quarantined() {
+        let dir = fixture::temp_dir("quarantine_quarantined");
+        fixture::create_bundles(&dir, &["Downloaded.app", "Approved.app"]);
+        xattr::set(dir.join("Downloaded.app"), QUARANTINE_ATTRIBUTE, b"0081;").unwrap();
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths).with_quarantine_mode(QuarantineMode::Hidden);
+        let profile = Profile::default();
+        let downloaded = checker.check(dir.join("Downloaded.app"), &profile);
+        assert!(matches!(downloaded, Outcome::UnwantedPath));
+        let approved = checker.check(dir.join("Approved.app"), &profile);
+        assert!(matches!(approved, Outcome::BundlePath));
+    }
+
+    #[test]
+    fn test_quarantined_kept_by_default() {
+        let dir = fixture::temp_dir("quarantine_default");
+        fixture::create_bundles(&dir, &["Downloaded.app"]);
+        xattr::set(dir.join("Downloaded.app"), QUARANTINE_ATTRIBUTE, b"0081;").unwrap();
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths);
+        let downloaded = checker.check(dir.join("Downloaded.app"), &Profile::default());
+        assert!(matches!(downloaded, Outcome::BundlePath));
+    }
+}
+
+#[cfg(test)]
+mod strict_bundle_checker_test {
+    use std::collections::HashSet;
+
+    use crate::configurator::Profile;
+    use crate::query::checker::{Checker, Outcome};
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_strict_bundles() {
+        let dir = fixture::temp_dir("strict_bundle_checker");
+        fixture::create_bundles(&dir, &["Real.app/Contents", "Artifact.app"]);
+        let ignored_paths = HashSet::new();
+        let profile = Profile::default();
+        let checker = Checker::new(&ignored_paths).with_strict_bundles(true);
+        let real = checker.check(dir.join("Real.app"), &profile);
+        assert!(matches!(real, Outcome::BundlePath));
+        let artifact = checker.check(dir.join("Artifact.app"), &profile);
+        assert!(matches!(artifact, Outcome::NormalPath));
+        let checker = Checker::new(&ignored_paths);
+        let artifact = checker.check(dir.join("Artifact.app"), &profile);
+        assert!(matches!(artifact, Outcome::BundlePath));
+    }
+}
+
+#[cfg(test)]
+mod executable_checker_test {
+    use std::collections::HashSet;
+    use std::fs;
+
+    use crate::configurator::Profile;
+    use crate::query::checker::{Checker, Outcome};
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_has_executable() {
+        let dir = fixture::temp_dir("executable_checker");
+        fixture::create_bundles(&dir, &["Stub.app", "Complete.app/Contents/MacOS"]);
+        fixture::create_info_plist(dir.join("Named.app"), &[("CFBundleExecutable", "Named")]);
+        fs::write(dir.join("Complete.app/Contents/MacOS/Complete"), b"").unwrap();
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths).with_executable_required(true);
+        let profile = Profile::default();
+        let stub = checker.check(dir.join("Stub.app"), &profile);
+        assert!(matches!(stub, Outcome::UnwantedPath));
+        let complete = checker.check(dir.join("Complete.app"), &profile);
+        assert!(matches!(complete, Outcome::BundlePath));
+        let named = checker.check(dir.join("Named.app"), &profile);
+        assert!(matches!(named, Outcome::UnwantedPath));
+        fixture::create_bundles(&dir, &["Named.app/Contents/MacOS"]);
+        fs::write(dir.join("Named.app/Contents/MacOS/Named"), b"").unwrap();
+        let named = checker.check(dir.join("Named.app"), &profile);
+        assert!(matches!(named, Outcome::BundlePath));
+    }
+
+    #[test]
+    fn test_has_executable_wrapped() {
+        let dir = fixture::temp_dir("executable_checker_wrapped");
+        let bundle = dir.join("Notes.app");
+        let wrapped = fixture::create_wrapped_bundle(&bundle, &[("CFBundleExecutable", "Notes")]);
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths).with_executable_required(true);
+        let profile = Profile::default();
+        assert!(matches!(
+            checker.check(&bundle, &profile),
+            Outcome::BundlePath
+        ));
+        fs::remove_file(wrapped.join("Notes")).unwrap();
+        assert!(matches!(
+            checker.check(&bundle, &profile),
+            Outcome::UnwantedPath
+        ));
+    }
+
+    #[test]
+    fn test_stub_kept_by_default() {
+        let dir = fixture::temp_dir("executable_default");
+        fixture::create_bundles(&dir, &["Stub.app"]);
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths);
+        let stub = checker.check(dir.join("Stub.app"), &Profile::default());
+        assert!(matches!(stub, Outcome::BundlePath));
+    }
+}
+
+#[cfg(test)]
+mod internal_helper_checker_test {
+    use std::collections::HashSet;
+
+    use crate::configurator::Profile;
+    use crate::query::checker::{Checker, Outcome};
+    use crate::utils::fixture;
+
+    const INTERNAL_FOLDERS: [&str; 4] = ["Contents", "Frameworks", "PlugIns", "XPCServices"];
+
+    #[test]
+    fn test_is_internal_helper() {
+        let dir = fixture::temp_dir("internal_helper_checker");
+        let helper = "Host.app/Contents/Frameworks/Helper.app";
+        fixture::create_bundles(&dir, &[helper, "Notes.app"]);
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths)
+            .with_internal_folders(&INTERNAL_FOLDERS)
+            .with_roots(vec![dir.as_path()]);
+        let profile = Profile::default();
+        let helper = checker.check(dir.join(helper), &profile);
+        assert!(matches!(helper, Outcome::UnwantedPath));
+        let notes = checker.check(dir.join("Notes.app"), &profile);
+        assert!(matches!(notes, Outcome::BundlePath));
+    }
+
+    #[test]
+    fn test_internal_root_kept() {
+        let dir = fixture::temp_dir("internal_helper_root");
+        let root = dir.join("Xcode.app/Contents/Applications");
+        fixture::create_bundles(&root, &["Simulator.app"]);
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths)
+            .with_internal_folders(&INTERNAL_FOLDERS)
+            .with_roots(vec![root.as_path()]);
+        let simulator = checker.check(root.join("Simulator.app"), &Profile::default());
+        assert!(matches!(simulator, Outcome::BundlePath));
+    }
+
+    #[test]
+    fn test_innermost_root_kept() {
+        let dir = fixture::temp_dir("internal_helper_innermost_root");
+        let root = dir.join("Xcode.app/Contents/Applications");
+        fixture::create_bundles(&root, &["Simulator.app"]);
+        let ignored_paths = HashSet::new();
+        let checker = Checker::new(&ignored_paths)
+            .with_internal_folders(&INTERNAL_FOLDERS)
+            .with_roots(vec![dir.as_path(), root.as_path()]);
+        let simulator = checker.check(root.join("Simulator.app"), &Profile::default());
+        assert!(matches!(simulator, Outcome::BundlePath));
+    }
 }
@@ -0,0 +1,24 @@
+use crate::query::service::RankedService;
+
+/// Where the services matched by a query are read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultOrigin {
+    /// Results of the same request made recently
+    RecentResults,
+    /// The existing cache
+    Cache,
+    /// The cache rebuilt by walking the roots, because it is missing or invalid
+    Rebuild,
+    /// A walk of the roots, because caching is disabled
+    Walk,
+    /// Any of the above along with services found otherwise: explicit services,
+    /// the path fallback or Spotlight
+    Mixed,
+}
+
+/// Ranked services of a query, with how they are found
+#[derive(Debug, Clone)]
+pub struct QueryDiagnostics {
+    pub services: Vec<RankedService>,
+    pub served_from: ResultOrigin,
+}
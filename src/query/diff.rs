@@ -0,0 +1,107 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::query::service::Service;
+
+/// Changes between two results of the same query, keyed by `Service::dedup_key`
+#[derive(Debug, Default)]
+pub struct ResultDiff {
+    /// Services only in the new results, in their new order
+    pub added: Vec<Service>,
+    /// Services only in the old results, in their old order
+    pub removed: Vec<Service>,
+    /// Services in both results whose relative order changed, in their new order
+    pub reordered: Vec<Service>,
+}
+
+impl ResultDiff {
+    /// Check if the results are the same
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.reordered.is_empty()
+    }
+}
+
+/// Results of a query delivered in steps, see `QueryProcessor::query_progressive`
+#[derive(Debug)]
+pub enum ProgressiveResult {
+    /// Matched services read from the cache, or walked if there is none
+    Cached(Vec<Service>),
+    /// What a fresh walk changed from the cached results
+    Changed(ResultDiff),
+}
+
+/// Compute what changed from the `old` results to the `new` results.
+/// A service kept in both is reordered if its position among the kept services moved
+pub fn diff_results(old: &[Service], new: &[Service]) -> ResultDiff {
+    let old_keys = old.iter().map(Service::dedup_key).collect::<Vec<_>>();
+    let new_keys = new.iter().map(Service::dedup_key).collect::<Vec<_>>();
+    let old_set = old_keys.iter().collect::<HashSet<_>>();
+    let new_set = new_keys.iter().collect::<HashSet<_>>();
+    let kept_positions = old_keys
+        .iter()
+        .filter(|key| new_set.contains(key))
+        .enumerate()
+        .map(|(position, key)| (key, position))
+        .collect::<HashMap<_, _>>();
+    let mut diff = ResultDiff {
+        removed: old
+            .iter()
+            .zip(&old_keys)
+            .filter(|(_, key)| !new_set.contains(key))
+            .map(|(service, _)| service.clone())
+            .collect(),
+        ..ResultDiff::default()
+    };
+    let mut position = 0;
+    for (service, key) in new.iter().zip(&new_keys) {
+        if !old_set.contains(key) {
+            diff.added.push(service.clone());
+            continue;
+        }
+        if kept_positions.get(key) != Some(&position) {
+            diff.reordered.push(service.clone());
+        }
+        position += 1;
+    }
+    diff
+}
+
+#[cfg(test)]
+mod diff_test {
+    use crate::query::diff::diff_results;
+    use crate::query::service::Service;
+
+    fn services(names: &[&str]) -> Vec<Service> {
+        names
+            .iter()
+            .map(|name| Service::new(format!("/Applications/{}.app", name)))
+            .collect()
+    }
+
+    fn titles(services: &[Service]) -> Vec<&str> {
+        services
+            .iter()
+            .map(|service| service.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_results() {
+        let old = services(&["Books", "Calendar", "Notes", "Safari"]);
+        let new = services(&["Music", "Notes", "Books", "Safari"]);
+        let diff = diff_results(&old, &new);
+        assert_eq!(titles(&diff.added), ["Music"]);
+        assert_eq!(titles(&diff.removed), ["Calendar"]);
+        assert_eq!(titles(&diff.reordered), ["Notes", "Books"]);
+    }
+
+    #[test]
+    fn test_diff_results_unchanged() {
+        let old = services(&["Books", "Calendar"]);
+        let new = services(&["Books", "Music", "Calendar"]);
+        let diff = diff_results(&old, &new);
+        assert_eq!(titles(&diff.added), ["Music"]);
+        assert!(diff.removed.is_empty());
+        assert!(diff.reordered.is_empty());
+        assert!(diff_results(&old, &old).is_empty());
+    }
+}
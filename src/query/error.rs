@@ -0,0 +1,40 @@
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+
+/// Failure of a query, from the configs to reading the cache
+#[derive(Debug)]
+pub enum QueryError {
+    /// The configs cannot be queried with
+    Config(String),
+    /// A configured root cannot be read
+    Io { path: PathBuf, source: io::Error },
+    /// The cache cannot be rebuilt
+    Cache(io::Error),
+    /// The cache exists but cannot be parsed
+    Deserialize(io::Error),
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Config(message) => write!(f, "Invalid configs: {}", message),
+            QueryError::Io { path, source } => {
+                write!(f, "Failed to read {}: {}", path.display(), source)
+            }
+            QueryError::Cache(source) => write!(f, "Failed to rebuild cache: {}", source),
+            QueryError::Deserialize(source) => write!(f, "Cache is invalid: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QueryError::Config(_) => None,
+            QueryError::Io { source, .. }
+            | QueryError::Cache(source)
+            | QueryError::Deserialize(source) => Some(source),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::io::{self, Error, ErrorKind, Write};
+
+use crate::query::service::Service;
+#[cfg(feature = "bincode")]
+use crate::utils::serde::encode_framed;
+
+/// Format of exported services
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// An array of services serialized as they are cached
+    Json,
+    /// A header row, then the title, path, bundle id, version and category of each service
+    Csv,
+    /// Length prefixed bincode records, as they are cached
+    #[cfg(feature = "bincode")]
+    Framed,
+}
+
+/// Write the services to the writer in the format
+pub fn write_services<W: Write>(
+    services: &[Service],
+    format: ExportFormat,
+    mut writer: W,
+) -> io::Result<()> {
+    match format {
+        ExportFormat::Json => serde_json::to_writer_pretty(&mut writer, services)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?,
+        ExportFormat::Csv => {
+            writeln!(writer, "title,path,bundleId,version,category")?;
+            for service in services {
+                let fields = [
+                    service.title.as_str(),
+                    &service.id().to_string_lossy(),
+                    service.bundle_id().unwrap_or_default(),
+                    service.version().unwrap_or_default(),
+                    service.category().unwrap_or_default(),
+                ]
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>();
+                writeln!(writer, "{}", fields.join(","))?;
+            }
+        }
+        #[cfg(feature = "bincode")]
+        ExportFormat::Framed => {
+            let mut buffer = Vec::new();
+            for service in services {
+                encode_framed(service, &mut buffer)?;
+            }
+            writer.write_all(&buffer)?;
+        }
+    }
+    writer.flush()
+}
+
+/// Quote the field if it has commas, quotes or line breaks, doubling the quotes in it
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod export_test {
+    use crate::query::export::{write_services, ExportFormat};
+    use crate::query::service::Service;
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_write_csv() {
+        let dir = fixture::temp_dir("export_write_csv");
+        fixture::create_info_plist(
+            dir.join("Books, Reader.app"),
+            &[("CFBundleIdentifier", "com.apple.iBooksX")],
+        );
+        let services = [Service::new(dir.join("Books, Reader.app"))];
+        let mut output = Vec::new();
+        write_services(&services, ExportFormat::Csv, &mut output).expect("Failed to export");
+        let expected = format!(
+            "title,path,bundleId,version,category\n\"Books, Reader\",\"{}\",com.apple.iBooksX,,\n",
+            dir.join("Books, Reader.app").display()
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
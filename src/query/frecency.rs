@@ -0,0 +1,173 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{self, Error, ErrorKind};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::query::cache::CacheStore;
+use crate::utils::serde::{deserialize_from_bytes, serialize_to_string};
+
+/// Seconds it takes for the bonus of a selection to drop by half
+const HALF_LIFE: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+
+/// How often and how recently a service was selected
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Selection {
+    count: u32,
+    #[serde(rename = "lastSelected")]
+    last_selected: u64,
+}
+
+impl Selection {
+    /// Bonus of the selection at `now`, which is the count decaying with
+    /// the time passed since the last selection
+    pub fn bonus(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_selected) as f64;
+        self.count as f64 * 0.5f64.powf(age / HALF_LIFE)
+    }
+}
+
+/// Selections of services keyed by their dedup keys
+pub struct FrecencyStore {
+    store: Box<dyn CacheStore + Send + Sync>,
+    /// Held while selections are read, updated and written back, so no selection is lost
+    recording: Mutex<()>,
+}
+
+impl FrecencyStore {
+    pub fn new<S: CacheStore + Send + Sync + 'static>(store: S) -> Self {
+        FrecencyStore {
+            store: Box::new(store),
+            recording: Mutex::new(()),
+        }
+    }
+
+    /// Read all selections. Nothing is selected if the store is empty
+    pub fn bunch_read(&self) -> io::Result<HashMap<String, Selection>> {
+        match self.store.read() {
+            Ok(bytes) => deserialize_from_bytes(&bytes)
+                .map_err(|error| Error::new(ErrorKind::InvalidData, error)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Get the keys of all selected services, the most recently selected first
+    pub fn recent(&self) -> io::Result<Vec<String>> {
+        let mut selections = self.bunch_read()?.into_iter().collect::<Vec<_>>();
+        selections.sort_by_key(|(_, selection)| Reverse(selection.last_selected));
+        Ok(selections.into_iter().map(|(key, _)| key).collect())
+    }
+
+    /// Count one more selection of `key` at `now`. Other selections are evicted,
+    /// the least recently selected first, while the selections are too large to save
+    pub fn record(&self, key: String, now: u64) -> io::Result<()> {
+        let _recording = self
+            .recording
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let mut selections = self.bunch_read()?;
+        let selection = selections.entry(key.clone()).or_default();
+        selection.count += 1;
+        selection.last_selected = now;
+        let content = loop {
+            let error = match serialize_to_string(&selections) {
+                Ok(content) => break content,
+                Err(error) => error,
+            };
+            let oldest = selections
+                .iter()
+                .filter(|(selected, _)| **selected != key)
+                .min_by_key(|(_, selection)| selection.last_selected)
+                .map(|(selected, _)| selected.clone());
+            match oldest {
+                Some(oldest) => selections.remove(&oldest),
+                None => return Err(Error::new(ErrorKind::InvalidData, error)),
+            };
+        };
+        self.store.write(content.as_bytes())
+    }
+}
+
+/// Seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod frecency_test {
+    use crate::query::cache::{FileStore, MemoryStore};
+    use crate::query::frecency::{FrecencyStore, HALF_LIFE};
+    use crate::utils::fixture;
+    use crate::utils::serde::MAX_FRAME_SIZE;
+
+    #[test]
+    fn test_record() {
+        let frecency = FrecencyStore::new(MemoryStore::default());
+        assert!(frecency.bunch_read().expect("Failed to read").is_empty());
+        frecency.record("com.apple.Safari".to_owned(), 100).unwrap();
+        frecency.record("com.apple.Safari".to_owned(), 200).unwrap();
+        let selections = frecency.bunch_read().expect("Failed to read");
+        assert_eq!(selections["com.apple.Safari"].bonus(200), 2.0);
+    }
+
+    #[test]
+    fn test_record_concurrent() {
+        let dir = fixture::temp_dir("frecency_record_concurrent");
+        let frecency = FrecencyStore::new(FileStore::new(dir.join("selections.json")));
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for now in 0..10 {
+                        frecency.record("com.apple.Safari".to_owned(), now).unwrap();
+                    }
+                });
+            }
+        });
+        let selections = frecency.bunch_read().expect("Failed to read");
+        assert_eq!(selections["com.apple.Safari"].count, 80);
+    }
+
+    #[test]
+    fn test_recent() {
+        let frecency = FrecencyStore::new(MemoryStore::default());
+        assert!(frecency.recent().expect("Failed to read").is_empty());
+        frecency.record("com.apple.Safari".to_owned(), 100).unwrap();
+        frecency.record("com.apple.Notes".to_owned(), 300).unwrap();
+        frecency.record("com.apple.Books".to_owned(), 200).unwrap();
+        let recent = frecency.recent().expect("Failed to read");
+        assert_eq!(
+            recent,
+            ["com.apple.Notes", "com.apple.Books", "com.apple.Safari"]
+        );
+    }
+
+    #[test]
+    fn test_oldest_evicted() {
+        let frecency = FrecencyStore::new(MemoryStore::default());
+        let key = |index: u64| format!("{}{}", index, "a".repeat(MAX_FRAME_SIZE / 4 + 1));
+        for index in 0..4 {
+            frecency.record(key(index), index).unwrap();
+        }
+        let selections = frecency.bunch_read().expect("Failed to read");
+        assert_eq!(selections.len(), 3);
+        assert!(!selections.contains_key(&key(0)));
+        assert!((1..4).all(|index| selections.contains_key(&key(index))));
+        let huge = "a".repeat(MAX_FRAME_SIZE);
+        assert!(frecency.record(huge, 4).is_err());
+    }
+
+    #[test]
+    fn test_bonus_decays() {
+        let frecency = FrecencyStore::new(MemoryStore::default());
+        frecency.record("com.apple.Safari".to_owned(), 0).unwrap();
+        let selection = frecency.bunch_read().unwrap()["com.apple.Safari"];
+        assert_eq!(selection.bonus(HALF_LIFE as u64), 0.5);
+        assert!(selection.bonus(HALF_LIFE as u64 * 2) < selection.bonus(HALF_LIFE as u64));
+    }
+}
@@ -0,0 +1,23 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Paths in a folder, read lazily
+pub type Entries<'f> = Box<dyn Iterator<Item = io::Result<PathBuf>> + 'f>;
+
+/// Filesystem folders are listed with while walking the roots
+pub trait Fs {
+    fn read_dir(&self, path: &Path) -> io::Result<Entries<'_>>;
+}
+
+/// Filesystem of the standard library
+pub struct StdFs;
+
+impl Fs for StdFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Entries<'_>> {
+        let entries = fs::read_dir(path)?;
+        Ok(Box::new(
+            entries.map(|entry| entry.map(|entry| entry.path())),
+        ))
+    }
+}
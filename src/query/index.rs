@@ -0,0 +1,103 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::query::matcher::{fold_accent, sanitize, tokenize, Pattern};
+
+/// Positions of services grouped by the first characters of the tokens in their
+/// match targets, so a fuzzy query is only matched against services that may match it.
+/// Initials are lowercased and stripped of accents, so `u` and `ü` find each other
+#[derive(Default)]
+pub struct TokenIndex {
+    initials: HashMap<char, BTreeSet<usize>>,
+}
+
+impl TokenIndex {
+    /// Index a match target of the service at the position
+    pub fn insert(&mut self, position: usize, target: &str) {
+        let target = sanitize(target);
+        let lowered = target.to_lowercase();
+        let tokens = tokenize(&target);
+        let initials = std::iter::once(lowered.as_str())
+            .chain(tokens.iter().copied())
+            .filter_map(|token| token.to_lowercase().chars().next())
+            .map(fold_accent);
+        for initial in initials {
+            self.initials.entry(initial).or_default().insert(position);
+        }
+    }
+
+    /// Get positions of the services the pattern may match in order,
+    /// or none if every service may match
+    pub fn candidates(&self, pattern: &Pattern) -> Option<BTreeSet<usize>> {
+        match pattern {
+            Pattern::Fuzzy(query) => {
+                let initial = fold_accent(sanitize(query).to_lowercase().chars().next()?);
+                Some(self.initials.get(&initial).cloned().unwrap_or_default())
+            }
+            Pattern::Wildcard(wildcard) => {
+                let initial = wildcard.first_char()?;
+                Some(self.initials.get(&initial).cloned().unwrap_or_default())
+            }
+            Pattern::Any(patterns) => {
+                patterns
+                    .iter()
+                    .try_fold(BTreeSet::new(), |mut candidates, pattern| {
+                        candidates.extend(self.candidates(pattern)?);
+                        Some(candidates)
+                    })
+            }
+            Pattern::All(patterns) => patterns
+                .iter()
+                .filter_map(|pattern| self.candidates(pattern))
+                .reduce(|left, right| left.intersection(&right).copied().collect()),
+            Pattern::Anchored(pattern) => self.candidates(pattern),
+            // Misspellings may start with any character, and paths with any folder
+            Pattern::Literal(_) | Pattern::Not(_) | Pattern::Typo(..) | Pattern::Path(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod index_test {
+    use crate::query::index::TokenIndex;
+    use crate::query::matcher::Pattern;
+
+    #[test]
+    fn test_candidates() {
+        let mut index = TokenIndex::default();
+        for (position, name) in ["Activity Monitor", "Books", "Audio MIDI Setup"]
+            .iter()
+            .enumerate()
+        {
+            index.insert(position, name);
+        }
+        let candidates = |req: &str| {
+            index
+                .candidates(&Pattern::parse(req))
+                .map(|candidates| candidates.into_iter().collect::<Vec<_>>())
+        };
+        assert_eq!(candidates("am"), Some(vec![0, 2]));
+        assert_eq!(candidates("mon"), Some(vec![0, 2]));
+        assert_eq!(candidates("b"), Some(vec![1]));
+        assert_eq!(candidates("act mon"), Some(vec![0, 2]));
+        assert_eq!(candidates("x"), Some(vec![]));
+        assert_eq!(candidates("b*ks"), Some(vec![1]));
+        assert_eq!(candidates("*ks"), None);
+        assert_eq!(candidates("-books"), None);
+        assert_eq!(candidates(r#""ook""#), None);
+        assert_eq!(candidates(""), None);
+    }
+
+    #[test]
+    fn test_candidates_folded() {
+        let mut index = TokenIndex::default();
+        index.insert(0, "Überwachung");
+        index.insert(1, "Uhr");
+        let candidates = |req: &str| {
+            index
+                .candidates(&Pattern::parse(req))
+                .map(|candidates| candidates.into_iter().collect::<Vec<_>>())
+        };
+        assert_eq!(candidates("u"), Some(vec![0, 1]));
+        assert_eq!(candidates("ü"), Some(vec![0, 1]));
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+
+/// Least recently used cache holding at most `capacity` entries
+pub struct LruCache<K, V> {
+    capacity: usize,
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Get a copy of the value, and mark the entry as most recently used
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let index = self.entries.iter().position(|(entry, _)| entry == key)?;
+        let entry = self.entries.remove(index)?;
+        let value = entry.1.clone();
+        self.entries.push_front(entry);
+        Some(value)
+    }
+
+    /// Insert the value, evicting the least recently used entry if full
+    pub fn put(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.retain(|(entry, _)| entry != &key);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_back();
+        }
+        self.entries.push_front((key, value));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod lru_test {
+    use crate::query::lru::LruCache;
+
+    #[test]
+    fn test_get() {
+        let mut cache = LruCache::new(2);
+        cache.put("a", 1);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.get(&"a");
+        cache.put("c", 3);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some(3));
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let mut cache = LruCache::new(0);
+        cache.put("a", 1);
+        assert_eq!(cache.get(&"a"), None);
+    }
+}
@@ -1,5 +1,51 @@
+use std::borrow::Cow;
 use std::collections::HashSet;
 
+use unicode_normalization::UnicodeNormalization;
+
+/// Strip zero width and control characters, and normalize to NFC.
+/// ASCII text without control characters is returned as is
+pub fn sanitize<'a>(text: &'a str) -> Cow<'a, str> {
+    if text.is_ascii() && !text.chars().any(|character| character.is_ascii_control()) {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(
+            text.chars()
+                .filter(|character| !character.is_control() && !is_zero_width(*character))
+                .nfc()
+                .collect(),
+        )
+    }
+}
+
+/// Replace `-` and `_` with spaces, so words separated by any of them match
+/// queries separating the words by spaces. Byte positions are kept
+pub fn unify_separators(text: Cow<'_, str>) -> Cow<'_, str> {
+    match text.contains(['-', '_']) {
+        true => Cow::Owned(text.replace(['-', '_'], " ")),
+        false => text,
+    }
+}
+
+/// Sanitize the text, unify its separators, lowercase it and strip its accents,
+/// e.g. `Über-Tool` to `uber tool`, the form prefilters check targets in
+pub fn fold(text: &str) -> String {
+    unify_separators(sanitize(text))
+        .to_lowercase()
+        .chars()
+        .map(fold_accent)
+        .collect()
+}
+
+/// Strip accents from the character, e.g. `Ü` to `U`, by taking its canonical base character
+pub fn fold_accent(character: char) -> char {
+    std::iter::once(character).nfd().next().unwrap_or(character)
+}
+
+fn is_zero_width(character: char) -> bool {
+    matches!(character, '\u{200B}'..='\u{200F}' | '\u{2060}' | '\u{FEFF}')
+}
+
 pub fn tokenize_and_clean(name: &str) -> Vec<&str> {
     let stop_words: HashSet<&str> = vec!["and", "&", "And"].into_iter().collect();
     tokenize(name)
@@ -16,7 +62,8 @@ pub fn tokenize(name: &str) -> Vec<&str> {
         .collect()
 }
 
-/// Tokenize camel case to separate components. Supper lower camel and upper camel
+/// Tokenize camel case to separate components. Supper lower camel and upper camel.
+/// Transitions between letters, digits and scripts without cases (e.g. CJK) are boundaries too
 fn tokenize_camel_case<'a>(name: &'a str) -> Vec<&'a str> {
     let mut tokens = Vec::<&'a str>::new();
     let mut start_index: usize = 0;
@@ -25,7 +72,7 @@ fn tokenize_camel_case<'a>(name: &'a str) -> Vec<&'a str> {
     for (index, character) in name.char_indices() {
         let case = Case::from(character);
         let diff = prev_case.diff(&case);
-        if (diff == Diff::Sinking && index - start_index > 1)
+        if (diff == Diff::Sinking && name[start_index..index].chars().nth(1).is_some())
             || (diff == Diff::CharType)
             || (diff == Diff::Rising)
         {
@@ -42,6 +89,7 @@ enum Case {
     None,
     Lower,
     Upper,
+    Uncased,
 }
 
 #[derive(Eq, PartialEq)]
@@ -54,17 +102,22 @@ enum Diff {
 
 impl Case {
     fn from(target: char) -> Self {
-        match (target.is_alphabetic(), target.is_lowercase()) {
-            (false, _) => Case::None,
-            (true, true) => Case::Lower,
-            (true, false) => Case::Upper,
+        match (
+            target.is_alphabetic(),
+            target.is_lowercase(),
+            target.is_uppercase(),
+        ) {
+            (false, _, _) => Case::None,
+            (true, true, _) => Case::Lower,
+            (true, false, true) => Case::Upper,
+            (true, false, false) => Case::Uncased,
         }
     }
 
     fn diff(&self, another: &Case) -> Diff {
         use Case::*;
         match (self, another) {
-            (None, None) | (Lower, Lower) | (Upper, Upper) => Diff::None,
+            (None, None) | (Lower, Lower) | (Upper, Upper) | (Uncased, Uncased) => Diff::None,
             (Lower, Upper) => Diff::Rising,
             (Upper, Lower) => Diff::Sinking,
             _ => Diff::CharType,
@@ -74,7 +127,16 @@ impl Case {
 
 #[cfg(test)]
 mod cleaner_test {
-    use crate::query::matcher::cleaner::{tokenize_and_clean, tokenize_camel_case};
+    use crate::query::matcher::cleaner::{
+        fold, sanitize, tokenize_and_clean, tokenize_camel_case, unify_separators,
+    };
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(fold("Über-Tool"), "uber tool");
+        assert_eq!(fold("Cafe\u{301}_Noir"), "cafe noir");
+        assert_eq!(fold("Books"), "books");
+    }
 
     #[test]
     fn test_tokenize_and_clean_simple() {
@@ -117,4 +179,46 @@ mod cleaner_test {
         let expected = vec!["Go", "2", "Shell"];
         assert_eq!(res, expected);
     }
+
+    #[test]
+    fn test_tokenize_with_trailing_digit() {
+        let res = tokenize_camel_case("Photos3");
+        let expected = vec!["Photos", "3"];
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn test_tokenize_with_script_change() {
+        let res = tokenize_camel_case("微信WeChat");
+        let expected = vec!["微信", "We", "Chat"];
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn test_tokenize_with_accented_capital() {
+        let res = tokenize_camel_case("ÜberWachung");
+        let expected = vec!["Über", "Wachung"];
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn test_unify_separators() {
+        assert_eq!(unify_separators(sanitize("App-Store_2")), "App Store 2");
+        assert_eq!(unify_separators(sanitize("App Store")), "App Store");
+    }
+
+    #[test]
+    fn test_sanitize_ascii() {
+        assert_eq!(sanitize("books"), "books");
+    }
+
+    #[test]
+    fn test_sanitize_invisible() {
+        assert_eq!(sanitize("\u{FEFF}bo\u{200D}oks\n"), "books");
+    }
+
+    #[test]
+    fn test_sanitize_normalize() {
+        assert_eq!(sanitize("Cafe\u{301}"), "Caf\u{E9}");
+    }
 }
@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use super::cleaner::fold;
+
+/// Separator splitting requests into path fragments, e.g. `applications/books`
+const FRAGMENT_SEPARATOR: char = '/';
+
+/// Fragments of a request separated by `/`, matching paths with a component starting
+/// with each fragment in order, the last one being the bundle itself
+#[derive(Debug, PartialEq, Eq)]
+pub struct PathFragments(Vec<String>);
+
+impl PathFragments {
+    /// Parse the request into folded fragments, skipping empty ones.
+    /// Requests without the separator or any fragment are not path requests
+    pub fn parse(req: &str) -> Option<Self> {
+        if !req.contains(FRAGMENT_SEPARATOR) {
+            return None;
+        }
+        let fragments = req
+            .split(FRAGMENT_SEPARATOR)
+            .map(|fragment| fold(fragment.trim()))
+            .filter(|fragment| !fragment.is_empty())
+            .collect::<Vec<_>>();
+        (!fragments.is_empty()).then_some(PathFragments(fragments))
+    }
+
+    /// Check if the path has components starting with the fragments in order,
+    /// with the last fragment matching the last component
+    pub fn matches(&self, path: &Path) -> bool {
+        let components = path
+            .iter()
+            .map(|component| fold(&component.to_string_lossy()))
+            .collect::<Vec<_>>();
+        let (last, folders) = match (self.0.split_last(), components.split_last()) {
+            (Some((last, fragments)), Some((name, folders))) if name.starts_with(last) => {
+                (fragments, folders)
+            }
+            _ => return false,
+        };
+        let mut folders = folders.iter();
+        last.iter()
+            .all(|fragment| folders.any(|folder| folder.starts_with(fragment)))
+    }
+}
+
+#[cfg(test)]
+mod fragments_test {
+    use std::path::Path;
+
+    use crate::query::matcher::fragments::PathFragments;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(PathFragments::parse("books"), None);
+        assert_eq!(PathFragments::parse(" / "), None);
+        assert_eq!(
+            PathFragments::parse("/Applications//Books "),
+            Some(PathFragments(vec!["applications".into(), "books".into()]))
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let matches = |req: &str, path: &str| {
+            PathFragments::parse(req)
+                .expect("Not a path request")
+                .matches(Path::new(path))
+        };
+        let books = "/System/Applications/Books.app";
+        assert!(matches("applications/books", books));
+        assert!(matches("sys/app/bo", books));
+        assert!(matches("/books", books));
+        assert!(!matches("books/applications", books));
+        assert!(!matches("applications/system/books", books));
+        assert!(!matches("applications/boat", books));
+        assert!(!matches(
+            "applications/books",
+            "/Applications/Books/Atlas.app"
+        ));
+    }
+}
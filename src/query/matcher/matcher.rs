@@ -1,7 +1,219 @@
-use crate::query::matcher::cleaner::tokenize_and_clean;
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::query::matcher::cleaner::{fold_accent, sanitize, tokenize_and_clean, unify_separators};
+
+/// Score of a query equal to the target, the maximum so no bonus lets a partial match outrank it
+pub const EXACT_SCORE: u32 = u32::MAX;
+/// Score of a query being the prefix of the target
+pub const PREFIX_SCORE: u32 = 75;
+/// Score of a query being the prefix of a target component
+pub const COMPONENT_SCORE: u32 = 50;
+/// Score of a query made of prefixes of target components
+pub const INITIALS_SCORE: u32 = 25;
+/// Bonus of initials matched with the exact case, e.g. `VS` for `Visual Studio`
+pub const CASE_BONUS: u32 = 5;
+/// Bonus of initials with separators, which match at word boundaries, e.g. `vs-code`
+/// for `Visual Studio Code`
+pub const BOUNDARY_BONUS: u32 = 10;
+/// Bonus of initials in proportion to their longest run of consecutive characters,
+/// so `cod ed` in `Code Editor` outranks the same query split over more components
+pub const RUN_BONUS: u32 = 10;
+/// Score of a query within the edit distance of the target or one of its components,
+/// below every other strategy
+pub const TYPO_SCORE: u32 = 10;
+/// Length in characters below which queries get no typo tolerance, since a few edits
+/// turn short queries into unrelated words
+pub const TYPO_MIN_QUERY_LEN: usize = 4;
+/// Age after which installed bundles get no recency bonus
+pub const RECENCY_WINDOW: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How well a query matches a target, with byte ranges of the matched parts
+/// in the sanitized target
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Score {
+    pub value: u32,
+    pub ranges: Vec<Range<usize>>,
+}
+
+impl Score {
+    /// Score with a single matched range
+    pub fn new(value: u32, range: Range<usize>) -> Self {
+        Score {
+            value,
+            ranges: vec![range],
+        }
+    }
+
+    /// Add up to `weight` in proportion to how much of the target is matched,
+    /// so short targets outrank long ones matched the same way
+    pub fn with_length_bonus(mut self, target_len: usize, weight: u32) -> Self {
+        let matched: usize = self.ranges.iter().map(|range| range.len()).sum();
+        let bonus = (weight as usize * matched.min(target_len)).checked_div(target_len);
+        self.value = self.value.saturating_add(bonus.unwrap_or_default() as u32);
+        self
+    }
+
+    /// Scale the value to `0.0..=1.0` as `value / max_score`, where `max_score` is the highest
+    /// value a match short of exact can get, e.g. from `Pattern::max_score`.
+    /// Exact matches are 1.0, and matches of a request that cannot score at all are 0.0
+    pub fn normalized(&self, max_score: u32) -> f32 {
+        if self.value == EXACT_SCORE {
+            return 1.0;
+        }
+        match max_score {
+            0 => 0.0,
+            max_score => (self.value as f32 / max_score as f32).min(1.0),
+        }
+    }
+
+    /// Add up to `weight` for targets installed `age` ago, decreasing linearly
+    /// to nothing at `RECENCY_WINDOW`
+    pub fn with_recency_bonus(mut self, age: Option<Duration>, weight: u32) -> Self {
+        if let Some(remaining) = age.and_then(|age| RECENCY_WINDOW.checked_sub(age)) {
+            let bonus = weight as f64 * remaining.as_secs_f64() / RECENCY_WINDOW.as_secs_f64();
+            self.value = self.value.saturating_add(bonus as u32);
+        }
+        self
+    }
+}
 
 pub fn match_query(query: &str, target: &str) -> bool {
-    prefix_match(query, target) || initial_match(query, target)
+    match_cleaned(query, &unify_separators(sanitize(target)))
+}
+
+/// Match the query against a target already sanitized with its separators unified
+pub fn match_cleaned(query: &str, cleaned: &str) -> bool {
+    let query = unify_separators(sanitize(query)).to_lowercase();
+    prefix_match(&query, cleaned) || initial_match(&query, cleaned)
+}
+
+/// Score the query against the target with the best strategy matching it,
+/// with a bonus of up to `length_weight` for matching more of the target
+pub fn score_query(query: &str, target: &str, length_weight: u32) -> Option<Score> {
+    let target_len = sanitize(target).len();
+    score_strategy(query, target).map(|score| score.with_length_bonus(target_len, length_weight))
+}
+
+fn score_strategy(query: &str, target: &str) -> Option<Score> {
+    let cased = unify_separators(sanitize(query));
+    let query = cased.to_lowercase();
+    let target = unify_separators(sanitize(target));
+    if query.is_empty() {
+        return Some(Score::default());
+    }
+    let lowered = target.to_lowercase();
+    if lowered == query {
+        return Some(Score::new(EXACT_SCORE, 0..target.len()));
+    }
+    if lowered.starts_with(&query) {
+        return Some(Score::new(PREFIX_SCORE, 0..query.len()));
+    }
+    let components = tokenize_and_clean(&target);
+    let offset = |component: &str| component.as_ptr() as usize - target.as_ptr() as usize;
+    if let Some(component) = components
+        .iter()
+        .find(|component| component.to_lowercase().starts_with(&query))
+    {
+        let start = offset(component);
+        return Some(Score::new(COMPONENT_SCORE, start..start + query.len()));
+    }
+    match_components_prefix(&query, &components[..]).map(|lengths| {
+        let ranges = components
+            .iter()
+            .zip(lengths)
+            .map(|(component, length)| offset(component)..offset(component) + length)
+            .collect::<Vec<_>>();
+        let matched = ranges
+            .iter()
+            .map(|range| &target[range.clone()])
+            .collect::<String>();
+        let value = match matched == cased {
+            true => INITIALS_SCORE + CASE_BONUS,
+            false => INITIALS_SCORE,
+        };
+        let value = match query.trim_matches(' ').contains(' ') {
+            true => value + BOUNDARY_BONUS,
+            false => value,
+        };
+        let value = value + run_bonus(&target, &ranges);
+        Score { value, ranges }
+    })
+}
+
+/// Bonus of up to `RUN_BONUS` for matched ranges, `RUN_BONUS * (longest - 1) / (total - 1)`
+/// with lengths in characters, so ranges of single characters get nothing
+fn run_bonus(target: &str, ranges: &[Range<usize>]) -> u32 {
+    let lengths = ranges
+        .iter()
+        .map(|range| target[range.clone()].chars().count())
+        .collect::<Vec<_>>();
+    let total: usize = lengths.iter().sum();
+    let longest = lengths.iter().copied().max().unwrap_or_default();
+    let bonus =
+        (RUN_BONUS as usize * longest.saturating_sub(1)).checked_div(total.saturating_sub(1));
+    bonus.unwrap_or_default() as u32
+}
+
+/// Score the query if it is within `max_distance` edits of the whole target or one of
+/// its components, compared case insensitively. Queries shorter than
+/// `TYPO_MIN_QUERY_LEN` never match this way
+pub fn score_typo(
+    query: &str,
+    target: &str,
+    max_distance: usize,
+    length_weight: u32,
+) -> Option<Score> {
+    let query = unify_separators(sanitize(query)).to_lowercase();
+    let target = unify_separators(sanitize(target));
+    if max_distance == 0 || query.chars().count() < TYPO_MIN_QUERY_LEN {
+        return None;
+    }
+    let offset = |component: &str| component.as_ptr() as usize - target.as_ptr() as usize;
+    std::iter::once(target.as_ref())
+        .chain(tokenize_and_clean(&target))
+        .find(|candidate| edit_distance(&query, &candidate.to_lowercase(), max_distance).is_some())
+        .map(|candidate| {
+            let start = offset(candidate);
+            Score::new(TYPO_SCORE, start..start + candidate.len())
+                .with_length_bonus(target.len(), length_weight)
+        })
+}
+
+/// Levenshtein distance between the texts in characters, none if it exceeds `max`.
+/// Only cells within `max` of the diagonal are computed, and the computation stops
+/// as soon as a whole row exceeds `max`, so long texts stay cheap
+fn edit_distance(lhs: &str, rhs: &str, max: usize) -> Option<usize> {
+    let lhs = lhs.chars().collect::<Vec<_>>();
+    let rhs = rhs.chars().collect::<Vec<_>>();
+    if lhs.len().abs_diff(rhs.len()) > max {
+        return None;
+    }
+    let beyond = max + 1;
+    let mut previous = (0..=rhs.len())
+        .map(|column| column.min(beyond))
+        .collect::<Vec<_>>();
+    let mut current = vec![beyond; rhs.len() + 1];
+    for (row, lhs_char) in lhs.iter().enumerate() {
+        let first = (row + 1).saturating_sub(max);
+        let last = (row + 1 + max).min(rhs.len());
+        current.iter_mut().for_each(|cell| *cell = beyond);
+        if first == 0 {
+            current[0] = (row + 1).min(beyond);
+        }
+        for column in first.max(1)..=last {
+            let substituted = previous[column - 1] + usize::from(*lhs_char != rhs[column - 1]);
+            let cost = substituted
+                .min(previous[column] + 1)
+                .min(current[column - 1] + 1);
+            current[column] = cost.min(beyond);
+        }
+        if current[first..=last].iter().all(|cell| *cell > max) {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    Some(previous[rhs.len()]).filter(|distance| *distance <= max)
 }
 
 /// Simple match that query is the prefix of target
@@ -17,37 +229,59 @@ fn prefix_match(query: &str, target: &str) -> bool {
 /// Complex match that query contains prefixes of target components
 fn initial_match(query: &str, target: &str) -> bool {
     let components = tokenize_and_clean(target);
-    match_components_prefix(query, &components[..])
+    match_components_prefix(query, &components[..]).is_some()
 }
 
-/// Match query with target components
+/// Match query with target components, and return byte lengths of the matched prefixes.
+/// Characters of the components are compared with their accents stripped, and spaces
+/// in the query are only matched between components
 /// - Example:
 ///     - `am` matches with `*A*ctivity *M*onitor`
 ///     - `actmo` matches with `**Act**ivity **Mo**nitor`
-fn match_components_prefix(query: &str, target: &[&str]) -> bool {
+///     - `uf` matches with `*Ü*berwachung *F*ür`
+///     - `vs code` matches with `*V*isual*S*tudio**Code**`
+fn match_components_prefix(query: &str, target: &[&str]) -> Option<Vec<usize>> {
+    let query = query.trim_start_matches(' ');
     if query.is_empty() {
-        true
+        Some(Vec::new())
     } else if target.is_empty() {
-        false
+        None
     } else {
         let processing = target.first().expect("Get first target component failed");
-        for (index, (query_char, target_char)) in query.chars().zip(processing.chars()).enumerate()
+        for ((index, query_char), (target_index, target_char)) in
+            query.char_indices().zip(processing.char_indices())
         {
-            if query_char.eq_ignore_ascii_case(&target_char) {
-                if match_components_prefix(&query[index + 1..], &target[1..]) {
-                    return true;
+            if fold_accent(query_char).eq_ignore_ascii_case(&fold_accent(target_char)) {
+                let matched = index + query_char.len_utf8();
+                if let Some(mut lengths) = match_components_prefix(&query[matched..], &target[1..])
+                {
+                    lengths.insert(0, target_index + target_char.len_utf8());
+                    return Some(lengths);
                 }
             } else {
                 break;
             }
         }
-        false
+        None
     }
 }
 
 #[cfg(test)]
 mod matcher_test {
-    use crate::query::matcher::matcher::{initial_match, prefix_match};
+    use crate::query::matcher::matcher::{
+        edit_distance, initial_match, match_query, prefix_match, score_query, score_typo, Score,
+        BOUNDARY_BONUS, CASE_BONUS, COMPONENT_SCORE, EXACT_SCORE, INITIALS_SCORE, PREFIX_SCORE,
+        RECENCY_WINDOW, RUN_BONUS, TYPO_SCORE,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_normalized() {
+        assert_eq!(Score::new(EXACT_SCORE, 0..4).normalized(85), 1.0);
+        assert_eq!(Score::new(PREFIX_SCORE, 0..2).normalized(100), 0.75);
+        assert_eq!(Score::new(200, 0..2).normalized(100), 1.0);
+        assert_eq!(Score::default().normalized(0), 0.0);
+    }
 
     #[test]
     fn test_prefix_match() {
@@ -84,4 +318,143 @@ mod matcher_test {
         let res = initial_match("acx", "Activity Monitor.app");
         assert_eq!(res, false);
     }
+
+    #[test]
+    fn test_initial_match_digit() {
+        let res = initial_match("p3", "Photos3");
+        assert!(res)
+    }
+
+    #[test]
+    fn test_initial_match_folded_accents() {
+        assert!(initial_match("uf", "Überwachung Für Netzwerke"));
+        assert!(initial_match("üf", "Uberwachung Fur Netzwerke"));
+        let score = score_query("ufn", "Überwachung Für Netzwerke", 0).expect("Not matched");
+        assert_eq!(score.value, INITIALS_SCORE);
+        assert_eq!(score.ranges, [0..2, 13..14, 18..19]);
+    }
+
+    #[test]
+    fn test_initial_match_script_change() {
+        let res = initial_match("微wc", "微信WeChat");
+        assert!(res);
+        let res = initial_match("微c", "微信WeChat");
+        assert!(!res);
+    }
+
+    #[test]
+    fn test_score_query() {
+        let exact = Score::new(EXACT_SCORE, 0..5);
+        assert_eq!(score_query("books", "Books", 0), Some(exact));
+        let prefix = Score::new(PREFIX_SCORE, 0..3);
+        assert_eq!(score_query("act", "Activity Monitor", 0), Some(prefix));
+        let component = Score::new(COMPONENT_SCORE, 9..12);
+        assert_eq!(score_query("mon", "Activity Monitor", 0), Some(component));
+        let initials = score_query("actmo", "Activity Monitor", 0).expect("Not matched");
+        assert_eq!(initials.value, INITIALS_SCORE + RUN_BONUS / 2);
+        assert_eq!(initials.ranges, [0..3, 9..11]);
+        assert!(score_query("acx", "Activity Monitor", 0).is_none());
+    }
+
+    #[test]
+    fn test_score_runs() {
+        let run = score_query("boo", "Books", 0).expect("Not matched");
+        let scattered = score_query("boo", "Big Outdoor Office", 0).expect("Not matched");
+        assert!(run.value > scattered.value);
+        assert_eq!(scattered.value, INITIALS_SCORE);
+        let long = score_query("coded", "Code Editor", 0).expect("Not matched");
+        let fragmented = score_query("coded", "Cool Orange Dark Editor", 0).expect("Not matched");
+        assert_eq!(long.ranges, [0..3, 5..7]);
+        assert_eq!(long.value, INITIALS_SCORE + RUN_BONUS / 2);
+        assert_eq!(fragmented.value, INITIALS_SCORE + RUN_BONUS / 4);
+        assert!(long.value > fragmented.value);
+    }
+
+    #[test]
+    fn test_score_length() {
+        let short = score_query("note", "Notes", 10).expect("Not matched");
+        let long = score_query("note", "Notes and Reminders Archive", 10).expect("Not matched");
+        assert!(short.value > long.value);
+        assert_eq!(short.value, PREFIX_SCORE + 8);
+        assert_eq!(long.value, PREFIX_SCORE + 1);
+        assert_eq!(short.ranges, long.ranges);
+        let exact = score_query("notes", "Notes", 10).expect("Not matched");
+        assert_eq!(exact.value, EXACT_SCORE);
+    }
+
+    #[test]
+    fn test_score_initials_case() {
+        let exact = score_query("VS", "Visual Studio.app", 0).expect("Not matched");
+        let lower = score_query("vs", "Visual Studio.app", 0).expect("Not matched");
+        assert!(exact.value >= lower.value);
+        assert_eq!(exact.value, INITIALS_SCORE + CASE_BONUS);
+        assert_eq!(lower.value, INITIALS_SCORE);
+        assert_eq!(exact.ranges, lower.ranges);
+        let mixed = score_query("vS", "Visual Studio.app", 0).expect("Not matched");
+        assert_eq!(mixed.value, INITIALS_SCORE);
+    }
+
+    #[test]
+    fn test_score_separated_initials() {
+        let separated = score_query("vs-code", "Visual Studio Code", 0).expect("Not matched");
+        assert_eq!(
+            separated.value,
+            INITIALS_SCORE + BOUNDARY_BONUS + RUN_BONUS * 3 / 5
+        );
+        assert_eq!(separated.ranges, [0..1, 7..8, 14..18]);
+        let scattered = score_query("vscode", "Visual Studio Code", 0).expect("Not matched");
+        assert!(separated.value > scattered.value);
+        let camel = score_query("v-s-code", "VisualStudioCode", 0).expect("Not matched");
+        assert_eq!(camel.ranges, [0..1, 6..7, 12..16]);
+        assert!(score_query("vs-code", "Visual Studio", 0).is_none());
+        assert!(score_query("vis-ual", "Visual Studio Code", 0).is_none());
+        assert!(score_query("vs-code", "Xcode", 0).is_none());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("calender", "calendar", 1), Some(1));
+        assert_eq!(edit_distance("calender", "calendar", 0), None);
+        assert_eq!(edit_distance("kitten", "sitting", 3), Some(3));
+        assert_eq!(edit_distance("kitten", "sitting", 2), None);
+        assert_eq!(edit_distance("notes", "notes", 0), Some(0));
+        assert_eq!(edit_distance("note", "notes and reminders", 2), None);
+    }
+
+    #[test]
+    fn test_score_typo() {
+        let score = score_typo("calender", "Calendar", 1, 0).expect("Not matched");
+        assert_eq!(score, Score::new(TYPO_SCORE, 0..8));
+        assert!(score_typo("calender", "Calendar", 0, 0).is_none());
+        let component = score_typo("moniter", "Activity Monitor", 1, 0).expect("Not matched");
+        assert_eq!(component, Score::new(TYPO_SCORE, 9..16));
+        assert!(score_typo("mal", "Mail", 1, 0).is_none());
+    }
+
+    #[test]
+    fn test_recency_bonus() {
+        let score = || Score::new(PREFIX_SCORE, 0..4);
+        let fresh = score().with_recency_bonus(Some(Duration::ZERO), 20);
+        assert_eq!(fresh.value, PREFIX_SCORE + 20);
+        let half = score().with_recency_bonus(Some(RECENCY_WINDOW / 2), 20);
+        assert_eq!(half.value, PREFIX_SCORE + 10);
+        let old = score().with_recency_bonus(Some(RECENCY_WINDOW * 2), 20);
+        assert_eq!(old.value, PREFIX_SCORE);
+        assert_eq!(score().with_recency_bonus(None, 20).value, PREFIX_SCORE);
+    }
+
+    #[test]
+    fn test_match_query_with_bom() {
+        assert!(match_query("\u{FEFF}book", "Books"));
+    }
+
+    #[test]
+    fn test_match_query_with_zero_width_joiner() {
+        assert!(match_query("bo\u{200D}ok", "Books"));
+    }
+
+    #[test]
+    fn test_match_query_decomposed() {
+        assert!(match_query("cafe\u{301}", "Caf\u{E9} Table"));
+    }
 }
@@ -1,5 +1,16 @@
-pub use cleaner::tokenize;
-pub use matcher::match_query;
+pub use cleaner::{fold_accent, sanitize, tokenize};
+pub use fragments::PathFragments;
+pub use matcher::{Score, EXACT_SCORE};
+pub use pattern::Pattern;
+pub use prefilter::Prefilter;
+pub use target::Target;
+pub use wildcard::Wildcard;
 
 mod cleaner;
+mod fragments;
+#[allow(clippy::module_inception)]
 mod matcher;
+mod pattern;
+mod prefilter;
+mod target;
+mod wildcard;
@@ -0,0 +1,596 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::query::matcher::cleaner::{fold, sanitize, unify_separators};
+use crate::query::matcher::fragments::PathFragments;
+use crate::query::matcher::matcher::{
+    match_cleaned, match_query, score_query, score_typo, Score, COMPONENT_SCORE, EXACT_SCORE,
+    PREFIX_SCORE, TYPO_SCORE,
+};
+use crate::query::matcher::prefilter::Prefilter;
+use crate::query::matcher::target::Target;
+use crate::query::matcher::wildcard::Wildcard;
+
+/// Bonus of terms matching in the same order as they appear in the target
+pub const ORDER_BONUS: u32 = 10;
+
+/// Term separating alternative groups of terms, besides `|`
+pub const OR_KEYWORD: &str = "OR";
+
+/// Groups of terms naming the same apps across OS versions, matched for each other
+pub const BUILTIN_SYNONYMS: &[&[&str]] = &[&["settings", "preferences"]];
+
+/// Pattern parsed from a query request
+pub enum Pattern {
+    /// Match by prefixes and initials of the name, case insensitively
+    Fuzzy(String),
+    /// Match by case insensitive substring, for queries wrapped in quotes
+    Literal(String),
+    /// Match the whole target with `*` and `?` wildcards, for terms having them
+    Wildcard(Wildcard),
+    /// Match if any of the patterns matches
+    Any(Vec<Pattern>),
+    /// Match if all of the patterns match
+    All(Vec<Pattern>),
+    /// Match if the pattern does not match
+    Not(Box<Pattern>),
+    /// Match if the pattern matches, and its first character is the first
+    /// letter or digit of the target
+    Anchored(Box<Pattern>),
+    /// Match within the edit distance of the name or one of its words, for misspelled terms
+    Typo(String, usize),
+    /// Match paths by their components in order, for requests separated by `/`
+    Path(PathFragments),
+}
+
+impl Pattern {
+    /// Parse the request. Whitespace separated terms must all match,
+    /// except terms starting with `-` which must not match. Terms with wildcards
+    /// are compiled to wildcard patterns. Groups of terms separated by `OR` or `|`
+    /// are alternatives, any of which may match
+    pub fn parse(req: &str) -> Self {
+        let req = req.trim();
+        if req.len() >= 2 && req.starts_with('"') && req.ends_with('"') {
+            return Pattern::Literal(req[1..req.len() - 1].to_lowercase());
+        }
+        let mut groups = split_alternatives(req);
+        match groups.len() {
+            0 => Pattern::Fuzzy(String::new()),
+            1 => Pattern::group(&groups.remove(0)),
+            _ => Pattern::Any(groups.iter().map(|group| Pattern::group(group)).collect()),
+        }
+    }
+
+    /// Parse a group of terms which must all match
+    fn group(req: &str) -> Self {
+        let mut patterns = req
+            .split_whitespace()
+            .filter_map(|term| match term.strip_prefix('-') {
+                Some("") => None,
+                Some(excluded) => Some(Pattern::Not(Box::new(Pattern::term(excluded)))),
+                None => Some(Pattern::term(term)),
+            })
+            .collect::<Vec<_>>();
+        match patterns.len() {
+            0 => Pattern::Fuzzy(String::new()),
+            1 => patterns.remove(0),
+            _ => Pattern::All(patterns),
+        }
+    }
+
+    fn term(term: &str) -> Self {
+        match Wildcard::is_wildcard(term) {
+            true => Pattern::Wildcard(Wildcard::parse(term)),
+            false => Pattern::Fuzzy(term.to_owned()),
+        }
+    }
+
+    /// Also match expansions of the query if it is one of the abbreviations
+    pub fn expand(self, abbreviations: &HashMap<String, Vec<String>>) -> Self {
+        let expansions = match &self {
+            Pattern::Fuzzy(query) => abbreviations.get(&query.to_lowercase()),
+            _ => None,
+        };
+        match expansions {
+            Some(expansions) => {
+                let expansions = expansions
+                    .iter()
+                    .map(|expansion| Pattern::Fuzzy(expansion.to_lowercase()));
+                Pattern::Any(std::iter::once(self).chain(expansions).collect())
+            }
+            None => self,
+        }
+    }
+
+    /// Also match synonyms of each term, from the built-in groups and the given groups
+    /// in lowercase
+    pub fn with_synonyms(self, synonyms: &[Vec<String>]) -> Self {
+        match self {
+            Pattern::Fuzzy(query) => {
+                let term = query.to_lowercase();
+                let builtin = BUILTIN_SYNONYMS.iter().map(|group| group.to_vec());
+                let configured = synonyms
+                    .iter()
+                    .map(|group| group.iter().map(String::as_str).collect::<Vec<_>>());
+                let alternatives = builtin
+                    .chain(configured)
+                    .filter(|group| group.contains(&term.as_str()))
+                    .flatten()
+                    .filter(|synonym| *synonym != term)
+                    .map(|synonym| Pattern::Fuzzy(synonym.to_owned()))
+                    .collect::<Vec<_>>();
+                match alternatives.is_empty() {
+                    true => Pattern::Fuzzy(query),
+                    false => Pattern::Any(
+                        std::iter::once(Pattern::Fuzzy(query))
+                            .chain(alternatives)
+                            .collect(),
+                    ),
+                }
+            }
+            Pattern::All(patterns) => Pattern::All(
+                patterns
+                    .into_iter()
+                    .map(|pattern| pattern.with_synonyms(synonyms))
+                    .collect(),
+            ),
+            Pattern::Any(patterns) => Pattern::Any(
+                patterns
+                    .into_iter()
+                    .map(|pattern| pattern.with_synonyms(synonyms))
+                    .collect(),
+            ),
+            Pattern::Not(pattern) => Pattern::Not(Box::new(pattern.with_synonyms(synonyms))),
+            pattern => pattern,
+        }
+    }
+
+    /// Also match misspellings of each fuzzy term within `max_distance` edits,
+    /// scored below any other match of the term. Nothing changes if `max_distance` is 0
+    pub fn with_typos(self, max_distance: usize) -> Self {
+        match self {
+            Pattern::Fuzzy(query) if max_distance > 0 => {
+                let typo = Pattern::Typo(query.clone(), max_distance);
+                Pattern::Any(vec![Pattern::Fuzzy(query), typo])
+            }
+            Pattern::Any(patterns) => Pattern::Any(
+                patterns
+                    .into_iter()
+                    .map(|pattern| pattern.with_typos(max_distance))
+                    .collect(),
+            ),
+            Pattern::All(patterns) => Pattern::All(
+                patterns
+                    .into_iter()
+                    .map(|pattern| pattern.with_typos(max_distance))
+                    .collect(),
+            ),
+            pattern => pattern,
+        }
+    }
+
+    /// Check if the pattern has no terms, so it matches every target equally
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Pattern::Fuzzy(query) => query.trim().is_empty(),
+            Pattern::Anchored(pattern) => pattern.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Check if the pattern matches full paths rather than names
+    pub fn is_path(&self) -> bool {
+        match self {
+            Pattern::Path(_) => true,
+            Pattern::Anchored(pattern) => pattern.is_path(),
+            _ => false,
+        }
+    }
+
+    /// Only match targets starting with the first character of the query
+    pub fn anchored(self) -> Self {
+        Pattern::Anchored(Box::new(self))
+    }
+
+    /// Check if the first character of the query is the first letter or digit of the target,
+    /// compared case insensitively
+    fn leads(&self, target: &str) -> bool {
+        let first = |text: &str| text.chars().find(|character| character.is_alphanumeric());
+        match self {
+            Pattern::Fuzzy(query) | Pattern::Literal(query) | Pattern::Typo(query, _) => {
+                match (first(query), first(target)) {
+                    (Some(query), Some(target)) => query.to_lowercase().eq(target.to_lowercase()),
+                    (query, _) => query.is_none(),
+                }
+            }
+            Pattern::Wildcard(wildcard) => match (wildcard.first_char(), first(target)) {
+                (Some(query), Some(target)) if query.is_alphanumeric() => {
+                    target.to_lowercase().eq(std::iter::once(query))
+                }
+                _ => true,
+            },
+            Pattern::Any(patterns) => patterns.iter().any(|pattern| pattern.leads(target)),
+            Pattern::All(patterns) => patterns
+                .iter()
+                .find(|pattern| !matches!(pattern, Pattern::Not(_)))
+                .is_none_or(|pattern| pattern.leads(target)),
+            Pattern::Not(_) | Pattern::Path(_) => true,
+            Pattern::Anchored(pattern) => pattern.leads(target),
+        }
+    }
+
+    pub fn matches(&self, target: &str) -> bool {
+        match self {
+            Pattern::Fuzzy(query) => match_query(query, target),
+            Pattern::Literal(query) => unify_separators(sanitize(target))
+                .to_lowercase()
+                .contains(unify_separators(sanitize(query)).as_ref()),
+            Pattern::Wildcard(wildcard) => wildcard.matches(target),
+            Pattern::Any(patterns) => patterns.iter().any(|pattern| pattern.matches(target)),
+            Pattern::All(patterns) => patterns.iter().all(|pattern| pattern.matches(target)),
+            Pattern::Not(pattern) => !pattern.matches(target),
+            Pattern::Anchored(pattern) => pattern.leads(target) && pattern.matches(target),
+            Pattern::Typo(query, max_distance) => {
+                score_typo(query, target, *max_distance, 0).is_some()
+            }
+            Pattern::Path(fragments) => fragments.matches(Path::new(target)),
+        }
+    }
+
+    /// Match the prepared target as `matches` does, without cleaning it again
+    pub fn matches_target(&self, target: &Target) -> bool {
+        match self {
+            Pattern::Fuzzy(query) => match_cleaned(query, target.cleaned()),
+            Pattern::Literal(query) => target
+                .lowered()
+                .contains(unify_separators(sanitize(query)).as_ref()),
+            Pattern::Any(patterns) => patterns
+                .iter()
+                .any(|pattern| pattern.matches_target(target)),
+            Pattern::All(patterns) => patterns
+                .iter()
+                .all(|pattern| pattern.matches_target(target)),
+            Pattern::Not(pattern) => !pattern.matches_target(target),
+            Pattern::Anchored(pattern) => {
+                pattern.leads(target.raw()) && pattern.matches_target(target)
+            }
+            _ => self.matches(target.raw()),
+        }
+    }
+
+    /// Check if the fuzzy terms joined by spaces equal the target, compared case insensitively
+    fn is_exact(&self, target: &str) -> bool {
+        let terms = match self {
+            Pattern::All(patterns) => patterns
+                .iter()
+                .map(|pattern| match pattern {
+                    Pattern::Fuzzy(query) => Some(unify_separators(sanitize(query)).to_lowercase()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        };
+        terms.is_some_and(|terms| {
+            terms.join(" ") == unify_separators(sanitize(target)).to_lowercase()
+        })
+    }
+
+    /// Score the target if it matches. Scores of all terms add up, with a bonus
+    /// if the terms match in order, and a bonus of up to `length_weight` per term
+    /// for matching more of the target. A query equal to the target gets the maximum score
+    pub fn score(&self, target: &str, length_weight: u32) -> Option<Score> {
+        match self {
+            Pattern::Fuzzy(query) => score_query(query, target, length_weight),
+            Pattern::Literal(query) => {
+                let target = unify_separators(sanitize(target)).to_lowercase();
+                let query = unify_separators(sanitize(query));
+                let start = target.find(query.as_ref())?;
+                let value = match start {
+                    0 if target.len() == query.len() => EXACT_SCORE,
+                    0 => PREFIX_SCORE,
+                    _ => COMPONENT_SCORE,
+                };
+                let score = Score::new(value, start..start + query.len());
+                Some(score.with_length_bonus(target.len(), length_weight))
+            }
+            Pattern::Wildcard(wildcard) => {
+                let length = sanitize(target).len();
+                wildcard.matches(target).then(|| {
+                    Score::new(COMPONENT_SCORE, 0..length).with_length_bonus(length, length_weight)
+                })
+            }
+            Pattern::Any(patterns) => patterns
+                .iter()
+                .filter_map(|pattern| pattern.score(target, length_weight))
+                .reduce(|best, score| {
+                    if score.value > best.value {
+                        score
+                    } else {
+                        best
+                    }
+                }),
+            Pattern::All(patterns) => {
+                let mut starts = Vec::with_capacity(patterns.len());
+                let mut total =
+                    patterns
+                        .iter()
+                        .try_fold(Score::default(), |mut total, pattern| {
+                            let score = pattern.score(target, length_weight)?;
+                            starts.extend(score.ranges.first().map(|range| range.start));
+                            total.value = total.value.saturating_add(score.value);
+                            total.ranges.extend(score.ranges);
+                            Some(total)
+                        })?;
+                if starts.len() > 1 && starts.windows(2).all(|pair| pair[0] < pair[1]) {
+                    total.value = total.value.saturating_add(ORDER_BONUS);
+                }
+                if self.is_exact(target) {
+                    total.value = EXACT_SCORE;
+                }
+                Some(total)
+            }
+            Pattern::Not(pattern) => match pattern.matches(target) {
+                true => None,
+                false => Some(Score::default()),
+            },
+            Pattern::Anchored(pattern) => match pattern.leads(target) {
+                true => pattern.score(target, length_weight),
+                false => None,
+            },
+            Pattern::Typo(query, max_distance) => {
+                score_typo(query, target, *max_distance, length_weight)
+            }
+            Pattern::Path(fragments) => fragments.matches(Path::new(target)).then(|| {
+                let start = target.rfind('/').map_or(0, |separator| separator + 1);
+                Score::new(COMPONENT_SCORE, start..target.len())
+            }),
+        }
+    }
+
+    /// Build the check targets folded by `fold` have to pass to match: the characters of
+    /// each term in order, ignoring whitespace. Patterns matching otherwise, like
+    /// wildcards, misspellings and exclusions, let every target pass
+    pub fn prefilter(&self) -> Prefilter {
+        match self {
+            Pattern::Fuzzy(query) | Pattern::Literal(query) => Prefilter::Chars(
+                fold(query)
+                    .chars()
+                    .filter(|character| !character.is_whitespace())
+                    .collect(),
+            ),
+            Pattern::Any(patterns) => {
+                Prefilter::Any(patterns.iter().map(Self::prefilter).collect())
+            }
+            Pattern::All(patterns) => {
+                Prefilter::All(patterns.iter().map(Self::prefilter).collect())
+            }
+            Pattern::Anchored(pattern) => pattern.prefilter(),
+            Pattern::Wildcard(_) | Pattern::Not(_) | Pattern::Typo(..) | Pattern::Path(_) => {
+                Prefilter::Always
+            }
+        }
+    }
+
+    /// Highest score a target can get without matching exactly: every term matching
+    /// as a prefix with the full length bonus, plus the order bonus for more than one term
+    pub fn max_score(&self, length_weight: u32) -> u32 {
+        match self {
+            Pattern::Fuzzy(query) | Pattern::Literal(query) if query.trim().is_empty() => 0,
+            Pattern::Fuzzy(_) | Pattern::Literal(_) => PREFIX_SCORE + length_weight,
+            Pattern::Wildcard(_) => COMPONENT_SCORE + length_weight,
+            Pattern::Path(_) => COMPONENT_SCORE,
+            Pattern::Typo(..) => TYPO_SCORE + length_weight,
+            Pattern::Not(_) => 0,
+            Pattern::Anchored(pattern) => pattern.max_score(length_weight),
+            Pattern::Any(patterns) => patterns
+                .iter()
+                .map(|pattern| pattern.max_score(length_weight))
+                .max()
+                .unwrap_or_default(),
+            Pattern::All(patterns) => {
+                let scored = patterns
+                    .iter()
+                    .filter(|pattern| !matches!(pattern, Pattern::Not(_)))
+                    .count();
+                let total = patterns
+                    .iter()
+                    .map(|pattern| pattern.max_score(length_weight))
+                    .fold(0, u32::saturating_add);
+                match scored > 1 {
+                    true => total.saturating_add(ORDER_BONUS),
+                    false => total,
+                }
+            }
+        }
+    }
+}
+
+/// Split the request into groups of terms separated by `OR` terms or `|`,
+/// dropping empty groups
+pub fn split_alternatives(req: &str) -> Vec<String> {
+    req.split('|')
+        .flat_map(|part| {
+            let terms = part.split_whitespace().collect::<Vec<_>>();
+            terms
+                .split(|term| *term == OR_KEYWORD)
+                .map(|group| group.join(" "))
+                .collect::<Vec<_>>()
+        })
+        .filter(|group| !group.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod pattern_test {
+    use std::collections::HashMap;
+
+    use crate::query::matcher::matcher::{Score, COMPONENT_SCORE, PREFIX_SCORE};
+    use crate::query::matcher::pattern::{Pattern, ORDER_BONUS};
+
+    #[test]
+    fn test_max_score() {
+        assert_eq!(Pattern::parse("").max_score(10), 0);
+        assert_eq!(Pattern::parse("books").max_score(10), PREFIX_SCORE + 10);
+        assert_eq!(
+            Pattern::parse("open books -old").max_score(10),
+            2 * (PREFIX_SCORE + 10) + ORDER_BONUS
+        );
+        assert_eq!(
+            Pattern::parse("books OR open books").max_score(0),
+            2 * PREFIX_SCORE + ORDER_BONUS
+        );
+        let pattern = Pattern::parse("open books");
+        let score = pattern.score("Open Books Pro", 10).unwrap();
+        assert!(score.value <= pattern.max_score(10));
+    }
+
+    #[test]
+    fn test_alternatives() {
+        let pattern = Pattern::parse("books OR music");
+        assert!(pattern.matches("Books"));
+        assert!(pattern.matches("Music"));
+        assert!(!pattern.matches("Maps"));
+        let pattern = Pattern::parse("books|mus");
+        assert!(pattern.matches("Music"));
+        let score = Pattern::parse("zzz OR book").score("Books", 0);
+        assert_eq!(score, Pattern::parse("book").score("Books", 0));
+    }
+
+    #[test]
+    fn test_wildcard_terms() {
+        assert!(Pattern::parse("b*ks").matches("Books"));
+        assert!(Pattern::parse("?ooks").matches("Books"));
+        assert!(Pattern::parse("c*").matches("Calculator"));
+        assert!(!Pattern::parse("c* -calc*").matches("Calculator"));
+        assert!(Pattern::parse("c* -calc*").matches("Calendar"));
+        let score = Pattern::parse("b*ks")
+            .score("Books", 0)
+            .expect("Not matched");
+        assert_eq!(score, Score::new(COMPONENT_SCORE, 0..5));
+        assert!(Pattern::parse("b*").anchored().matches("Books"));
+        assert!(!Pattern::parse("o*").anchored().matches("Books"));
+    }
+
+    #[test]
+    fn test_anchored_match() {
+        let pattern = Pattern::parse("b");
+        assert!(pattern.matches("Books"));
+        assert!(pattern.matches("Audio Books"));
+        let anchored = Pattern::parse("b").anchored();
+        assert!(anchored.matches("Books"));
+        assert!(!anchored.matches("Audio Books"));
+        assert!(anchored.score("Audio Books", 0).is_none());
+        let literal = Pattern::parse(r#""book""#);
+        assert!(literal.matches("Notebook"));
+        assert!(!literal.anchored().matches("Notebook"));
+        let terms = Pattern::parse("-audio b").anchored();
+        assert!(terms.matches("Books"));
+        assert!(!terms.matches("Audio Books"));
+    }
+
+    #[test]
+    fn test_literal_match() {
+        let pattern = Pattern::parse(r#""book""#);
+        assert!(pattern.matches("Notebook"));
+    }
+
+    #[test]
+    fn test_literal_unmatched() {
+        let pattern = Pattern::parse(r#""bok""#);
+        assert!(!pattern.matches("Books"));
+    }
+
+    #[test]
+    fn test_literal_bypasses_fuzzy() {
+        assert!(Pattern::parse("bok").matches("Book Keeper"));
+        assert!(!Pattern::parse(r#""bok""#).matches("Book Keeper"));
+    }
+
+    #[test]
+    fn test_lone_quote() {
+        let pattern = Pattern::parse(r#"""#);
+        assert!(!pattern.matches("Books"));
+    }
+
+    #[test]
+    fn test_expand_abbreviation() {
+        let mut abbreviations = HashMap::new();
+        abbreviations.insert("browser".to_owned(), vec!["Google Chrome".to_owned()]);
+        let pattern = Pattern::parse("browser").expand(&abbreviations);
+        assert!(pattern.matches("Google Chrome"));
+        assert!(!pattern.matches("Books"));
+        assert!(!Pattern::parse("browser").matches("Google Chrome"));
+    }
+
+    #[test]
+    fn test_expand_keeps_query() {
+        let mut abbreviations = HashMap::new();
+        abbreviations.insert("book".to_owned(), vec!["Kindle".to_owned()]);
+        let pattern = Pattern::parse("book").expand(&abbreviations);
+        assert!(pattern.matches("Books"));
+        assert!(pattern.matches("Kindle"));
+    }
+
+    #[test]
+    fn test_builtin_synonyms() {
+        let pattern = Pattern::parse("system preferences").with_synonyms(&[]);
+        assert!(pattern.matches("System Settings"));
+        assert!(pattern.matches("System Preferences"));
+        assert!(!Pattern::parse("preferences").matches("System Settings"));
+    }
+
+    #[test]
+    fn test_configured_synonyms() {
+        let synonyms = vec![vec!["editor".to_owned(), "textedit".to_owned()]];
+        let pattern = Pattern::parse("editor").with_synonyms(&synonyms);
+        assert!(pattern.matches("TextEdit"));
+        assert!(!Pattern::parse("-editor")
+            .with_synonyms(&synonyms)
+            .matches("TextEdit"));
+    }
+
+    #[test]
+    fn test_all_terms() {
+        let pattern = Pattern::parse("activity mon");
+        assert!(pattern.matches("Activity Monitor"));
+        assert!(!pattern.matches("Activity Tracker"));
+    }
+
+    #[test]
+    fn test_negative_term() {
+        let pattern = Pattern::parse("editor -photo");
+        assert!(pattern.matches("Text Editor"));
+        assert!(!pattern.matches("Photo Editor"));
+    }
+
+    #[test]
+    fn test_score_terms() {
+        let score = Pattern::parse("activity mon -photo")
+            .score("Activity Monitor", 0)
+            .expect("Not matched");
+        assert_eq!(score.value, 125 + ORDER_BONUS);
+        assert_eq!(score.ranges, [0..8, 9..12]);
+        assert!(Pattern::parse("editor -photo")
+            .score("Photo Editor", 0)
+            .is_none());
+    }
+
+    #[test]
+    fn test_score_in_order() {
+        let in_order = Pattern::parse("activity monitor")
+            .score("Activity Monitor Pro", 0)
+            .expect("Not matched");
+        let reversed = Pattern::parse("monitor activity")
+            .score("Activity Monitor Pro", 0)
+            .expect("Not matched");
+        assert!(in_order.value > reversed.value);
+        assert_eq!(in_order.value, reversed.value + ORDER_BONUS);
+    }
+
+    #[test]
+    fn test_lone_dash() {
+        let pattern = Pattern::parse("editor -");
+        assert!(pattern.matches("Photo Editor"));
+        assert!(Pattern::parse("-").matches("Books"));
+    }
+}
@@ -0,0 +1,51 @@
+/// Cheap check of folded targets a pattern can only match if they pass, built once
+/// per request by `Pattern::prefilter` and run before the matcher
+#[derive(Debug, PartialEq, Eq)]
+pub enum Prefilter {
+    /// Every target passes
+    Always,
+    /// Targets containing the characters in order pass
+    Chars(Vec<char>),
+    Any(Vec<Prefilter>),
+    All(Vec<Prefilter>),
+}
+
+impl Prefilter {
+    /// Check if the target, folded by `fold`, may match the pattern. Passing does not
+    /// mean the pattern matches, but failing means it does not
+    pub fn admits(&self, folded: &str) -> bool {
+        match self {
+            Prefilter::Always => true,
+            Prefilter::Chars(chars) => {
+                let mut target = folded.chars();
+                chars
+                    .iter()
+                    .all(|character| target.any(|target| target == *character))
+            }
+            Prefilter::Any(filters) => filters.iter().any(|filter| filter.admits(folded)),
+            Prefilter::All(filters) => filters.iter().all(|filter| filter.admits(folded)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod prefilter_test {
+    use crate::query::matcher::cleaner::fold;
+    use crate::query::matcher::pattern::Pattern;
+    use crate::query::matcher::prefilter::Prefilter;
+
+    #[test]
+    fn test_admits() {
+        let admits =
+            |req: &str, target: &str| Pattern::parse(req).prefilter().admits(&fold(target));
+        assert!(admits("act mon", "Activity Monitor"));
+        assert!(admits("vs-code", "Visual Studio Code"));
+        assert!(admits("uf", "Überwachung Für Netzwerke"));
+        assert!(admits("üf", "Uberwachung Fur Netzwerke"));
+        assert!(admits("books OR zzz", "Books"));
+        assert!(admits("-books b", "Boat"));
+        assert!(!admits("mon act", "Activity"));
+        assert!(!admits("zzz", "Books"));
+        assert_eq!(Pattern::parse("").prefilter(), Prefilter::Chars(Vec::new()));
+    }
+}
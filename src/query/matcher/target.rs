@@ -0,0 +1,114 @@
+#[cfg(test)]
+use std::cell::Cell;
+
+use crate::query::matcher::cleaner::{fold_accent, sanitize, unify_separators};
+
+/// Match target cleaned once, so it is matched against many requests
+/// without being cleaned again for each of them
+pub struct Target {
+    /// The target as given, for patterns matching it as it is
+    raw: String,
+    /// Sanitized with separators unified, the form fuzzy terms are matched against
+    cleaned: String,
+    /// Cleaned and lowercased, the form quoted terms are found in
+    lowered: String,
+    /// Lowered with accents stripped, the form prefilters check
+    folded: String,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Number of targets prepared on this thread
+    static PREPARED: Cell<usize> = const { Cell::new(0) };
+}
+
+impl Target {
+    pub fn new(target: &str) -> Self {
+        #[cfg(test)]
+        PREPARED.with(|prepared| prepared.set(prepared.get() + 1));
+        let cleaned = unify_separators(sanitize(target)).into_owned();
+        let lowered = cleaned.to_lowercase();
+        let folded = lowered.chars().map(fold_accent).collect();
+        Target {
+            raw: target.to_owned(),
+            cleaned,
+            lowered,
+            folded,
+        }
+    }
+
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn cleaned(&self) -> &str {
+        &self.cleaned
+    }
+
+    pub fn lowered(&self) -> &str {
+        &self.lowered
+    }
+
+    pub fn folded(&self) -> &str {
+        &self.folded
+    }
+}
+
+#[cfg(test)]
+mod target_test {
+    use serde_json::json;
+
+    use crate::configurator::{get_content_with, Configs};
+    use crate::query::matcher::cleaner::fold;
+    use crate::query::matcher::target::{Target, PREPARED};
+    use crate::query::{QueryProcessor, Service};
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_new() {
+        let target = Target::new("Über-Tool");
+        assert_eq!(target.raw(), "Über-Tool");
+        assert_eq!(target.cleaned(), "Über Tool");
+        assert_eq!(target.lowered(), "über tool");
+        assert_eq!(target.folded(), fold("Über-Tool"));
+    }
+
+    #[test]
+    fn test_prepared_once() {
+        let dir = fixture::temp_dir("target_prepared_once");
+        let names = [
+            "Überwachung Für Netzwerke.app",
+            "Café Noir.app",
+            "Cafe Latte.app",
+            "Books.app",
+        ];
+        fixture::create_bundles(&dir, &names);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QueryProcessor::with_configs(&configs, Vec::new());
+        let prepared = || PREPARED.with(|prepared| prepared.get());
+        let before = prepared();
+        let index = processor.prepare();
+        assert_eq!(prepared() - before, names.len());
+        let titles = |services: Vec<Service>| {
+            let mut titles = services
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>();
+            titles.sort();
+            titles
+        };
+        let requests = [
+            "cafe", "café", "uf", "üf", "ufn", "noir", "cn", "b", "\"fe n\"", "zzz",
+        ];
+        for req in requests {
+            let walked = titles(processor.query_services(req));
+            let before = prepared();
+            assert_eq!(titles(index.query(req)), walked, "request {}", req);
+            assert_eq!(prepared(), before, "request {}", req);
+        }
+    }
+}
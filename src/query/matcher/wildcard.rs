@@ -0,0 +1,123 @@
+use crate::query::matcher::cleaner::sanitize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// Any characters, including none
+    Any,
+    /// Exactly one character
+    One,
+    Char(char),
+}
+
+/// Glob style pattern compared case insensitively against the whole target,
+/// where `*` matches any characters, `?` matches one, and `\` escapes the next character
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wildcard {
+    /// The term as written, with its escapes
+    term: String,
+    tokens: Vec<Token>,
+}
+
+impl Wildcard {
+    /// Check if the term has wildcards, escaped or not
+    pub fn is_wildcard(term: &str) -> bool {
+        term.contains(['*', '?'])
+    }
+
+    pub fn parse(term: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut characters = term.chars();
+        while let Some(character) = characters.next() {
+            let character = match character {
+                '*' => {
+                    tokens.push(Token::Any);
+                    continue;
+                }
+                '?' => {
+                    tokens.push(Token::One);
+                    continue;
+                }
+                '\\' => characters.next().unwrap_or('\\'),
+                character => character,
+            };
+            tokens.extend(character.to_lowercase().map(Token::Char));
+        }
+        Wildcard {
+            term: term.to_owned(),
+            tokens,
+        }
+    }
+
+    /// Get the term the wildcard is parsed from
+    pub fn as_str(&self) -> &str {
+        &self.term
+    }
+
+    /// Get the character matched targets must start with, if it is not a wildcard
+    pub fn first_char(&self) -> Option<char> {
+        match self.tokens.first() {
+            Some(Token::Char(character)) => Some(*character),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, target: &str) -> bool {
+        let target = sanitize(target).to_lowercase().chars().collect::<Vec<_>>();
+        let (mut token, mut matched) = (0, 0);
+        // Position of the last `*` and where its match ends, to backtrack to
+        let mut backtrack = None;
+        while matched < target.len() {
+            match self.tokens.get(token) {
+                Some(Token::One) => {
+                    token += 1;
+                    matched += 1;
+                }
+                Some(Token::Char(character)) if *character == target[matched] => {
+                    token += 1;
+                    matched += 1;
+                }
+                Some(Token::Any) => {
+                    backtrack = Some((token, matched));
+                    token += 1;
+                }
+                _ => match backtrack {
+                    Some((star, end)) => {
+                        backtrack = Some((star, end + 1));
+                        token = star + 1;
+                        matched = end + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+        self.tokens[token..]
+            .iter()
+            .all(|token| *token == Token::Any)
+    }
+}
+
+#[cfg(test)]
+mod wildcard_test {
+    use crate::query::matcher::wildcard::Wildcard;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(Wildcard::parse("b*ks").matches("Books"));
+        assert!(Wildcard::parse("?ooks").matches("Books"));
+        assert!(Wildcard::parse("c*").matches("Calculator"));
+        assert!(Wildcard::parse("*o*").matches("Books"));
+        assert!(!Wildcard::parse("b*k").matches("Books"));
+        assert!(!Wildcard::parse("?ooks").matches("ooks"));
+        assert!(!Wildcard::parse("c*").matches("Books"));
+    }
+
+    #[test]
+    fn test_wildcard_escaped() {
+        let escaped = Wildcard::parse(r"c\*");
+        assert!(escaped.matches("C*"));
+        assert!(!escaped.matches("Calculator"));
+        assert!(Wildcard::parse(r"what\?*").matches("What? Now"));
+        assert_eq!(escaped.first_char(), Some('c'));
+        assert_eq!(Wildcard::parse("*c").first_char(), None);
+    }
+}
@@ -0,0 +1,19 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Counters of the work done by a query processor since it was created
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub queries: usize,
+    /// Queries answered from recent results, and reads of cached services
+    pub cache_hits: usize,
+    pub cache_rebuilds: usize,
+    /// Bundles found by walking the roots, whether they match or not
+    pub bundles_discovered: usize,
+    /// Services run through the matcher against requests, to match or to score them
+    pub services_scored: usize,
+    /// Paths excluded by each built in checker, keyed by the checker name, e.g. `hidden`.
+    /// Checkers excluding no path are left out
+    pub checker_terminations: BTreeMap<String, usize>,
+    pub average_latency: Duration,
+}
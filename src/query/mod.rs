@@ -1,12 +1,120 @@
 use std::io::stdout;
 
-pub use query::QueryProcessor;
+use crate::query::matcher::Pattern;
 
+pub use cache::{CacheManager, CacheStore, FileStore, MemoryStore};
+pub use cancellation::CancellationToken;
+pub use checker::{Classification, MAX_SYMLINK_DEPTH};
+pub use diagnostics::{QueryDiagnostics, ResultOrigin};
+pub use diff::{diff_results, ProgressiveResult, ResultDiff};
+pub use error::QueryError;
+pub use export::ExportFormat;
+pub use frecency::{FrecencyStore, Selection};
+pub use fs::{Entries, Fs, StdFs};
+pub use metrics::Metrics;
+pub use normalizer::{Identity, QueryNormalizer};
+pub use options::{QueryOptions, COLLAPSED_KEY};
+pub use parsed::{ParsedQuery, QueryParseError};
+pub use progress::RebuildProgress;
+pub use query::{QueryProcessor, ServiceIndex};
+pub use refresher::Refresher;
+pub use registry::{CheckerMode, CheckerRegistry, LeafChecker};
+pub use self_check::{RootReport, SelfCheckReport};
+pub use service::{RankedService, Service, DEPTH_KEY, LABEL_KEY};
+pub use walk::EmptyFolders;
+
+mod arrange;
+#[cfg(feature = "async")]
+mod blocking;
+mod bundle_info;
+mod cache;
+mod cancellation;
 mod checker;
+mod diagnostics;
+mod diff;
+mod error;
+mod export;
+mod frecency;
+mod fs;
+mod index;
+mod lru;
 mod matcher;
+mod metrics;
+mod normalizer;
+mod options;
+mod parsed;
+mod progress;
+#[allow(clippy::module_inception)]
 mod query;
+mod refresher;
+mod registry;
+mod self_check;
 mod service;
+mod top_k;
+mod walk;
 
 pub fn query(req: &str) {
     QueryProcessor::new(stdout()).query(req);
 }
+
+/// Score the query against the candidate name the way requests are scored internally,
+/// none if it does not match. Higher scores rank higher. A query equal to the candidate
+/// gets the maximum score. Otherwise, a query matching the start of
+/// the candidate outscores one matching the start of a later word, which outscores one
+/// made of initials scattered over the words. The configured bonuses, e.g. for short
+/// names or recently installed bundles, are not added
+pub fn score_query(query: &str, candidate: &str) -> Option<u32> {
+    Pattern::parse(query)
+        .score(candidate, 0)
+        .map(|score| score.value)
+}
+
+#[cfg(test)]
+mod score_test {
+    use crate::query::score_query;
+
+    #[test]
+    fn test_score_order() {
+        let exact = score_query("safari", "Safari").expect("Not matched");
+        let prefix = score_query("saf", "Safari").expect("Not matched");
+        let word = score_query("stu", "Visual Studio").expect("Not matched");
+        let scattered = score_query("vs", "Visual Studio").expect("Not matched");
+        assert!(exact > prefix);
+        assert!(prefix > word);
+        assert!(word > scattered);
+    }
+
+    #[test]
+    fn test_score_exact_name() {
+        let exact = score_query("notes", "Notes").expect("Not matched");
+        let partials = [
+            score_query("notes", "Notes and Reminders"),
+            score_query("notes", "Sticky Notes"),
+            score_query("notes notes", "Notes Notes Pro"),
+        ];
+        for partial in partials {
+            assert!(exact > partial.expect("Not matched"));
+        }
+        let words = score_query("visual studio", "Visual Studio").expect("Not matched");
+        assert_eq!(words, exact);
+        assert!(words > score_query("visual studio", "Visual Studio Code").unwrap());
+    }
+
+    #[test]
+    fn test_score_unmatched() {
+        assert_eq!(score_query("xyz", "Safari"), None);
+        assert_eq!(score_query("-saf", "Safari"), None);
+    }
+
+    #[test]
+    fn test_score_case_insensitive() {
+        assert_eq!(score_query("SAF", "Safari"), score_query("saf", "Safari"));
+    }
+
+    #[test]
+    fn test_score_terms_add_up() {
+        let one = score_query("visual", "Visual Studio Code").expect("Not matched");
+        let two = score_query("visual code", "Visual Studio Code").expect("Not matched");
+        assert!(two > one);
+    }
+}
@@ -0,0 +1,19 @@
+/// Preprocess query requests before they are matched
+pub trait QueryNormalizer {
+    fn normalize(&self, raw: String) -> String;
+}
+
+/// Normalizer keeping the request as is
+pub struct Identity;
+
+impl QueryNormalizer for Identity {
+    fn normalize(&self, raw: String) -> String {
+        raw
+    }
+}
+
+impl<F: Fn(String) -> String> QueryNormalizer for F {
+    fn normalize(&self, raw: String) -> String {
+        self(raw)
+    }
+}
@@ -0,0 +1,117 @@
+use crate::configurator::Profile;
+use crate::query::service::Service;
+
+/// Prefix of the category types defined by Apple
+const CATEGORY_PREFIX: &str = "public.app-category.";
+
+/// Metadata key of the number of services with the same name collapsed into the one kept
+pub const COLLAPSED_KEY: &str = "collapsed";
+
+/// Overrides of the configured checks and filters for a single query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryOptions {
+    /// Include hidden bundles and bundles in hidden folders
+    pub include_hidden: bool,
+    /// Include bundles under the ignored paths
+    pub include_ignored: bool,
+    /// Only include bundles of the category type, either in full like
+    /// `public.app-category.games` or without the prefix like `games`
+    pub category_filter: Option<String>,
+    /// Only include bundles with the extension, compared case insensitively
+    pub extension_filter: Option<String>,
+    /// Only include bundles with one of the extensions, with or without the leading `.`
+    /// and compared case insensitively. All discovered bundles are included if empty
+    pub allowed_extensions: Vec<String>,
+    /// Keep only the best ranked one of services with the same name, with the number
+    /// of them under `COLLAPSED_KEY` in its metadata
+    pub collapse_duplicate_names: bool,
+    /// Read the `Info.plist` of bundles found by walking, for their bundle ids, versions
+    /// and other properties. Without it, services only have their names and paths, which
+    /// is cheaper for counting or checking existence. Services read from the cache
+    /// already have them. Enabled by default
+    pub enrich: bool,
+    /// Only include bundles installed from the App Store, having their receipts.
+    /// Services read without enrichment are never taken as installed from the App Store
+    pub app_store_only: bool,
+    /// Walk the roots instead of reading the cache, e.g. to show changes not refreshed yet.
+    /// The cache is left as it is for other queries
+    pub bypass_cache: bool,
+    /// Record how deep below its root each service is found under `DEPTH_KEY` in its
+    /// metadata, for debugging the walk. The roots are walked instead of reading the cache
+    pub record_depth: bool,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        QueryOptions {
+            include_hidden: false,
+            include_ignored: false,
+            category_filter: None,
+            extension_filter: None,
+            allowed_extensions: Vec::new(),
+            collapse_duplicate_names: false,
+            enrich: true,
+            app_store_only: false,
+            bypass_cache: false,
+            record_depth: false,
+        }
+    }
+}
+
+impl QueryOptions {
+    /// Check if any configured check is disabled, so cached services cannot be used
+    pub fn overrides_checks(&self) -> bool {
+        self.include_hidden || self.include_ignored
+    }
+
+    /// Check if the roots have to be walked instead of reading cached services
+    pub fn skips_cache(&self) -> bool {
+        self.bypass_cache || self.record_depth || self.overrides_checks()
+    }
+
+    /// Check if the service passes the filters, compared case insensitively
+    pub fn keeps(&self, service: &Service) -> bool {
+        if self.app_store_only && !service.is_app_store() {
+            return false;
+        }
+        let extension = service
+            .id()
+            .extension()
+            .and_then(|extension| extension.to_str());
+        if let Some(filter) = &self.extension_filter {
+            if !extension.is_some_and(|extension| extension.eq_ignore_ascii_case(filter)) {
+                return false;
+            }
+        }
+        if !self.allowed_extensions.is_empty()
+            && !extension.is_some_and(|extension| {
+                self.allowed_extensions.iter().any(|allowed| {
+                    allowed
+                        .trim_start_matches('.')
+                        .eq_ignore_ascii_case(extension)
+                })
+            })
+        {
+            return false;
+        }
+        let filter = match &self.category_filter {
+            Some(filter) => filter,
+            None => return true,
+        };
+        service.category().is_some_and(|category| {
+            category.eq_ignore_ascii_case(filter)
+                || category
+                    .strip_prefix(CATEGORY_PREFIX)
+                    .is_some_and(|category| category.eq_ignore_ascii_case(filter))
+        })
+    }
+
+    /// Disable checks of the profile overridden by the options
+    pub fn apply(&self, profile: Profile) -> Profile {
+        Profile {
+            hidden_checked: profile.hidden_checked && !self.include_hidden,
+            ignored_checked: profile.ignored_checked && !self.include_ignored,
+            ..profile
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::query::matcher::Pattern;
+
+/// Components of a query request, as they are matched
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedQuery {
+    /// Terms that must all match
+    pub terms: Vec<String>,
+    /// Terms that must not match, without their `-`
+    pub excluded: Vec<String>,
+    /// Substring to match, for requests wrapped in quotes
+    pub literal: Option<String>,
+    /// Extension of the bundles to keep, from the trailing `.extension` term
+    pub extension: Option<String>,
+    /// Other groups of terms separated by `OR` or `|`, any of which may match instead
+    pub alternatives: Vec<ParsedQuery>,
+}
+
+/// Syntax error of a query request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryParseError {
+    /// A quote is opened or closed without its pair
+    UnterminatedQuote,
+    /// A `-` is not followed by the term to exclude
+    EmptyExclusion,
+}
+
+impl Display for QueryParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryParseError::UnterminatedQuote => write!(f, "Quote is not terminated"),
+            QueryParseError::EmptyExclusion => write!(f, "Nothing to exclude after `-`"),
+        }
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+impl ParsedQuery {
+    /// Parse the request without the trailing extension term into the components
+    /// of the pattern it is matched with. Quotes left in terms are not paired,
+    /// and a lone `-` excludes nothing, so both are syntax errors
+    pub fn parse(req: &str) -> Result<Self, QueryParseError> {
+        let parsed = match Pattern::parse(req) {
+            Pattern::Literal(literal) => {
+                return Ok(ParsedQuery {
+                    literal: Some(literal),
+                    ..ParsedQuery::default()
+                })
+            }
+            Pattern::Any(groups) => {
+                let mut groups = groups.iter().map(Self::from_group);
+                let mut parsed = groups.next().unwrap_or_default();
+                parsed.alternatives = groups.collect();
+                parsed
+            }
+            pattern => Self::from_group(&pattern),
+        };
+        let unpaired = |parsed: &ParsedQuery| {
+            let mut terms = parsed.terms.iter().chain(&parsed.excluded);
+            terms.any(|term| term.contains('"'))
+        };
+        if unpaired(&parsed) || parsed.alternatives.iter().any(unpaired) {
+            return Err(QueryParseError::UnterminatedQuote);
+        }
+        if req.split_whitespace().any(|term| term == "-") {
+            return Err(QueryParseError::EmptyExclusion);
+        }
+        Ok(parsed)
+    }
+
+    /// Components of a group of terms which must all match
+    fn from_group(pattern: &Pattern) -> Self {
+        let patterns = match pattern {
+            Pattern::All(patterns) => patterns.as_slice(),
+            pattern => std::slice::from_ref(pattern),
+        };
+        let mut parsed = ParsedQuery::default();
+        for pattern in patterns {
+            match pattern {
+                Pattern::Not(excluded) => parsed.excluded.extend(term(excluded)),
+                pattern => parsed.terms.extend(term(pattern)),
+            }
+        }
+        parsed
+    }
+}
+
+/// Get the term the pattern is parsed from, none for the empty request
+fn term(pattern: &Pattern) -> Option<String> {
+    match pattern {
+        Pattern::Fuzzy(term) if term.is_empty() => None,
+        Pattern::Fuzzy(term) => Some(term.to_owned()),
+        Pattern::Wildcard(wildcard) => Some(wildcard.as_str().to_owned()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod parsed_test {
+    use crate::query::matcher::Pattern;
+    use crate::query::parsed::{ParsedQuery, QueryParseError};
+
+    #[test]
+    fn test_parse() {
+        let parsed = ParsedQuery::parse(" vis -code stu*io ").expect("Failed to parse");
+        assert_eq!(parsed.terms, ["vis", "stu*io"]);
+        assert_eq!(parsed.excluded, ["code"]);
+        assert_eq!(parsed.literal, None);
+        let parsed = ParsedQuery::parse("\"Visual Studio\"").expect("Failed to parse");
+        assert_eq!(parsed.literal.as_deref(), Some("visual studio"));
+        assert!(parsed.terms.is_empty());
+    }
+
+    #[test]
+    fn test_parse_alternatives() {
+        let parsed = ParsedQuery::parse("books OR music -pro | tv").expect("Failed to parse");
+        assert_eq!(parsed.terms, ["books"]);
+        assert_eq!(parsed.alternatives.len(), 2);
+        assert_eq!(parsed.alternatives[0].terms, ["music"]);
+        assert_eq!(parsed.alternatives[0].excluded, ["pro"]);
+        assert_eq!(parsed.alternatives[1].terms, ["tv"]);
+        let parsed = ParsedQuery::parse("books or music").expect("Failed to parse");
+        assert_eq!(parsed.terms, ["books", "or", "music"]);
+        assert!(parsed.alternatives.is_empty());
+    }
+
+    #[test]
+    fn test_parse_as_matched() {
+        let req = r#""vis" "stu""#;
+        let parsed = ParsedQuery::parse(req).expect("Failed to parse");
+        match Pattern::parse(req) {
+            Pattern::Literal(literal) => assert_eq!(parsed.literal, Some(literal)),
+            _ => panic!("Not parsed as a literal"),
+        }
+        let parsed = ParsedQuery::parse(r#"stu\*io"#).expect("Failed to parse");
+        assert_eq!(parsed.terms, [r#"stu\*io"#]);
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        for req in ["\"visual studio", "visual studio\"", "\""] {
+            assert_eq!(
+                ParsedQuery::parse(req),
+                Err(QueryParseError::UnterminatedQuote)
+            );
+        }
+        assert_eq!(
+            ParsedQuery::parse("visual - code"),
+            Err(QueryParseError::EmptyExclusion)
+        );
+    }
+}
@@ -0,0 +1,9 @@
+/// Progress of walking the roots to rebuild the cache, reported as each root is walked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RebuildProgress {
+    /// Roots walked so far, including the one just walked
+    pub roots_walked: usize,
+    pub roots: usize,
+    /// Services found in the roots walked so far
+    pub services: usize,
+}
@@ -1,107 +1,5276 @@
-use std::fs::read_dir;
-use std::io::Write;
-use std::path::Path;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::{self, Error, ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread::Scope;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::query::checker::{Checker, Outcome};
-use crate::query::matcher;
-use crate::query::service::Service;
+use crate::configurator::{
+    Configs, DedupPolicy, MatchField, PathDisplay, Profile, Root, SourceOrder, TieBreak,
+    TraversalOrder,
+};
+use crate::query::arrange::{ArrangeKey, TieKey};
+#[cfg(feature = "async")]
+use crate::query::blocking::BlockingPool;
+use crate::query::cache::{CacheManager, FileStore, MemoryStore};
+use crate::query::cancellation::CancellationToken;
+use crate::query::checker::{Checker, Classification, Outcome};
+use crate::query::diagnostics::{QueryDiagnostics, ResultOrigin};
+#[cfg(feature = "async")]
+use crate::query::diff::{diff_results, ProgressiveResult};
+use crate::query::error::QueryError;
+use crate::query::export::{self, ExportFormat};
+use crate::query::frecency::{self, FrecencyStore, Selection};
+use crate::query::fs::{Fs, StdFs};
+use crate::query::index::TokenIndex;
+use crate::query::lru::LruCache;
+use crate::query::matcher::{self, PathFragments, Pattern, Prefilter, Score, Target, EXACT_SCORE};
+use crate::query::metrics::Metrics;
+use crate::query::normalizer::{Identity, QueryNormalizer};
+use crate::query::options::{QueryOptions, COLLAPSED_KEY};
+use crate::query::parsed::{ParsedQuery, QueryParseError};
+use crate::query::progress::RebuildProgress;
+use crate::query::refresher::Refresher;
+use crate::query::registry::{CheckerRegistry, LeafChecker};
+use crate::query::self_check::SelfCheckReport;
+use crate::query::service::{RankedService, Service, DEPTH_KEY, LABEL_KEY};
+use crate::query::top_k::TopK;
+use crate::query::walk::{EmptyFolders, Walk, WalkHistory, WalkScope};
+use crate::utils;
 use crate::utils::serde::serialize_to_string;
 
+/// Queries services under the configured roots. All state shared by queries is behind
+/// locks or atomics, so a processor can serve queries from many threads through `&self`
 pub struct QueryProcessor<'a, W: Write> {
+    configs: &'a Configs,
     checker: Checker<'a>,
+    cache: Option<CacheManager>,
+    query_cache: Mutex<LruCache<String, Vec<Service>>>,
+    output_cache: Mutex<LruCache<String, Vec<u8>>>,
+    rebuild_lock: Mutex<()>,
+    rebuilds: AtomicUsize,
+    truncations: AtomicUsize,
+    queries: AtomicUsize,
+    cache_hits: AtomicUsize,
+    discovered: AtomicUsize,
+    scored: AtomicUsize,
+    query_nanos: AtomicU64,
+    sizes: Mutex<HashMap<PathBuf, u64>>,
+    frecency: FrecencyStore,
+    device_id: fn(&Path) -> Option<u64>,
+    installed: fn(&Service) -> Option<SystemTime>,
+    spotlight: fn(&str) -> Vec<PathBuf>,
+    cancellation: CancellationToken,
+    fs: Box<dyn Fs + Send + Sync + 'a>,
+    normalizer: Box<dyn QueryNormalizer + Send + Sync + 'a>,
     output: W,
 }
 
 impl<'a, W: Write> QueryProcessor<'a, W> {
     /// New query processor
     pub fn new(writer: W) -> Self {
-        let ignored_paths = crate::CONFIG.get_ignore_paths();
+        Self::with_configs(&crate::CONFIG, writer)
+    }
+
+    /// New query processor based on given configs
+    pub fn with_configs(configs: &'a Configs, writer: W) -> Self {
+        let system_version = if configs.is_compatibility_checked() {
+            utils::system_version()
+        } else {
+            None
+        };
         QueryProcessor {
-            checker: Checker::new(ignored_paths),
+            configs,
+            checker: Checker::new(configs.get_ignore_paths())
+                .with_ignore_overrides(
+                    configs
+                        .get_ignore_overrides()
+                        .iter()
+                        .map(PathBuf::as_path)
+                        .collect(),
+                )
+                .with_system_version(system_version)
+                .with_ignored_extensions(configs.get_ignore_extensions())
+                .with_symlinked_bundles(configs.is_symlinked_bundles_included())
+                .with_max_symlink_depth(configs.get_max_symlink_depth())
+                .with_quarantine_mode(configs.get_quarantine_mode())
+                .with_executable_required(configs.is_executable_required())
+                .with_strict_bundles(configs.is_strict_bundles())
+                .with_internal_folders(configs.get_internal_helper_folders())
+                .with_enabled_checks(configs.get_checkers())
+                .with_roots(configs.get_paths()),
+            cache: configs
+                .get_cache_dir()
+                .map(|dir| CacheManager::new(dir, configs)),
+            query_cache: Mutex::new(LruCache::new(configs.get_query_cache_size())),
+            output_cache: Mutex::new(LruCache::new(configs.get_output_cache_size())),
+            rebuild_lock: Mutex::new(()),
+            rebuilds: AtomicUsize::new(0),
+            truncations: AtomicUsize::new(0),
+            queries: AtomicUsize::new(0),
+            cache_hits: AtomicUsize::new(0),
+            discovered: AtomicUsize::new(0),
+            scored: AtomicUsize::new(0),
+            query_nanos: AtomicU64::new(0),
+            sizes: Mutex::new(HashMap::new()),
+            frecency: match configs.get_cache_dir() {
+                Some(dir) => FrecencyStore::new(FileStore::new(dir.join("selections.json"))),
+                None => FrecencyStore::new(MemoryStore::default()),
+            },
+            device_id: |path| path.metadata().ok().map(|metadata| metadata.dev()),
+            installed: Service::installed,
+            spotlight: utils::spotlight_search,
+            cancellation: CancellationToken::new(),
+            fs: Box::new(StdFs),
+            normalizer: Box::new(Identity),
             output: writer,
         }
     }
 
-    /// Query based on the request, and return serialized bytes of the services
+    /// Preprocess each request with the normalizer before matching
+    pub fn with_normalizer<N: QueryNormalizer + Send + Sync + 'a>(mut self, normalizer: N) -> Self {
+        self.normalizer = Box::new(normalizer);
+        self
+    }
+
+    /// Also exclude paths with the registered checkers enabled in config. Paths excluded
+    /// by filtering only checkers are still walked into.
+    /// Services cached before the checkers are registered are not checked again
+    pub fn with_checkers(mut self, registry: CheckerRegistry) -> Self {
+        let (terminating, filtering) = registry.into_enabled(self.configs.get_checkers());
+        self.checker = self
+            .checker
+            .with_custom_checkers(terminating)
+            .with_filter_checkers(filtering);
+        self
+    }
+
+    /// Also treat paths any of the checkers recognizes as services, besides bundles.
+    /// Services cached before the checkers are added are not checked again
+    pub fn with_leaf_checkers(mut self, checkers: Vec<LeafChecker>) -> Self {
+        self.checker = self.checker.with_leaf_checkers(checkers);
+        self
+    }
+
+    /// List folders with the filesystem while walking the roots
+    pub fn with_fs<F: Fs + Send + Sync + 'a>(mut self, fs: F) -> Self {
+        self.fs = Box::new(fs);
+        self
+    }
+
+    /// Stop walking once the token is cancelled. Services found by a cancelled walk
+    /// are still returned, but never cached
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Query based on the request, and write serialized services to the output, each
+    /// on its own line. Nothing is written if no service matches, so an empty output means
+    /// no matches, while failures are only logged.
+    /// Recent outputs are kept serialized if configured, and written again as they are
     pub fn query(&mut self, req: &str) {
-        let req = req.to_lowercase();
-        crate::CONFIG
+        let key = self.normalize_request(req);
+        let cached = self
+            .output_cache
+            .lock()
+            .ok()
+            .and_then(|mut cache| cache.get(&key));
+        let output = match cached {
+            Some(output) => {
+                self.cache_hits.fetch_add(1, Ordering::Relaxed);
+                output
+            }
+            None => {
+                let services = self.query_services(req);
+                let output = self.serialize_services(&services);
+                if !self.cancellation.is_cancelled() {
+                    if let Ok(mut cache) = self.output_cache.lock() {
+                        cache.put(key, output.clone());
+                    }
+                }
+                output
+            }
+        };
+        if let Err(error) = self.output.write_all(&output) {
+            eprintln!("Failed to write: {}", error);
+        }
+    }
+
+    /// Query based on the request, and return all matched services, the highest scored
+    /// first unless pinned or ranked higher by selections
+    pub fn query_services(&self, req: &str) -> Vec<Service> {
+        self.timed_services(req).0
+    }
+
+    /// Query based on the request, and return matched services the predicate keeps,
+    /// in the same order as `query_services`. Recent results are kept unfiltered, so
+    /// different predicates can be used with the same request
+    pub fn query_filtered<F: Fn(&Service) -> bool>(&self, req: &str, predicate: F) -> Vec<Service> {
+        let mut services = self.query_services(req);
+        services.retain(|service| predicate(service));
+        services
+    }
+
+    /// Query based on the request, and return ranked services with where they are read from
+    pub fn query_diagnosed(&self, req: &str) -> QueryDiagnostics {
+        let (services, served_from, _) = self.timed_services(req);
+        QueryDiagnostics {
+            services: self.ranked_services(req, services),
+            served_from,
+        }
+    }
+
+    /// Match services like `matched_services`, and count the time taken
+    fn timed_services(&self, req: &str) -> (Vec<Service>, ResultOrigin, Result<(), QueryError>) {
+        let started = Instant::now();
+        let matched = self.matched_services(req);
+        let elapsed = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        self.query_nanos.fetch_add(elapsed, Ordering::Relaxed);
+        matched
+    }
+
+    /// Query based on the request like `query_services`, but fail instead of skipping
+    /// roots that cannot be read by the walk, and fail if the cache cannot be rebuilt
+    /// or parsed. A cache that is not writable is bypassed instead.
+    /// No matches are `Ok` with no services
+    pub fn try_query(&self, req: &str) -> Result<Vec<Service>, QueryError> {
+        if self.configs.get_roots().is_empty() {
+            return Err(QueryError::Config("No root is configured".to_owned()));
+        }
+        let (services, _, queried) = self.timed_services(req);
+        queried.map(|_| services)
+    }
+
+    /// Get counters of the work done since this processor was created
+    pub fn metrics(&self) -> Metrics {
+        let queries = self.queries.load(Ordering::Relaxed);
+        let query_nanos = self.query_nanos.load(Ordering::Relaxed);
+        Metrics {
+            queries,
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_rebuilds: self.rebuilds.load(Ordering::Relaxed),
+            bundles_discovered: self.discovered.load(Ordering::Relaxed),
+            services_scored: self.scored.load(Ordering::Relaxed),
+            checker_terminations: self.checker.terminations(),
+            average_latency: Duration::from_nanos(
+                query_nanos.checked_div(queries as u64).unwrap_or_default(),
+            ),
+        }
+    }
+
+    /// Match services with the request, and return them with where they are read from and
+    /// the first failure reading them, if any. Results with a failure are not kept as recent
+    fn matched_services(&self, req: &str) -> (Vec<Service>, ResultOrigin, Result<(), QueryError>) {
+        let req = self.normalize_request(req);
+        if let Some(services) = self
+            .query_cache
+            .lock()
+            .ok()
+            .and_then(|mut cache| cache.get(&req))
+        {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            return (services, ResultOrigin::RecentResults, Ok(()));
+        }
+        let mut services = Vec::new();
+        let (text, options) = self.split_request(&req);
+        let pattern = self.parse_pattern(text);
+        let (origin, streamed) =
+            self.stream_matching(&pattern, &options, |service| services.push(service));
+        let mut found = Vec::new();
+        if services.is_empty() && self.configs.is_path_fallback_enabled() {
+            found.extend(self.path_services(text));
+        }
+        if services.len() + found.len() < self.configs.get_spotlight_threshold() {
+            found.extend(self.spotlight_services(text));
+        }
+        found.retain(|service| options.keeps(service));
+        let origin = match found.is_empty() {
+            true => origin,
+            false => ResultOrigin::Mixed,
+        };
+        services.extend(found);
+        self.arrange_services(&mut services, &pattern);
+        if self.cancellation.is_cancelled() || streamed.is_err() {
+            return (services, origin, streamed);
+        }
+        if let Ok(mut cache) = self.query_cache.lock() {
+            cache.put(req, services.clone());
+        }
+        (services, origin, streamed)
+    }
+
+    /// Query based on the request, and return matched services grouped by the root
+    /// containing them in the order roots are configured. Services in each group
+    /// are sorted by their scores. Services outside of roots are grouped by their folders
+    pub fn query_grouped(&self, req: &str) -> Vec<(PathBuf, Vec<Service>)> {
+        let pattern = self.request_pattern(req);
+        let mut groups: Vec<(PathBuf, Vec<(u32, Service)>)> = self
+            .configs
             .get_paths()
             .into_iter()
-            .for_each(|path| self.walk_paths(path, &req));
-    }
-
-    /// Recursively iterate through files and folders, and return all legit file paths
-    fn walk_paths<P: AsRef<Path>>(&mut self, entry: P, query: &str) {
-        let entry = entry.as_ref();
-        match self.checker.check(entry) {
-            Outcome::UnwantedPath => (),
-            Outcome::BundlePath => self.filter_output_path(entry, query),
-            Outcome::NormalPath => {
-                let mut read_folder = match read_dir(&entry) {
-                    Ok(read_folder) => read_folder,
-                    Err(err) => {
-                        eprintln!("Read folder error: {}", err);
-                        return;
-                    }
+            .map(|root| (root.to_path_buf(), Vec::new()))
+            .collect();
+        for service in self.query_services(req) {
+            let score = match self.best_match(&pattern, &service) {
+                Some((_, score)) => score.value,
+                None => continue,
+            };
+            let key = self
+                .root_of(&service)
+                .or_else(|| service.id().parent())
+                .unwrap_or_else(|| service.id())
+                .to_path_buf();
+            match groups.iter_mut().find(|(root, _)| *root == key) {
+                Some((_, group)) => group.push((score, service)),
+                None => groups.push((key, vec![(score, service)])),
+            }
+        }
+        groups
+            .into_iter()
+            .filter(|(_, group)| !group.is_empty())
+            .map(|(root, mut group)| {
+                group.sort_by(|(left, _), (right, _)| right.cmp(left));
+                (
+                    root,
+                    group.into_iter().map(|(_, service)| service).collect(),
+                )
+            })
+            .collect()
+    }
+
+    /// Get the innermost configured root containing the service
+    fn root_of(&self, service: &Service) -> Option<&'a Path> {
+        self.configs
+            .get_paths()
+            .into_iter()
+            .filter(|root| service.id().starts_with(root))
+            .max_by_key(|root| root.components().count())
+    }
+
+    /// Walk the roots and return paths of all bundles found, each once and sorted,
+    /// without matching, ranking or reading the cache
+    pub fn inventory(&self) -> BTreeSet<PathBuf> {
+        let mut paths = BTreeSet::new();
+        let pattern = Pattern::parse("");
+        let _ = self.walk_roots(&pattern, &QueryOptions::default(), &mut |service| {
+            paths.insert(service.id().to_path_buf());
+        });
+        paths
+    }
+
+    /// Check what the path is to the checks configured for the root holding it,
+    /// or the default checks if no root holds it, without walking into it
+    pub fn classify(&self, path: &Path) -> Classification {
+        let profile = self
+            .configs
+            .get_roots()
+            .iter()
+            .filter(|root| path.starts_with(root.path()))
+            .max_by_key(|root| root.path().components().count())
+            .map(|root| self.configs.get_profile(root))
+            .unwrap_or_default();
+        self.checker.classify(path, &profile)
+    }
+
+    /// Get the service as displayed, with its path relative to its root
+    /// and its stable id if configured. Signatures only tell the cache which bundles
+    /// are unchanged, so they are never displayed
+    fn displayed<'s>(&self, service: &'s Service) -> Cow<'s, Service> {
+        let relative = match self.configs.get_path_display() {
+            PathDisplay::Absolute => None,
+            PathDisplay::Relative => self
+                .root_of(service)
+                .and_then(|root| service.relative_path(root))
+                .filter(|relative| !relative.as_os_str().is_empty()),
+        };
+        let displayed = match (relative, service.signature()) {
+            (Some(relative), _) => {
+                Cow::Owned(service.clone().with_subtitle(relative).without_signature())
+            }
+            (None, Some(_)) => Cow::Owned(service.clone().without_signature()),
+            (None, None) => Cow::Borrowed(service),
+        };
+        match self.configs.is_uids_emitted() {
+            true => Cow::Owned(displayed.into_owned().with_uid()),
+            false => displayed,
+        }
+    }
+
+    /// Write all services, matched by no request, to the file in the format
+    pub fn export<P: AsRef<Path>>(&self, path: P, format: ExportFormat) -> io::Result<()> {
+        let mut services = Vec::new();
+        self.stream_services("", &QueryOptions::default(), |service| {
+            services.push(service)
+        });
+        Self::dedup_services(
+            &mut services,
+            self.configs.is_native_arch_preferred(),
+            self.configs.get_dedup_policy(),
+        );
+        let file = io::BufWriter::new(std::fs::File::create(path)?);
+        export::write_services(&services, format, file)
+    }
+
+    /// Load all services once, so that each request only runs the matcher over them.
+    /// Match targets are prepared once here, so requests skip services their folded
+    /// targets rule out and match the rest without cleaning the targets again
+    pub fn prepare(&self) -> ServiceIndex<'_, 'a, W> {
+        let mut services = Vec::new();
+        self.stream_services("", &QueryOptions::default(), |service| {
+            services.push(service)
+        });
+        let mut tokens = TokenIndex::default();
+        let mut prepared = Vec::with_capacity(services.len());
+        for (position, service) in services.iter().enumerate() {
+            let targets = self.match_targets(service);
+            for (_, target) in &targets {
+                tokens.insert(position, target);
+            }
+            prepared.push(
+                targets
+                    .iter()
+                    .map(|(_, target)| Target::new(target))
+                    .collect(),
+            );
+        }
+        ServiceIndex {
+            processor: self,
+            services,
+            tokens,
+            targets: prepared,
+        }
+    }
+
+    /// Query based on the request with checks overridden and results filtered by
+    /// the options, and return all matched services. Options overriding checks bypass the caches
+    pub fn query_with(&self, req: &str, options: &QueryOptions) -> Vec<Service> {
+        if *options == QueryOptions::default() {
+            return self.query_services(req);
+        }
+        let req = self.normalize_request(req);
+        let (text, options) = self.split_request_with(&req, options);
+        let mut services = Vec::new();
+        self.stream_services(text, &options, |service| services.push(service));
+        self.arrange_services(&mut services, &self.parse_pattern(text));
+        if options.collapse_duplicate_names {
+            Self::collapse_duplicate_names(&mut services);
+        }
+        services
+    }
+
+    /// Query based on the request, and return matched services with how they
+    /// are matched, in the same order as `query_services`
+    pub fn query_ranked(&self, req: &str) -> Vec<RankedService> {
+        self.ranked_services(req, self.query_services(req))
+    }
+
+    /// Query based on the request, and serialize matched services into a JSON array.
+    /// With ranges, each service also has `matchRanges`, the char index pairs of the
+    /// parts of its name matched by the request. No matches are serialized as `[]`
+    pub fn query_json(&self, req: &str, with_ranges: bool) -> serde_json::Result<String> {
+        let services = self.query_services(req);
+        let pattern = with_ranges.then(|| self.request_pattern(req));
+        let values = services
+            .iter()
+            .map(|service| {
+                let mut value = serde_json::to_value(self.displayed(service))?;
+                if let (Some(pattern), Some(object)) = (&pattern, value.as_object_mut()) {
+                    let ranges = self.name_ranges(pattern, service);
+                    object.insert("matchRanges".to_owned(), serde_json::json!(ranges));
+                }
+                Ok(value)
+            })
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        serde_json::to_string(&values)
+    }
+
+    /// Get the char index pairs of the parts of the service name matched by the pattern.
+    /// Scores range over the sanitized name, e.g. composed and stripped of control characters,
+    /// so indices are counted in it rather than in the name as it is
+    fn name_ranges(&self, pattern: &Pattern, service: &Service) -> Vec<(usize, usize)> {
+        let title = matcher::sanitize(&service.title);
+        let char_index = |byte: usize| {
+            title
+                .char_indices()
+                .take_while(|(index, _)| *index < byte)
+                .count()
+        };
+        pattern
+            .score(&title, self.configs.get_length_weight())
+            .map(|score| score.ranges)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|range| (char_index(range.start), char_index(range.end)))
+            .collect()
+    }
+
+    /// Query based on the request, and return at most `max_results` matched services
+    /// with the highest scores, equally scored ones in the order `query_ranked` returns them.
+    /// Services are scored once as they are read or walked, instead of matched first.
+    /// Once `max_results` services match exactly, nothing can outrank them, so only
+    /// duplicates of the services scored are scored after, unless arranging may move
+    /// later exact matches ahead. Spotlight and the path fallback are not searched
+    pub fn query_top(&self, req: &str, max_results: usize) -> Vec<RankedService> {
+        let req = self.normalize_request(req);
+        let (text, options) = self.split_request(&req);
+        let pattern = self.parse_pattern(text);
+        let short_circuited = self.keeps_found_order();
+        let mut keys = HashSet::new();
+        let mut ranked = HashMap::new();
+        let mut services = Vec::new();
+        let mut exact = 0;
+        let _ = self.stream_matching(&Pattern::parse(""), &options, |service| {
+            if short_circuited && exact >= max_results && !keys.contains(&service.dedup_key()) {
+                return;
+            }
+            if let Some(service) = self.rank(&pattern, service) {
+                exact += usize::from(service.score == EXACT_SCORE);
+                if short_circuited {
+                    keys.insert(service.service.dedup_key());
+                }
+                services.push(service.service.clone());
+                ranked.insert(service.service.id().to_path_buf(), service);
+            }
+        });
+        self.arrange_scored(&mut services, pattern.is_empty(), |service| {
+            ranked.get(service.id()).map(|ranked| ranked.score)
+        });
+        let mut services = services
+            .iter()
+            .filter_map(|service| ranked.remove(service.id()))
+            .collect::<Vec<_>>();
+        services.sort_by_key(|service| Reverse(service.score));
+        services.truncate(max_results);
+        services
+    }
+
+    /// Query based on the request, and return the first `k` services `query_services` returns.
+    /// Services are arranged in a heap of the best `k` as they are read from the cache or
+    /// walked, instead of collecting and sorting all matched services. Only `k` services are
+    /// held, along with what decides between duplicates for the dedup keys of matched ones
+    pub fn query_top_k(&self, req: &str, k: usize) -> Vec<Service> {
+        let req = self.normalize_request(req);
+        let (text, options) = self.split_request(&req);
+        let pattern = self.parse_pattern(text);
+        let selections = self.selections();
+        let native_preferred = self.configs.is_native_arch_preferred();
+        let policy = self.configs.get_dedup_policy();
+        let mut top = TopK::new(k);
+        let mut offer = |service: Service| {
+            let preferred = |service: &Service, kept: &Service| {
+                Self::is_dedup_preferred(service, kept, native_preferred, policy)
+            };
+            top.push(service, preferred, |service| {
+                let score = self
+                    .best_match(&pattern, service)
+                    .map(|(_, score)| score.value);
+                let tie = self.tie_keys(std::slice::from_ref(service)).remove(0);
+                self.arrange_key(service, pattern.is_empty(), &selections, score, tie)
+            })
+        };
+        let mut matched = 0;
+        self.stream_services(text, &options, |service| {
+            matched += 1;
+            offer(service)
+        });
+        if matched == 0 && self.configs.is_path_fallback_enabled() {
+            for service in self.path_services(text) {
+                matched += 1;
+                if options.keeps(&service) {
+                    offer(service);
+                }
+            }
+        }
+        if matched < self.configs.get_spotlight_threshold() {
+            self.spotlight_services(text)
+                .into_iter()
+                .filter(|service| options.keeps(service))
+                .for_each(offer);
+        }
+        top.into_sorted_vec()
+    }
+
+    fn ranked_services(&self, req: &str, services: Vec<Service>) -> Vec<RankedService> {
+        let pattern = self.request_pattern(req);
+        services
+            .into_iter()
+            .filter_map(|service| self.rank(&pattern, service))
+            .collect()
+    }
+
+    /// Score the service with the pattern, none if it does not match
+    fn rank(&self, pattern: &Pattern, service: Service) -> Option<RankedService> {
+        let (matched_field, score) = self.best_match(pattern, &service)?;
+        let max_score = pattern
+            .max_score(self.configs.get_length_weight())
+            .saturating_add(self.configs.get_recency_weight());
+        Some(RankedService {
+            service,
+            score: score.value,
+            normalized_score: score.normalized(max_score),
+            matched_field,
+            ranges: score.ranges,
+        })
+    }
+
+    /// Record that the user selected the service, so it ranks higher in later queries
+    pub fn record_selection(&self, service: &Service) -> io::Result<()> {
+        self.frecency.record(service.dedup_key(), frecency::now())?;
+        self.clear_recent_results();
+        Ok(())
+    }
+
+    /// Get at most `limit` existing services, the most recently selected first, without matching
+    pub fn query_recent(&self, limit: usize) -> Vec<Service> {
+        let keys = match self.frecency.recent() {
+            Ok(keys) if !keys.is_empty() => keys,
+            Ok(_) => return Vec::new(),
+            Err(error) => {
+                eprintln!("Failed to read selections: {}", error);
+                return Vec::new();
+            }
+        };
+        let mut services = Vec::new();
+        self.stream_services("", &QueryOptions::default(), |service| {
+            services.push(service)
+        });
+        Self::dedup_services(
+            &mut services,
+            self.configs.is_native_arch_preferred(),
+            self.configs.get_dedup_policy(),
+        );
+        let mut services = services
+            .into_iter()
+            .map(|service| (service.dedup_key(), service))
+            .collect::<HashMap<_, _>>();
+        keys.iter()
+            .filter_map(|key| services.remove(key))
+            .take(limit)
+            .collect()
+    }
+
+    /// Get the ranking bonus of the service from how often and how recently it was selected
+    pub fn rank_bonus(&self, service: &Service) -> f64 {
+        self.frecency
+            .bunch_read()
+            .ok()
+            .and_then(|selections| selections.get(&service.dedup_key()).copied())
+            .map(|selection| selection.bonus(frecency::now()))
+            .unwrap_or_default()
+    }
+
+    /// Query based on the request, and call `on_service` with each matched service
+    /// as soon as it is found
+    pub fn query_streaming<F: FnMut(Service)>(&self, req: &str, on_service: F) {
+        let req = self.normalize_request(req);
+        let (text, options) = self.split_request(&req);
+        self.stream_services(text, &options, on_service);
+    }
+
+    /// Stream matched services of the normalized request.
+    /// The cache only holds services found with the configured checks, so it is
+    /// bypassed if any check is overridden
+    fn stream_services<F: FnMut(Service)>(
+        &self,
+        req: &str,
+        options: &QueryOptions,
+        on_service: F,
+    ) -> ResultOrigin {
+        self.stream_matching(&self.parse_pattern(req), options, on_service)
+            .0
+    }
+
+    /// Stream services matched by the pattern, like `stream_services`, and return where
+    /// they are read from with the first failure reading them, if any
+    fn stream_matching<F: FnMut(Service)>(
+        &self,
+        pattern: &Pattern,
+        options: &QueryOptions,
+        mut on_service: F,
+    ) -> (ResultOrigin, Result<(), QueryError>) {
+        let kept = |service: &Service| self.is_recent(service.id()) && options.keeps(service);
+        let mut on_service = |service: Service| {
+            if kept(&service) {
+                on_service(service)
+            }
+        };
+        let (origin, streamed) = match &self.cache {
+            Some(cache) if !options.skips_cache() && !cache.is_disabled() => {
+                let (services, origin, read) = self.cached_services(cache);
+                let matched = services
+                    .into_iter()
+                    .filter(|service| self.is_matched(pattern, service))
+                    .collect::<Vec<_>>();
+                let matched = match self.configs.is_existence_verified() {
+                    true => self.existing_services(matched),
+                    false => matched,
                 };
-                while let Some(Ok(path)) = read_folder.next() {
-                    self.walk_paths(path.path(), query);
+                matched.into_iter().for_each(&mut on_service);
+                (origin, read)
+            }
+            _ => {
+                let walked = self.walk_roots(pattern, options, &mut on_service);
+                (ResultOrigin::Walk, walked)
+            }
+        };
+        let mut explicit = self
+            .explicit_services()
+            .filter(|service| self.is_matched(pattern, service) && kept(service))
+            .peekable();
+        let origin = match explicit.peek() {
+            Some(_) => ResultOrigin::Mixed,
+            None => origin,
+        };
+        explicit.for_each(on_service);
+        (origin, streamed)
+    }
+
+    /// Drop services whose bundles no longer exist
+    fn existing_services(&self, services: Vec<Service>) -> Vec<Service> {
+        let exists = self.map_concurrently(&services, |service| service.id().exists());
+        services
+            .into_iter()
+            .zip(exists)
+            .filter_map(|(service, exists)| exists.then_some(service))
+            .collect()
+    }
+
+    /// Map the services in order, on at most as many threads as bundle infos are read with
+    fn map_concurrently<T: Send, F: Fn(&Service) -> T + Sync>(
+        &self,
+        services: &[Service],
+        map: F,
+    ) -> Vec<T> {
+        if services.len() <= 1 {
+            return services.iter().map(map).collect();
+        }
+        let width = self.configs.get_enrich_concurrency();
+        let chunk_size = services.len().div_ceil(width).max(1);
+        let map = &map;
+        std::thread::scope(|scope| {
+            services
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || chunk.iter().map(map).collect::<Vec<_>>()))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("Failed to map services"))
+                .collect()
+        })
+    }
+
+    /// Get sizes of the bundles of the services in order, computing the ones not cached yet
+    /// without holding the lock, so other queries are not blocked meanwhile
+    fn bundle_sizes(&self, services: &[Service]) -> Vec<u64> {
+        let cached = {
+            let sizes = self.sizes.lock().expect("Sizes lock is poisoned");
+            services
+                .iter()
+                .map(|service| sizes.get(service.id()).copied())
+                .collect::<Vec<_>>()
+        };
+        let missing = services
+            .iter()
+            .zip(&cached)
+            .filter(|(_, size)| size.is_none())
+            .map(|(service, _)| service.clone())
+            .collect::<Vec<_>>();
+        let computed = self.map_concurrently(&missing, |service| utils::disk_usage(service.id()));
+        let mut sizes = self.sizes.lock().expect("Sizes lock is poisoned");
+        for (service, size) in missing.iter().zip(&computed) {
+            sizes.entry(service.id().to_path_buf()).or_insert(*size);
+        }
+        let mut computed = computed.into_iter();
+        cached
+            .into_iter()
+            .map(|size| size.or_else(|| computed.next()).unwrap_or_default())
+            .collect()
+    }
+
+    /// Match all services against their full paths instead of the configured fields
+    fn path_services(&self, req: &str) -> Vec<Service> {
+        // Paths never start with the first character of the request, so they are not anchored
+        let pattern = Pattern::parse(req).expand(self.configs.get_abbreviations());
+        let mut services = Vec::new();
+        self.stream_services("", &QueryOptions::default(), |service| {
+            if pattern.matches(&service.id().to_string_lossy()) {
+                services.push(service)
+            }
+        });
+        services
+    }
+
+    /// Check if the bundle is modified within the configured window
+    fn is_recent(&self, path: &Path) -> bool {
+        let window = match self.configs.get_modified_within() {
+            Some(window) => window,
+            None => return true,
+        };
+        match path.metadata().and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified.elapsed().map_or(true, |age| age <= window),
+            Err(_) => false,
+        }
+    }
+
+    /// Get existing bundles found by Spotlight that match the request
+    fn spotlight_services(&self, req: &str) -> Vec<Service> {
+        let pattern = self.parse_pattern(req);
+        (self.spotlight)(req)
+            .iter()
+            .filter(|path| {
+                path.exists()
+                    && matches!(
+                        self.checker.check(path, &Profile::default()),
+                        Outcome::BundlePath
+                    )
+            })
+            .map(|path| self.new_service(path))
+            .filter(|service| self.is_matched(&pattern, service))
+            .collect()
+    }
+
+    /// Get configured services that exist and are bundles
+    fn explicit_services(&self) -> impl Iterator<Item = Service> + '_ {
+        self.configs
+            .get_explicit_services()
+            .iter()
+            .filter(move |path| {
+                path.exists()
+                    && matches!(
+                        self.checker.check(path, &Profile::default()),
+                        Outcome::BundlePath
+                    )
+            })
+            .map(move |path| self.new_service(path))
+    }
+
+    /// Read services from the cache, or rebuild it if it cannot be read. Concurrent queries
+    /// missing the cache wait for the same rebuild. Services are returned with where they
+    /// are read from, and the first failure reading or rebuilding the cache
+    fn cached_services(
+        &self,
+        cache: &CacheManager,
+    ) -> (Vec<Service>, ResultOrigin, Result<(), QueryError>) {
+        let read = match read_cached(cache) {
+            Ok(services) => return (self.cache_hit(services), ResultOrigin::Cache, Ok(())),
+            Err(error) => error,
+        };
+        let _rebuilding = self
+            .rebuild_lock
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        // Another query may have rebuilt the cache while this one waited
+        if let Ok(services) = read_cached(cache) {
+            return (self.cache_hit(services), ResultOrigin::Cache, Ok(()));
+        }
+        // A missing or unreadable cache is rebuilt as expected, but an invalid one is a failure
+        let read = match read.kind() {
+            ErrorKind::NotFound | ErrorKind::NotADirectory | ErrorKind::PermissionDenied => Ok(()),
+            _ => Err(QueryError::Deserialize(read)),
+        };
+        let (services, empty, walked) = self.walk_all(None, |_| ());
+        let saved = match self.save_walked(cache, &services, empty.as_ref()) {
+            Err(error) if error.kind() != ErrorKind::Interrupted && !cache.is_disabled() => {
+                eprintln!("Failed to save cache: {}", error);
+                Err(QueryError::Cache(error))
+            }
+            _ => Ok(()),
+        };
+        (services, ResultOrigin::Rebuild, read.and(walked).and(saved))
+    }
+
+    /// Count a read of the cache, and mark the services read as cached
+    fn cache_hit(&self, services: Vec<Service>) -> Vec<Service> {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        services
+            .into_iter()
+            .map(|service| service.with_cached(true))
+            .collect()
+    }
+
+    /// Run the configured canary request once, warming the cache, and log how many
+    /// services it matches. Returns the number, none if no canary request is configured
+    pub fn warm_and_verify(&self) -> Option<usize> {
+        let req = self.configs.get_startup_canary_query()?;
+        let matched = self.query_services(req).len();
+        eprintln!("Canary query {:?} matched {} services", req, matched);
+        Some(matched)
+    }
+
+    /// Check if the roots are readable and the cache is usable, without querying
+    pub fn self_check(&self) -> SelfCheckReport {
+        let mut report = SelfCheckReport::default();
+        if self.configs.get_roots().is_empty() {
+            report.warnings.push("No root is configured".to_owned());
+        }
+        for path in self.configs.get_paths() {
+            report.check_root(path);
+        }
+        if let Some(dir) = self.configs.get_cache_dir() {
+            report.check_cache_dir(dir);
+        }
+        if let Some(cache) = &self.cache {
+            match cache.bunch_read() {
+                Ok(_) => report.cache_readable = Some(true),
+                Err(error) if error.kind() == ErrorKind::NotFound => (),
+                Err(error) => {
+                    report.warnings.push(format!("Cache is invalid: {}", error));
+                    report.cache_readable = Some(false);
                 }
             }
         }
+        report
+    }
+
+    /// Walk the roots and replace the cache with all services found.
+    /// The cache is kept as is if the walk is cancelled or truncated
+    pub fn rebuild_cache(&self) -> io::Result<()> {
+        self.rebuild_cache_with_progress(|_| ())
+    }
+
+    /// Rebuild the cache like `rebuild_cache`, reporting the progress each time a root is walked
+    pub fn rebuild_cache_with_progress<P: FnMut(RebuildProgress)>(
+        &self,
+        on_progress: P,
+    ) -> io::Result<()> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return Ok(()),
+        };
+        let _rebuilding = self
+            .rebuild_lock
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let (services, empty, _) = self.walk_all(None, on_progress);
+        self.save_walked(cache, &services, empty.as_ref())
+    }
+
+    /// Walk the roots and replace the cache with all services found, reading again only
+    /// bundles changed since they are cached. The cache is rebuilt if it is unreadable
+    pub fn refresh_cache(&self) -> io::Result<()> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return Ok(()),
+        };
+        let _rebuilding = self
+            .rebuild_lock
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let previous = cache.bunch_read().ok().map(|services| {
+            services
+                .into_iter()
+                .map(|service| (service.id().to_path_buf(), service))
+                .collect::<HashMap<_, _>>()
+        });
+        let (services, empty, _) = self.walk_all(previous.as_ref(), |_| ());
+        self.save_walked(cache, &services, empty.as_ref())
     }
 
-    fn filter_output_path(&mut self, path: &Path, query: &str) {
-        let service = Service::new(path);
-        if matcher::match_query(query, &service.title) {
-            match serialize_to_string(&service) {
-                Ok(service) => {
-                    if let Err(error) = writeln!(self.output, "{}", service) {
-                        eprintln!("Failed to write: {}", error);
+    /// Refresh the cache every `interval` in a thread of the scope, so queries
+    /// always read a warm cache. Refreshing stops when the returned handle is dropped
+    pub fn spawn_refresher<'s>(
+        &'s self,
+        scope: &'s Scope<'s, '_>,
+        interval: Duration,
+    ) -> Refresher<'s>
+    where
+        W: Sync,
+    {
+        let (stop, stopped) = mpsc::channel();
+        let handle = scope.spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                match self.refresh_cache() {
+                    Err(error) if error.kind() != ErrorKind::Interrupted => {
+                        eprintln!("Failed to refresh cache: {}", error)
                     }
+                    _ => (),
                 }
-                Err(error) => eprintln!("Failed to serialize: {}", error),
             }
+        });
+        Refresher::new(stop, handle)
+    }
+
+    /// Walk the roots for all services, reusing the previous services of unchanged bundles
+    /// and skipping unchanged folders the last walk found empty, and report the progress
+    /// each time a root is walked. Folders found empty are returned too, unless the walk
+    /// is truncated, and so is the first root that cannot be read
+    fn walk_all<P: FnMut(RebuildProgress)>(
+        &self,
+        previous: Option<&HashMap<PathBuf, Service>>,
+        mut on_progress: P,
+    ) -> (Vec<Service>, Option<EmptyFolders>, Result<(), QueryError>) {
+        self.rebuilds.fetch_add(1, Ordering::Relaxed);
+        let truncations = self.truncations.load(Ordering::Relaxed);
+        let settings = self.walk_settings();
+        let empty = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.read_empty_folders().ok())
+            .filter(|empty| empty.settings() == settings);
+        let history = WalkHistory {
+            previous,
+            empty: empty.as_ref(),
+            walked: Some(RefCell::default()),
+        };
+        let mut services = Vec::new();
+        let pattern = Pattern::parse("");
+        let options = QueryOptions::default();
+        let roots = self.configs.get_roots();
+        let mut walked = Ok(());
+        for (index, root) in roots.iter().enumerate() {
+            let root_walked = self.walk_root(root, &pattern, &options, &history, &mut |service| {
+                services.push(service)
+            });
+            walked = walked.and(root_walked);
+            on_progress(RebuildProgress {
+                roots_walked: index + 1,
+                roots: roots.len(),
+                services: services.len(),
+            });
         }
+        let walked_folders = history.walked.unwrap_or_default().into_inner();
+        let empty = (self.truncations.load(Ordering::Relaxed) == truncations)
+            .then(|| EmptyFolders::from_walk(&walked_folders, settings));
+        (services, empty, walked)
     }
-}
 
-#[cfg(test)]
-mod query_test {
-    use std::path::PathBuf;
+    /// Hash the settings deciding which paths a walk finds, so empty folders
+    /// recorded with other settings are not skipped
+    fn walk_settings(&self) -> u64 {
+        let sorted = |paths: &HashSet<PathBuf>| paths.iter().cloned().collect::<BTreeSet<_>>();
+        let settings = format!(
+            "{:?}{:?}{:?}{:?}{:?}{:?}{}",
+            self.configs.get_paths(),
+            sorted(self.configs.get_ignore_paths()),
+            sorted(self.configs.get_ignore_overrides()),
+            self.configs.get_ignore_extensions(),
+            self.configs.get_checkers(),
+            self.configs.get_internal_helper_folders(),
+            self.checker.leaf_checkers(),
+        );
+        utils::stable_hash(settings)
+    }
 
-    use crate::query::query::QueryProcessor;
+    /// Save services and empty folders found by a walk to the cache, only if the walk completed.
+    /// Walks without empty folders are truncated, e.g. by a root timing out, and the cache
+    /// is kept as it is instead
+    fn save_walked(
+        &self,
+        cache: &CacheManager,
+        services: &[Service],
+        empty: Option<&EmptyFolders>,
+    ) -> io::Result<()> {
+        if self.cancellation.is_cancelled() {
+            return Err(Error::new(ErrorKind::Interrupted, "Walk cancelled"));
+        }
+        let empty = empty.ok_or_else(|| Error::new(ErrorKind::Interrupted, "Walk truncated"))?;
+        cache.bunch_save(services)?;
+        if let Err(error) = cache.save_empty_folders(empty) {
+            eprintln!("Failed to save empty folders: {}", error);
+        }
+        self.clear_recent_results();
+        Ok(())
+    }
 
-    type QP<'a, W> = QueryProcessor<'a, W>;
+    /// Drop results and outputs kept for recent queries, once the services they are made of change
+    fn clear_recent_results(&self) {
+        if let Ok(mut query_cache) = self.query_cache.lock() {
+            query_cache.clear();
+        }
+        if let Ok(mut output_cache) = self.output_cache.lock() {
+            output_cache.clear();
+        }
+    }
 
-    const APP_PATH: &str = "/System/Applications/Books.app";
-    const APP_FOLDER_PATH: &str = "/System/Applications";
+    /// Walk all roots, and fail with the first root that cannot be read
+    fn walk_roots<F: FnMut(Service)>(
+        &self,
+        pattern: &Pattern,
+        options: &QueryOptions,
+        on_service: &mut F,
+    ) -> Result<(), QueryError> {
+        let history = WalkHistory::default();
+        let mut walked = Ok(());
+        for root in self.configs.get_roots() {
+            let root_walked = self.walk_root(root, pattern, options, &history, on_service);
+            walked = walked.and(root_walked);
+        }
+        walked
+    }
 
-    #[test]
-    fn test_walk_dir_single() {
-        let settings = crate::configurator::get_content();
-        std::env::set_var("SETTINGS", settings);
-        let mut output = Vec::<u8>::new();
-        let mut processor = QP::new(&mut output);
-        let single_file = PathBuf::from(APP_PATH);
-        processor.walk_paths(&single_file, "book");
-        let string = String::from_utf8(output).expect("Failed to parse");
-        let expected = r#"{"title":"Books","subtitle":"/System/Applications/Books.app","id":"/System/Applications/Books.app"}
-"#;
-        assert_eq!(string, expected);
+    /// Walk the root, and fail if it cannot be read. Folders below it that cannot be read
+    /// are skipped
+    fn walk_root<F: FnMut(Service)>(
+        &self,
+        root: &Root,
+        pattern: &Pattern,
+        options: &QueryOptions,
+        history: &WalkHistory,
+        on_service: &mut F,
+    ) -> Result<(), QueryError> {
+        let profile = options.apply(self.configs.get_profile(root));
+        let scope = self.root_scope(root, &profile, options, history);
+        // A panic, e.g. from a custom checker, loses only the rest of this root
+        let walked = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.walk_paths(root.path(), &scope, pattern, &mut |service| {
+                on_service(labeled(root, service))
+            })
+        }));
+        self.report_walk(root, &scope, walked.is_err());
+        match walked {
+            Ok(Some(source)) => Err(QueryError::Io {
+                path: root.path().to_path_buf(),
+                source,
+            }),
+            _ => Ok(()),
+        }
     }
 
-    #[test]
-    fn test_walk_dir_all_apps_starts_with_a() {
-        let settings = crate::configurator::get_content();
-        std::env::set_var("SETTINGS", settings);
-        let mut output = Vec::new();
-        let mut processor = QP::new(&mut output);
-        let content = PathBuf::from(APP_FOLDER_PATH);
-        processor.walk_paths(&content, "a");
-        let string = String::from_utf8(output).expect("Failed to parse");
-        let lines = string.split("\n").collect::<Vec<_>>();
-        assert_eq!(lines.len(), 9);
+    /// Get the scope walking the root starts with
+    fn root_scope<'w>(
+        &self,
+        root: &Root,
+        profile: &'w Profile,
+        options: &QueryOptions,
+        history: &'w WalkHistory,
+    ) -> WalkScope<'w> {
+        let deadline = self
+            .configs
+            .get_per_root_timeout()
+            .map(|timeout| Instant::now() + timeout);
+        WalkScope::new(profile)
+            .with_device(self.root_device(root))
+            .with_deadline(deadline)
+            .with_history(history)
+            .with_recursive(root.is_recursive())
+            .with_enriched(options.enrich)
+            .with_depth_recorded(options.record_depth)
+    }
+
+    /// Count the walk of the root as truncated if it panicked or timed out
+    fn report_walk(&self, root: &Root, scope: &WalkScope, panicked: bool) {
+        if panicked {
+            eprintln!("Panicked walking root: {}", root.path().display());
+            self.truncations.fetch_add(1, Ordering::Relaxed);
+        } else if scope.is_expired() {
+            eprintln!("Timed out walking root: {}", root.path().display());
+            self.truncations.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Get the device walking should stay on, if enabled
+    fn root_device(&self, root: &Root) -> Option<u64> {
+        if self.configs.is_filesystem_kept() {
+            (self.device_id)(root.path())
+        } else {
+            None
+        }
+    }
+
+    /// Walk the root and send each service found into the channel, waiting while
+    /// the channel is full. Bundle infos are read on a thread off the async executor.
+    /// Fails if the receiver is dropped
+    #[cfg(feature = "async")]
+    pub async fn discover_into(
+        &self,
+        root: &Root,
+        sender: futures::channel::mpsc::Sender<Service>,
+    ) -> Result<(), futures::channel::mpsc::SendError> {
+        self.discover_with(root, &QueryOptions::default(), sender)
+            .await
+    }
+
+    /// Discover services of the root like `discover_into`, with checks overridden by the options
+    #[cfg(feature = "async")]
+    async fn discover_with(
+        &self,
+        root: &Root,
+        options: &QueryOptions,
+        mut sender: futures::channel::mpsc::Sender<Service>,
+    ) -> Result<(), futures::channel::mpsc::SendError> {
+        use futures::SinkExt;
+
+        let profile = options.apply(self.configs.get_profile(root));
+        let history = WalkHistory::default();
+        let scope = self.root_scope(root, &profile, options, &history);
+        let mut walk = Walk::new(root.path(), &scope);
+        let pool = BlockingPool::new(1);
+        loop {
+            // A panic, e.g. from a custom checker, loses only the rest of this root
+            let next = panic::catch_unwind(AssertUnwindSafe(|| self.next_bundle(&mut walk)));
+            let (path, index, depth) = match next {
+                Ok(Some(found)) => found,
+                Ok(None) => break,
+                Err(_) => {
+                    self.report_walk(root, &scope, true);
+                    return Ok(());
+                }
+            };
+            let service = match walk.scopes[index].unchanged(&path) {
+                Some(service) => service.clone(),
+                None if !walk.scopes[index].enriched => self.prepare_service(Service::bare(&path)),
+                None => {
+                    let bundle = path.clone();
+                    match pool.run(move || Service::new(bundle)).await {
+                        Some(service) => self.prepare_service(service),
+                        None => continue,
+                    }
+                }
+            };
+            let service = with_depth(&walk.scopes[index], service, depth);
+            sender.send(labeled(root, service)).await?
+        }
+        self.report_walk(root, &scope, false);
+        Ok(())
+    }
+
+    /// Read bundle infos of the paths into services, with at most the configured
+    /// number of reads at the same time on a pool of threads, off the async executor.
+    /// Services are in the order they are read
+    #[cfg(feature = "async")]
+    pub async fn enrich_services(&self, paths: Vec<PathBuf>) -> Vec<Service> {
+        use futures::StreamExt;
+
+        let pool = BlockingPool::new(self.configs.get_enrich_concurrency());
+        futures::stream::iter(paths)
+            .map(|path| pool.run(move || Service::new(path)))
+            .buffer_unordered(self.configs.get_enrich_concurrency())
+            .filter_map(|service| async { service })
+            .map(|service| self.prepare_service(service))
+            .collect()
+            .await
+    }
+
+    /// Query based on the request, and stream the matched services read from the cache
+    /// first, then what a fresh walk of the roots changed from them as a diff, e.g. to show
+    /// results at once and refine them after. The walk starts once the cached results are
+    /// taken from the stream, reading bundle infos on a thread off the async executor,
+    /// and the cache is left as it is. Results already walked afresh, e.g. because
+    /// there is no cache, are not walked again, so they change nothing
+    #[cfg(feature = "async")]
+    pub fn query_progressive<'q>(
+        &'q self,
+        req: &'q str,
+    ) -> impl futures::Stream<Item = ProgressiveResult> + 'q {
+        use futures::StreamExt;
+
+        futures::stream::once(async move { self.timed_services(req) }).flat_map(
+            move |(cached, origin, _)| {
+                let first = ProgressiveResult::Cached(cached.clone());
+                let changed = futures::stream::once(async move {
+                    let fresh = match origin {
+                        ResultOrigin::Walk | ResultOrigin::Rebuild => cached.clone(),
+                        _ => self.fresh_services(req).await,
+                    };
+                    ProgressiveResult::Changed(diff_results(&cached, &fresh))
+                });
+                futures::stream::once(async { first }).chain(changed)
+            },
+        )
+    }
+
+    /// Walk the roots for services matched by the request, bypassing the cache,
+    /// and arrange them. Bundle infos are read on a thread off the async executor
+    #[cfg(feature = "async")]
+    async fn fresh_services(&self, req: &str) -> Vec<Service> {
+        use futures::StreamExt;
+
+        let req = self.normalize_request(req);
+        let (text, options) = self.split_request(&req);
+        let pattern = self.parse_pattern(text);
+        let mut services = Vec::new();
+        for root in self.configs.get_roots() {
+            let (sender, receiver) = futures::channel::mpsc::channel(1);
+            let (_, found) = futures::join!(
+                self.discover_with(root, &options, sender),
+                receiver.collect::<Vec<_>>()
+            );
+            services.extend(found);
+        }
+        services.extend(self.explicit_services());
+        services.retain(|service| {
+            self.is_recent(service.id())
+                && options.keeps(service)
+                && self.is_matched(&pattern, service)
+        });
+        self.arrange_services(&mut services, &pattern);
+        services
+    }
+
+    /// Query based on the request, and return paths of matched services each
+    /// terminated by a NUL byte, e.g. for `xargs -0`. No matches are no bytes at all
+    pub fn query_paths_nul(&self, req: &str) -> Vec<u8> {
+        let mut paths = Vec::new();
+        for service in self.query_services(req) {
+            paths.extend_from_slice(service.id().as_os_str().as_bytes());
+            paths.push(b'\0');
+        }
+        paths
+    }
+
+    /// Query based on the request, and return services in the page
+    /// together with the total number of matched services
+    pub fn query_page(&self, req: &str, offset: usize, limit: usize) -> (Vec<Service>, usize) {
+        let services = self.query_services(req);
+        let total = services.len();
+        let page = services.into_iter().skip(offset).take(limit).collect();
+        (page, total)
+    }
+
+    /// Dedup matched services, and order them by their scores with the pattern,
+    /// then by ranking and pinning
+    fn arrange_services(&self, services: &mut Vec<Service>, pattern: &Pattern) {
+        self.arrange_scored(services, pattern.is_empty(), |service| {
+            self.best_match(pattern, service)
+                .map(|(_, score)| score.value)
+        });
+    }
+
+    /// Arrange services like `arrange_services`, with their scores given by `score`.
+    /// Services of a listing, matched by an empty request, are ordered by root priority first
+    fn arrange_scored<S: FnMut(&Service) -> Option<u32>>(
+        &self,
+        services: &mut Vec<Service>,
+        listing: bool,
+        mut score: S,
+    ) {
+        Self::dedup_services(
+            services,
+            self.configs.is_native_arch_preferred(),
+            self.configs.get_dedup_policy(),
+        );
+        let selections = self.selections();
+        let keys = services
+            .iter()
+            .zip(self.tie_keys(services))
+            .map(|(service, tie)| {
+                self.arrange_key(service, listing, &selections, score(service), tie)
+            })
+            .collect();
+        Self::sort_by_keys(services, keys);
+    }
+
+    /// Get where the service goes when arranged: pinned services first as they are configured,
+    /// then frequently and recently selected ones, then the ones scored higher, with
+    /// services under higher priority roots first among equally scored ones or in listings.
+    /// The configured tie break and source order decide between the rest
+    fn arrange_key(
+        &self,
+        service: &Service,
+        listing: bool,
+        selections: &HashMap<String, Selection>,
+        score: Option<u32>,
+        tie: TieKey,
+    ) -> ArrangeKey {
+        let pinned = self.configs.get_pinned_paths();
+        let bonus = match selections.is_empty() {
+            true => 0.0,
+            false => selections
+                .get(&service.dedup_key())
+                .map(|selection| selection.bonus(frecency::now()))
+                .unwrap_or_default(),
+        };
+        ArrangeKey {
+            pin: pinned
+                .iter()
+                .position(|path| path == service.id())
+                .unwrap_or(pinned.len()),
+            bonus,
+            priority: self.root_priority(service),
+            score,
+            listing,
+            tie,
+            cached: self.configs.get_source_order() == SourceOrder::UpdatedFirst
+                && service.is_cached(),
+        }
+    }
+
+    /// Get the selections ranking services, none if they cannot be read
+    fn selections(&self) -> HashMap<String, Selection> {
+        self.frecency.bunch_read().unwrap_or_else(|error| {
+            eprintln!("Failed to read selections: {}", error);
+            HashMap::new()
+        })
+    }
+
+    /// Check if arranging keeps equally scored services in the order they are found,
+    /// with no tie break, root priority, source order, selection or pin reordering them
+    fn keeps_found_order(&self) -> bool {
+        self.configs.get_tie_break() == TieBreak::None
+            && self.configs.get_source_order() == SourceOrder::CachedFirst
+            && self.configs.get_pinned_paths().is_empty()
+            && self
+                .configs
+                .get_roots()
+                .iter()
+                .all(|root| root.priority() == 0)
+            && self
+                .frecency
+                .bunch_read()
+                .is_ok_and(|selections| selections.is_empty())
+    }
+
+    /// Keep only the first one of arranged services with the same name, recording how many
+    /// of them there are in its metadata if more than one
+    fn collapse_duplicate_names(services: &mut Vec<Service>) {
+        let mut kept: Vec<(Service, usize)> = Vec::with_capacity(services.len());
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        for service in services.drain(..) {
+            match indices.entry(service.title.clone()) {
+                Entry::Occupied(entry) => kept[*entry.get()].1 += 1,
+                Entry::Vacant(entry) => {
+                    entry.insert(kept.len());
+                    kept.push((service, 1));
+                }
+            }
+        }
+        services.extend(kept.into_iter().map(|(service, count)| match count {
+            1 => service,
+            _ => service.with_metadata(COLLAPSED_KEY, count.to_string()),
+        }));
+    }
+
+    /// Keep only the preferred one of services sharing the same dedup key,
+    /// at the position of the first one found
+    fn dedup_services(services: &mut Vec<Service>, native_preferred: bool, policy: DedupPolicy) {
+        let mut kept: Vec<Service> = Vec::with_capacity(services.len());
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        for service in services.drain(..) {
+            match indices.entry(service.dedup_key()) {
+                Entry::Occupied(entry) => {
+                    let kept_service = &mut kept[*entry.get()];
+                    if Self::is_dedup_preferred(&service, kept_service, native_preferred, policy) {
+                        *kept_service = service;
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(kept.len());
+                    kept.push(service);
+                }
+            }
+        }
+        *services = kept;
+    }
+
+    /// Check if the service is kept over the kept one sharing its dedup key.
+    /// The policy decides between a cached and a freshly read service,
+    /// and the versions decide otherwise
+    fn is_dedup_preferred(
+        service: &Service,
+        kept: &Service,
+        native_preferred: bool,
+        policy: DedupPolicy,
+    ) -> bool {
+        match (policy, service.is_cached(), kept.is_cached()) {
+            (DedupPolicy::PreferUpdated, false, true)
+            | (DedupPolicy::PreferCached, true, false) => true,
+            (DedupPolicy::PreferUpdated, true, false)
+            | (DedupPolicy::PreferCached, false, true) => false,
+            _ => service.is_preferred_to(kept, native_preferred),
+        }
+    }
+
+    /// Get the keys of the configured tie break for the services in order,
+    /// read for all services before sorting
+    fn tie_keys(&self, services: &[Service]) -> Vec<TieKey> {
+        match self.configs.get_tie_break() {
+            TieBreak::None => services.iter().map(|_| TieKey::None).collect(),
+            TieBreak::LastUsed => self.map_concurrently(services, |service| {
+                TieKey::LastUsed(Reverse(service.last_used()))
+            }),
+            TieBreak::Size => self
+                .bundle_sizes(services)
+                .into_iter()
+                .map(|size| TieKey::Size(Reverse(size)))
+                .collect(),
+        }
+    }
+
+    /// Stably sort the services by the keys computed for them in order
+    fn sort_by_keys<K: Ord>(services: &mut Vec<Service>, keys: Vec<K>) {
+        let mut keyed = keys.into_iter().zip(services.drain(..)).collect::<Vec<_>>();
+        keyed.sort_by(|(left, _), (right, _)| left.cmp(right));
+        services.extend(keyed.into_iter().map(|(_, service)| service));
+    }
+
+    /// Get the priority of the innermost root containing the service, 0 if none does
+    fn root_priority(&self, service: &Service) -> i32 {
+        self.configs
+            .get_roots()
+            .iter()
+            .filter(|root| service.id().starts_with(root.path()))
+            .max_by_key(|root| root.path().components().count())
+            .map(Root::priority)
+            .unwrap_or_default()
+    }
+
+    /// Normalize the request, then replace misspelled terms with their corrections.
+    /// Terms are joined by single spaces if corrections are configured or whitespace is collapsed
+    fn normalize_request(&self, req: &str) -> String {
+        let req = self.normalizer.normalize(req.to_owned());
+        let corrections = self.configs.get_corrections();
+        if corrections.is_empty() && !self.configs.is_whitespace_collapsed() {
+            return req;
+        }
+        req.split_whitespace()
+            .map(|term| {
+                corrections
+                    .get(&term.to_lowercase())
+                    .map_or(term, String::as_str)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Split the trailing `.extension` term off the normalized request if bundles have
+    /// the extension, into the rest of the request and options keeping only those bundles.
+    /// If names leave out bundle extensions, so can the last term, e.g. `books.app`
+    fn split_request<'r>(&self, req: &'r str) -> (&'r str, QueryOptions) {
+        let trimmed = req.trim_end();
+        let (text, last) = match trimmed.rsplit_once('.') {
+            Some((name, _))
+                if self.configs.is_bundle_extension_stripped()
+                    && !name.is_empty()
+                    && !name.ends_with(char::is_whitespace) =>
+            {
+                trimmed.split_at(name.len())
+            }
+            _ => trimmed
+                .rsplit_once(char::is_whitespace)
+                .unwrap_or(("", trimmed)),
+        };
+        match last.strip_prefix('.') {
+            Some(extension) if self.checker.is_bundle_extension(extension) => {
+                let options = QueryOptions {
+                    extension_filter: Some(extension.to_owned()),
+                    ..QueryOptions::default()
+                };
+                (text.trim_end(), options)
+            }
+            _ => (req, QueryOptions::default()),
+        }
+    }
+
+    /// Split the trailing extension term off the request like `split_request`,
+    /// filtering by its extension on top of the options
+    fn split_request_with<'r>(
+        &self,
+        req: &'r str,
+        options: &QueryOptions,
+    ) -> (&'r str, QueryOptions) {
+        let (text, split) = self.split_request(req);
+        let options = QueryOptions {
+            extension_filter: split
+                .extension_filter
+                .or_else(|| options.extension_filter.clone()),
+            ..options.clone()
+        };
+        (text, options)
+    }
+
+    /// Parse the request into the components it would be matched by, without running it
+    pub fn parse_query(&self, raw: &str) -> Result<ParsedQuery, QueryParseError> {
+        let req = self.normalize_request(raw);
+        let (text, options) = self.split_request(&req);
+        Ok(ParsedQuery {
+            extension: options.extension_filter,
+            ..ParsedQuery::parse(text)?
+        })
+    }
+
+    /// Normalize the request, and parse it without the trailing extension term
+    fn request_pattern(&self, req: &str) -> Pattern {
+        let req = self.normalize_request(req);
+        self.parse_pattern(self.split_request(&req).0)
+    }
+
+    /// Parse the request into the pattern matching services, requests separated by `/`
+    /// into path fragments if enabled
+    fn parse_pattern(&self, req: &str) -> Pattern {
+        let fragments = self
+            .configs
+            .is_path_fragments_enabled()
+            .then(|| PathFragments::parse(req))
+            .flatten();
+        let pattern = match fragments {
+            Some(fragments) => Pattern::Path(fragments),
+            None => Pattern::parse(req)
+                .expand(self.configs.get_abbreviations())
+                .with_synonyms(self.configs.get_synonyms())
+                .with_typos(self.configs.get_max_edit_distance()),
+        };
+        match self.configs.is_first_char_anchored() {
+            true => pattern.anchored(),
+            false => pattern,
+        }
+    }
+
+    /// Get values of the configured fields of the service, with both the base and
+    /// the localized name for names, each followed by its ASCII transliteration
+    /// if non ASCII and enabled
+    fn match_targets<'s>(&self, service: &'s Service) -> Vec<(MatchField, Cow<'s, str>)> {
+        let mut targets = Vec::new();
+        let mut push = |field: MatchField, target: Cow<'s, str>| {
+            let transliterated = matches!(field, MatchField::Name | MatchField::LocalizedName)
+                && self.configs.is_names_transliterated()
+                && !target.is_ascii();
+            let transliterated = transliterated.then(|| deunicode::deunicode(&target));
+            targets.push((field, target));
+            if let Some(transliterated) = transliterated {
+                targets.push((field, Cow::Owned(transliterated)));
+            }
+        };
+        for field in self.configs.get_match_fields() {
+            if let Some(target) = service.field(*field) {
+                push(*field, target);
+            }
+            if *field == MatchField::Name {
+                if let Some(localized) = service.field(MatchField::LocalizedName) {
+                    push(MatchField::LocalizedName, localized);
+                }
+            }
+        }
+        targets
+    }
+
+    /// Get the match targets of the service for the pattern, with its full path
+    /// for patterns matching paths
+    fn pattern_targets<'s>(
+        &self,
+        pattern: &Pattern,
+        service: &'s Service,
+    ) -> Vec<(MatchField, Cow<'s, str>)> {
+        let mut targets = self.match_targets(service);
+        if pattern.is_path()
+            && !targets
+                .iter()
+                .any(|(field, _)| *field == MatchField::FullPath)
+        {
+            targets.extend(
+                service
+                    .field(MatchField::FullPath)
+                    .map(|path| (MatchField::FullPath, path)),
+            );
+        }
+        targets
+    }
+
+    /// Get the configured field of the service the pattern matches best, and its score
+    /// with the bonus for recently installed bundles
+    fn best_match(&self, pattern: &Pattern, service: &Service) -> Option<(MatchField, Score)> {
+        self.scored.fetch_add(1, Ordering::Relaxed);
+        let (field, score) = self
+            .pattern_targets(pattern, service)
+            .into_iter()
+            .filter_map(|(field, target)| {
+                Some((
+                    field,
+                    pattern.score(&target, self.configs.get_length_weight())?,
+                ))
+            })
+            .reduce(|best, matched| {
+                if matched.1.value > best.1.value {
+                    matched
+                } else {
+                    best
+                }
+            })?;
+        let weight = self.configs.get_recency_weight();
+        if weight == 0 {
+            return Some((field, score));
+        }
+        let age = (self.installed)(service).and_then(|installed| installed.elapsed().ok());
+        Some((field, score.with_recency_bonus(age, weight)))
+    }
+
+    /// Check if the pattern matches any of the configured fields of the service.
+    /// Empty patterns match every service without running the matcher
+    fn is_matched(&self, pattern: &Pattern, service: &Service) -> bool {
+        if pattern.is_empty() {
+            return true;
+        }
+        self.scored.fetch_add(1, Ordering::Relaxed);
+        self.pattern_targets(pattern, service)
+            .iter()
+            .any(|(_, target)| pattern.matches(target))
+    }
+
+    fn new_service(&self, path: &Path) -> Service {
+        self.prepare_service(Service::new(path))
+    }
+
+    /// Set the folder of a new service, and strip its version if configured
+    fn prepare_service(&self, service: Service) -> Service {
+        let parent = service.id().parent().map(Path::to_path_buf);
+        let service = service.with_parent(parent);
+        let service = if self.configs.is_versions_stripped() {
+            service.without_version()
+        } else {
+            service
+        };
+        if self.configs.is_bundle_extension_stripped() {
+            service
+        } else {
+            service.with_bundle_extension()
+        }
+    }
+
+    /// Walk below the entry and pass on all matched services. Returns the error reading
+    /// the entry, if it is a folder that cannot be read
+    fn walk_paths<P: AsRef<Path>, F: FnMut(Service)>(
+        &self,
+        entry: P,
+        scope: &WalkScope,
+        pattern: &Pattern,
+        on_service: &mut F,
+    ) -> Option<io::Error> {
+        let mut walk = Walk::new(entry.as_ref(), scope);
+        while let Some((path, index, depth)) = self.next_bundle(&mut walk) {
+            let scope = &walk.scopes[index];
+            let service = match scope.unchanged(&path) {
+                Some(service) => service.clone(),
+                None if scope.enriched => self.new_service(&path),
+                None => self.prepare_service(Service::bare(&path)),
+            };
+            let service = with_depth(scope, service, depth);
+            if self.is_matched(pattern, &service) {
+                on_service(service);
+            }
+        }
+        walk.error
+    }
+
+    /// Iterate through files and folders in the configured order until a bundle is found,
+    /// and get it with the index of its scope and its depth below the entry. Folders on other
+    /// devices than the device of the scope are skipped if it is given, and so are paths
+    /// matching the ignore files of the folders containing them. Folders below the entry
+    /// are not walked into unless the scope is recursive
+    fn next_bundle(&self, walk: &mut Walk) -> Option<(PathBuf, usize, usize)> {
+        let order = self.configs.get_traversal_order();
+        let (device, recursive) = (walk.scope().device, walk.scope().recursive);
+        while !self.cancellation.is_cancelled() && !walk.scope().is_expired() {
+            let (entry, index, depth) = match order {
+                TraversalOrder::DepthFirst => walk.entries.pop_back(),
+                TraversalOrder::BreadthFirst => walk.entries.pop_front(),
+            }?;
+            let scopes = &mut walk.scopes;
+            let occupy_parent = |scopes: &[WalkScope]| {
+                if let Some(parent) = entry.parent().filter(|_| entry.is_dir()) {
+                    scopes[index].occupy(parent);
+                }
+            };
+            match self.checker.check(&entry, scopes[index].profile) {
+                // Folders not walked into may hold bundles other settings find
+                Outcome::UnwantedPath if scopes[index].walked.is_some() => occupy_parent(scopes),
+                Outcome::UnwantedPath => (),
+                Outcome::BundlePath => {
+                    self.discovered.fetch_add(1, Ordering::Relaxed);
+                    return Some((entry, index, depth));
+                }
+                Outcome::NormalPath => {
+                    if (device.is_some() && (self.device_id)(&entry) != device)
+                        || (!recursive && entry != walk.entry)
+                    {
+                        occupy_parent(scopes);
+                        continue;
+                    }
+                    if scopes[index].skips(&entry) {
+                        continue;
+                    }
+                    scopes[index].record(&entry);
+                    let index = match scopes[index].enter(&entry) {
+                        Some(inner) => {
+                            scopes.push(inner);
+                            scopes.len() - 1
+                        }
+                        None => index,
+                    };
+                    let (paths, read) = self.read_folder(&entry);
+                    let unreadable = match read {
+                        Err(error) if error.kind() != ErrorKind::NotADirectory => {
+                            if entry == walk.entry {
+                                walk.error = Some(error);
+                            }
+                            true
+                        }
+                        _ => false,
+                    };
+                    let scope = &scopes[index];
+                    if unreadable
+                        || paths.iter().any(|path| {
+                            self.checker.is_bundle_like(path)
+                                || (scope.is_ignored(path) && path.is_dir())
+                        })
+                    {
+                        scope.occupy(&entry);
+                    }
+                    let paths = paths
+                        .into_iter()
+                        .filter(|path| !scope.is_ignored(path))
+                        .map(|path| (path, index, depth + 1));
+                    match order {
+                        TraversalOrder::DepthFirst => walk
+                            .entries
+                            .extend(paths.collect::<Vec<_>>().into_iter().rev()),
+                        TraversalOrder::BreadthFirst => walk.entries.extend(paths),
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Read paths in the folder, at most the configured number of them,
+    /// along with the error that stopped reading, if any
+    fn read_folder(&self, folder: &Path) -> (Vec<PathBuf>, io::Result<()>) {
+        let read_folder = match self.fs.read_dir(folder) {
+            Ok(read_folder) => read_folder,
+            Err(err) => {
+                eprintln!("Read folder error: {}", err);
+                return (Vec::new(), Err(err));
+            }
+        };
+        let limit = self
+            .configs
+            .get_max_entries_per_folder()
+            .unwrap_or(usize::MAX);
+        let mut paths = Vec::new();
+        for path in read_folder {
+            let path = match path {
+                Ok(path) => path,
+                Err(error) => return (paths, Err(error)),
+            };
+            if paths.len() == limit {
+                eprintln!(
+                    "Truncated folder after {} entries: {}",
+                    limit,
+                    folder.display()
+                );
+                self.truncations.fetch_add(1, Ordering::Relaxed);
+                break;
+            }
+            paths.push(path);
+        }
+        (paths, Ok(()))
+    }
+
+    /// Query based on the request, and write each serialized service to the writer
+    /// as soon as it is serialized, then flush the writer. Services are written
+    /// as `query` writes them after a fresh walk, which reads bundle infos on a thread
+    /// off the async executor like `query_progressive`, bypassing the cache
+    #[cfg(feature = "async")]
+    pub async fn write_results<A: futures::AsyncWrite + Unpin>(
+        &self,
+        req: &str,
+        mut writer: A,
+    ) -> io::Result<()> {
+        use futures::AsyncWriteExt;
+
+        let services = self.fresh_services(req).await;
+        for line in self.serialized_lines(&services) {
+            writer.write_all(line.as_bytes()).await?;
+        }
+        writer.flush().await
+    }
+
+    /// Serialize the services as displayed, one per line
+    fn serialize_services(&self, services: &[Service]) -> Vec<u8> {
+        self.serialized_lines(services)
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    /// Serialize each service as displayed into a line. Services are left out from the
+    /// first one that would make the output longer than the configured maximum
+    fn serialized_lines<'s>(
+        &'s self,
+        services: &'s [Service],
+    ) -> impl Iterator<Item = String> + 's {
+        let limit = self.configs.get_max_output_bytes().unwrap_or(usize::MAX);
+        let mut written = 0;
+        services
+            .iter()
+            .filter_map(
+                move |service| match serialize_to_string(&self.displayed(service)) {
+                    Ok(line) => Some(line),
+                    Err(error) => {
+                        eprintln!("Failed to serialize: {}", error);
+                        None
+                    }
+                },
+            )
+            .map_while(move |mut line| {
+                if written + line.len() + 1 > limit {
+                    eprintln!("Truncated output after {} bytes", written);
+                    return None;
+                }
+                written += line.len() + 1;
+                line.push('\n');
+                Some(line)
+            })
+    }
+}
+
+/// Read all cached services, through a memory map of the cache file if enabled
+fn read_cached(cache: &CacheManager) -> io::Result<Vec<Service>> {
+    #[cfg(feature = "mmap")]
+    {
+        let mut services = Vec::new();
+        cache.mapped_read(|service| services.push(service))?;
+        Ok(services)
+    }
+    #[cfg(not(feature = "mmap"))]
+    cache.bunch_read()
+}
+
+/// Label the service with the label of the root it is found in, if any
+fn labeled(root: &Root, service: Service) -> Service {
+    match root.label() {
+        Some(label) => service.with_metadata(LABEL_KEY, label),
+        None => service,
+    }
+}
+
+/// Record the depth of the service below its root, if the scope records depths
+fn with_depth(scope: &WalkScope, service: Service, depth: usize) -> Service {
+    match scope.depth_recorded {
+        true => service.with_metadata(DEPTH_KEY, depth.to_string()),
+        false => service,
+    }
+}
+
+/// Services loaded by a query processor, to be matched against many requests
+pub struct ServiceIndex<'p, 'a, W: Write> {
+    processor: &'p QueryProcessor<'a, W>,
+    services: Vec<Service>,
+    tokens: TokenIndex,
+    /// Match targets of each service prepared for the matcher
+    targets: Vec<Vec<Target>>,
+}
+
+impl<'p, 'a, W: Write> ServiceIndex<'p, 'a, W> {
+    /// Match the request against the loaded services, and return all matched services
+    pub fn query(&self, req: &str) -> Vec<Service> {
+        let processor = self.processor;
+        let req = processor.normalize_request(req);
+        let (text, options) = processor.split_request(&req);
+        let pattern = processor.parse_pattern(text);
+        let prefilter = pattern.prefilter();
+        let is_matched = |position: &usize| {
+            let service = &self.services[*position];
+            self.is_matched(&pattern, &prefilter, service, &self.targets[*position])
+                && options.keeps(service)
+        };
+        let candidates = match self.tokens.candidates(&pattern) {
+            Some(candidates) => candidates.into_iter().collect(),
+            None => (0..self.services.len()).collect::<Vec<_>>(),
+        };
+        let mut services = candidates
+            .into_iter()
+            .filter(is_matched)
+            .map(|position| self.services[position].clone())
+            .collect();
+        processor.arrange_services(&mut services, &pattern);
+        services
+    }
+
+    /// Match the pattern against the prepared targets, skipping services their folded
+    /// targets rule out, or against the service as queries do for patterns needing more
+    fn is_matched(
+        &self,
+        pattern: &Pattern,
+        prefilter: &Prefilter,
+        service: &Service,
+        targets: &[Target],
+    ) -> bool {
+        if pattern.is_empty() || pattern.is_path() {
+            return self.processor.is_matched(pattern, service);
+        }
+        if !targets
+            .iter()
+            .any(|target| prefilter.admits(target.folded()))
+        {
+            return false;
+        }
+        self.processor.scored.fetch_add(1, Ordering::Relaxed);
+        targets.iter().any(|target| pattern.matches_target(target))
+    }
+
+    /// Get the number of loaded services
+    pub fn len(&self) -> usize {
+        self.services.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.services.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod query_test {
+    use std::cmp::Reverse;
+    use std::fs::{File, FileTimes};
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use serde_json::json;
+
+    use crate::configurator::{get_content_with, Configs, DedupPolicy, MatchField, Profile};
+    use crate::query::cache::MemoryStore;
+    use crate::query::cancellation::CancellationToken;
+    use crate::query::checker::{Classification, QUARANTINE_ATTRIBUTE};
+    use crate::query::diagnostics::ResultOrigin;
+    use crate::query::error::QueryError;
+    use crate::query::export::ExportFormat;
+    use crate::query::frecency::FrecencyStore;
+    use crate::query::fs::{Entries, Fs, StdFs};
+    use crate::query::matcher::{Pattern, EXACT_SCORE};
+    use crate::query::metrics::Metrics;
+    use crate::query::options::{QueryOptions, COLLAPSED_KEY};
+    use crate::query::parsed::QueryParseError;
+    use crate::query::progress::RebuildProgress;
+    use crate::query::query::QueryProcessor;
+    use crate::query::registry::{CheckerMode, CheckerRegistry, LeafChecker};
+    use crate::query::service::{RankedService, Service, DEPTH_KEY};
+    use crate::query::walk::{WalkScope, IGNORE_FILE};
+    use crate::utils::fixture;
+    use crate::utils::serde::deserialize_from_bytes;
+
+    type QP<'a, W> = QueryProcessor<'a, W>;
+
+    const APP_PATH: &str = "/System/Applications/Books.app";
+    const APP_FOLDER_PATH: &str = "/System/Applications";
+
+    #[test]
+    fn test_walk_dir_single() {
+        let settings = crate::configurator::get_content();
+        std::env::set_var("SETTINGS", settings);
+        let processor = QP::new(Vec::new());
+        let single_file = PathBuf::from(APP_PATH);
+        let mut services = Vec::new();
+        let profile = Profile::default();
+        let pattern = Pattern::parse("book");
+        processor.walk_paths(
+            &single_file,
+            &WalkScope::new(&profile),
+            &pattern,
+            &mut |service| services.push(service),
+        );
+        let output = processor.serialize_services(&services);
+        let string = String::from_utf8(output).expect("Failed to parse");
+        let expected = r#"{"title":"Books","subtitle":"/System/Applications/Books.app","id":"/System/Applications/Books.app","parent":"/System/Applications"}
+"#;
+        assert_eq!(string, expected);
+    }
+
+    #[test]
+    fn test_walk_dir_all_apps_starts_with_a() {
+        let settings = crate::configurator::get_content();
+        std::env::set_var("SETTINGS", settings);
+        let processor = QP::new(Vec::new());
+        let content = PathBuf::from(APP_FOLDER_PATH);
+        let mut services = Vec::new();
+        let profile = Profile::default();
+        let pattern = Pattern::parse("a");
+        processor.walk_paths(
+            &content,
+            &WalkScope::new(&profile),
+            &pattern,
+            &mut |service| services.push(service),
+        );
+        let output = processor.serialize_services(&services);
+        let string = String::from_utf8(output).expect("Failed to parse");
+        let lines = string.split("\n").collect::<Vec<_>>();
+        assert_eq!(lines.len(), 9);
+    }
+
+    #[test]
+    fn test_query_page() {
+        let dir = fixture::temp_dir("query_page");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app", "Arrow.app", "Boat.app"]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let (page, total) = processor.query_page("a", 1, 1);
+        assert_eq!(total, processor.query_services("a").len());
+        assert_eq!(total, 3);
+        assert_eq!(page.len(), 1);
+    }
+
+    #[test]
+    fn test_query_pinned_first() {
+        let dir = fixture::temp_dir("query_pinned_first");
+        fixture::create_bundles(&dir, &["Alpha.app", "Zeta Archive.app", "Boat.app"]);
+        let pinned = dir.join("Zeta Archive.app");
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["pinnedApps"]["value"] = json!([pinned]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("a");
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[0].id(), pinned);
+        assert!(processor
+            .query_services("bo")
+            .iter()
+            .all(|s| s.id() != pinned));
+    }
+
+    #[test]
+    fn test_query_streaming() {
+        let dir = fixture::temp_dir("query_streaming");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app", "Boat.app"]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let mut count = 0;
+        processor.query_streaming("a", |_| count += 1);
+        assert_eq!(count, processor.query_services("a").len());
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_query_root_profiles() {
+        let dir = fixture::temp_dir("query_root_profiles");
+        fixture::create_bundles(&dir, &["real/Books.app", "permissive", "strict"]);
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("permissive/link")).unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("strict/link")).unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([
+                {"path": dir.join("permissive"), "profile": "permissive"},
+                dir.join("strict"),
+            ]);
+            value["internal"]["profiles"] = json!({"permissive": {"checkSymlink": false}});
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("books");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].id(), dir.join("permissive/link/Books.app"));
+    }
+
+    #[test]
+    fn test_query_cached() {
+        let dir = fixture::temp_dir("query_cached");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "apps/Boat.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("a").len(), 1);
+        fixture::create_bundles(&dir, &["apps/Anchor.app"]);
+        assert_eq!(processor.query_services("a").len(), 1);
+        assert_eq!(processor.query_services("").len(), 2);
+    }
+
+    #[test]
+    fn test_query_abbreviation() {
+        let dir = fixture::temp_dir("query_abbreviation");
+        fixture::create_bundles(&dir, &["Google Chrome.app", "Books.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["abbreviations"]["value"] = json!({"browser": ["Google Chrome"]});
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("browser");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Google Chrome");
+    }
+
+    #[test]
+    fn test_query_dedup() {
+        let dir = fixture::temp_dir("query_dedup");
+        fixture::create_bundles(&dir, &["apps/Books.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps"), dir.join("apps/Books.app")]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("books").len(), 1);
+    }
+
+    #[test]
+    fn test_query_explicit_services() {
+        let dir = fixture::temp_dir("query_explicit_services");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "elsewhere/Anchor.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["configurable"]["explicitServices"]["value"] = json!([
+                dir.join("elsewhere/Anchor.app"),
+                dir.join("elsewhere/Missing.app"),
+            ]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("a");
+        assert_eq!(services.len(), 2);
+        assert!(services
+            .iter()
+            .any(|s| s.id() == dir.join("elsewhere/Anchor.app")));
+    }
+
+    #[test]
+    fn test_query_cache() {
+        let dir = fixture::temp_dir("query_cache");
+        fixture::create_bundles(&dir, &["Atlas.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["queryCacheSize"] = json!(2);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("a").len(), 1);
+        fixture::create_bundles(&dir, &["Anchor.app"]);
+        assert_eq!(processor.query_services("a").len(), 1);
+        assert_eq!(processor.query_services("an").len(), 1);
+    }
+
+    #[test]
+    fn test_dedup_keeps_newest() {
+        let dir = fixture::temp_dir("query_dedup_keeps_newest");
+        let old = dir.join("Applications/Xcode.app");
+        let new = dir.join("Users/Applications/Xcode.app");
+        let id = ("CFBundleIdentifier", "com.apple.dt.Xcode");
+        fixture::create_info_plist(&old, &[id, ("CFBundleShortVersionString", "9.4")]);
+        fixture::create_info_plist(&new, &[id, ("CFBundleShortVersionString", "14.3")]);
+        let mut services = vec![Service::new(&old), Service::new(&new)];
+        QP::<Vec<u8>>::dedup_services(&mut services, false, DedupPolicy::default());
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].id(), new);
+        assert_eq!(services[0].version(), Some("14.3"));
+    }
+
+    #[test]
+    fn test_dedup_prefers_updated() {
+        let dir = fixture::temp_dir("query_dedup_prefers_updated");
+        let bundle = dir.join("apps/Xcode.app");
+        let id = ("CFBundleIdentifier", "com.apple.dt.Xcode");
+        fixture::create_info_plist(&bundle, &[id, ("CFBundleShortVersionString", "14.3")]);
+        let content = |policy: &str| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir.join("apps")]);
+                value["internal"]["cacheDir"] = json!(dir.join("cache"));
+                value["configurable"]["explicitServices"]["value"] = json!([bundle]);
+                value["configurable"]["dedupPolicy"]["value"] = json!(policy);
+            })
+        };
+        let configs = Configs::from(content("preferUpdated")).expect("Invalid settings");
+        QP::with_configs(&configs, Vec::new())
+            .rebuild_cache()
+            .expect("Failed to build cache");
+        fixture::create_info_plist(&bundle, &[id, ("CFBundleShortVersionString", "14.2")]);
+        let services = QP::with_configs(&configs, Vec::new()).query_services("xcode");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].version(), Some("14.2"));
+        assert!(!services[0].is_cached());
+        for policy in ["preferCached", "preferNewest"] {
+            let configs = Configs::from(content(policy)).expect("Invalid settings");
+            let services = QP::with_configs(&configs, Vec::new()).query_services("xcode");
+            assert_eq!(services[0].version(), Some("14.3"));
+        }
+    }
+
+    #[test]
+    fn test_source_order() {
+        let dir = fixture::temp_dir("query_source_order");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "extra/Anvil.app"]);
+        let content = |order: &str| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir.join("apps")]);
+                value["internal"]["cacheDir"] = json!(dir.join("cache"));
+                value["configurable"]["explicitServices"]["value"] =
+                    json!([dir.join("extra/Anvil.app")]);
+                value["configurable"]["sourceOrder"]["value"] = json!(order);
+            })
+        };
+        let titles = |order: &str| {
+            let configs = Configs::from(content(order)).expect("Invalid settings");
+            let processor = QP::with_configs(&configs, Vec::new());
+            processor.rebuild_cache().expect("Failed to build cache");
+            processor
+                .query_services("a")
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles("cachedFirst"), ["Atlas", "Anvil"]);
+        assert_eq!(titles("updatedFirst"), ["Anvil", "Atlas"]);
+    }
+
+    #[test]
+    fn test_dedup_prefers_native_arch() {
+        let dir = fixture::temp_dir("query_dedup_prefers_native_arch");
+        let marker = if cfg!(target_arch = "aarch64") {
+            "Intel"
+        } else {
+            "arm64"
+        };
+        let foreign = dir.join(format!("Applications/Zoom ({}).app", marker));
+        let native = dir.join("Applications/Zoom.app");
+        let id = ("CFBundleIdentifier", "us.zoom.xos");
+        fixture::create_info_plist(&foreign, &[id, ("CFBundleShortVersionString", "5.1")]);
+        fixture::create_info_plist(&native, &[id, ("CFBundleShortVersionString", "5.0")]);
+        let mut services = vec![Service::new(&foreign), Service::new(&native)];
+        QP::<Vec<u8>>::dedup_services(&mut services, true, DedupPolicy::default());
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].id(), native);
+        let mut services = vec![Service::new(&native), Service::new(&foreign)];
+        QP::<Vec<u8>>::dedup_services(&mut services, false, DedupPolicy::default());
+        assert_eq!(services[0].id(), foreign);
+    }
+
+    #[test]
+    fn test_query_match_bundle_id() {
+        let dir = fixture::temp_dir("query_match_bundle_id");
+        fixture::create_bundles(&dir, &["Calculator.app"]);
+        let id = ("CFBundleIdentifier", "com.apple.calculator");
+        fixture::create_info_plist(dir.join("Calculator.app"), &[id]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["matchFields"]["value"] = json!(["name", "bundleId"]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("com.apple.calculator");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Calculator");
+        assert_eq!(processor.query_services("calc").len(), 1);
+    }
+
+    #[test]
+    fn test_query_match_executable() {
+        let dir = fixture::temp_dir("query_match_executable");
+        fixture::create_info_plist(dir.join("Foo.app"), &[("CFBundleExecutable", "bar")]);
+        let content = |fields: serde_json::Value| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["internal"]["cacheDir"] = json!(null);
+                value["configurable"]["matchFields"]["value"] = fields;
+            })
+        };
+        let configs = Configs::from(content(json!(["name", "executable"]))).unwrap();
+        let processor = QP::with_configs(&configs, Vec::new());
+        let ranked = processor.query_ranked("bar");
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].service.title, "Foo");
+        assert_eq!(ranked[0].service.executable(), Some("bar"));
+        assert_eq!(ranked[0].matched_field, MatchField::Executable);
+        let configs = Configs::from(content(json!(["name"]))).unwrap();
+        assert!(QP::with_configs(&configs, Vec::new())
+            .query_services("bar")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_query_separators() {
+        let dir = fixture::temp_dir("query_separators");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "App-Store.app",
+                "App_Store.app",
+                "App Store.app",
+                "Apps.app",
+            ],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        for req in ["app store", "app-store", r#""app store""#, "as"] {
+            let mut titles = processor
+                .query_services(req)
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>();
+            titles.sort();
+            assert_eq!(titles, ["App Store", "App-Store", "App_Store"], "{}", req);
+        }
+    }
+
+    #[test]
+    fn test_query_match_name_only() {
+        let dir = fixture::temp_dir("query_match_name_only");
+        fixture::create_bundles(&dir, &["Calculator.app"]);
+        let id = ("CFBundleIdentifier", "com.apple.calculator");
+        fixture::create_info_plist(dir.join("Calculator.app"), &[id]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert!(processor.query_services("com.apple.calculator").is_empty());
+    }
+
+    #[test]
+    fn test_record_selection() {
+        let dir = fixture::temp_dir("query_record_selection");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app"]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("a");
+        let selected = services.last().expect("No service found").clone();
+        assert_eq!(processor.rank_bonus(&selected), 0.0);
+        processor
+            .record_selection(&selected)
+            .expect("Failed to record");
+        assert!(processor.rank_bonus(&selected) > 0.0);
+        let services = processor.query_services("a");
+        assert_eq!(services[0].id(), selected.id());
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let configs = Configs::from(get_content_with(|_| {})).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let parsed = processor
+            .parse_query("visual  -code stu*io .app")
+            .expect("Failed to parse");
+        assert_eq!(parsed.terms, ["visual", "stu*io"]);
+        assert_eq!(parsed.excluded, ["code"]);
+        assert_eq!(parsed.extension.as_deref(), Some("app"));
+        let parsed = processor
+            .parse_query("\"visual studio\" .prefPane")
+            .expect("Failed to parse");
+        assert_eq!(parsed.literal.as_deref(), Some("visual studio"));
+        assert_eq!(parsed.extension.as_deref(), Some("prefPane"));
+        let error = processor.parse_query("\"visual studio .app").unwrap_err();
+        assert_eq!(error, QueryParseError::UnterminatedQuote);
+    }
+
+    #[test]
+    fn test_query_alternatives() {
+        let dir = fixture::temp_dir("query_alternatives");
+        fixture::create_bundles(&dir, &["Books.app", "Music.app", "Maps.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let titles = |req: &str| {
+            let mut titles = processor
+                .query_services(req)
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>();
+            titles.sort();
+            titles
+        };
+        assert_eq!(titles("books OR music"), ["Books", "Music"]);
+        assert_eq!(titles("books OR zzz"), ["Books"]);
+        let parsed = processor
+            .parse_query("books | music")
+            .expect("Failed to parse");
+        assert_eq!(parsed.alternatives[0].terms, ["music"]);
+    }
+
+    #[test]
+    fn test_query_recent() {
+        let dir = fixture::temp_dir("query_recent");
+        fixture::create_bundles(&dir, &["Atlas.app", "Books.app", "Maps.app", "Notes.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let mut processor = QP::with_configs(&configs, Vec::new());
+        assert!(processor.query_recent(10).is_empty());
+        processor.frecency = FrecencyStore::new(MemoryStore::default());
+        let selections = [
+            ("Maps.app", 100),
+            ("Atlas.app", 300),
+            ("Gone.app", 400),
+            ("Notes.app", 200),
+        ];
+        for (bundle, selected) in selections {
+            let key = Service::new(dir.join(bundle)).dedup_key();
+            processor.frecency.record(key, selected).unwrap();
+        }
+        let titles = |limit: usize| {
+            processor
+                .query_recent(limit)
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(10), ["Atlas", "Notes", "Maps"]);
+        assert_eq!(titles(2), ["Atlas", "Notes"]);
+    }
+
+    #[test]
+    fn test_query_stay_on_filesystem() {
+        let dir = fixture::temp_dir("query_stay_on_filesystem");
+        fixture::create_bundles(&dir, &["Atlas.app", "mount/Anchor.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["stayOnFilesystem"]["value"] = json!(true);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let mut processor = QP::with_configs(&configs, Vec::new());
+        processor.device_id = |path| Some(if path.ends_with("mount") { 2 } else { 1 });
+        let services = processor.query_services("a");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Atlas");
+    }
+
+    #[test]
+    fn test_query_cross_filesystem() {
+        let dir = fixture::temp_dir("query_cross_filesystem");
+        fixture::create_bundles(&dir, &["Atlas.app", "mount/Anchor.app"]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let mut processor = QP::with_configs(&configs, Vec::new());
+        processor.device_id = |path| Some(if path.ends_with("mount") { 2 } else { 1 });
+        assert_eq!(processor.query_services("a").len(), 2);
+    }
+
+    #[test]
+    fn test_query_ranked() {
+        let dir = fixture::temp_dir("query_ranked");
+        fixture::create_bundles(&dir, &["Calculator.app", "Books.app"]);
+        let id = ("CFBundleIdentifier", "com.apple.calculator");
+        fixture::create_info_plist(dir.join("Calculator.app"), &[id]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["matchFields"]["value"] = json!(["name", "bundleId"]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let ranked = processor.query_ranked("book");
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].service.title, "Books");
+        assert_eq!(ranked[0].matched_field, MatchField::Name);
+        let score = Pattern::parse("book")
+            .score("Books", configs.get_length_weight())
+            .expect("Not matched");
+        assert_eq!(ranked[0].score, score.value);
+        assert_eq!(ranked[0].ranges, score.ranges);
+        let ranked = processor.query_ranked("com.apple");
+        assert_eq!(ranked[0].matched_field, MatchField::BundleId);
+    }
+
+    #[test]
+    fn test_query_ranked_normalized() {
+        let dir = fixture::temp_dir("query_ranked_normalized");
+        fixture::create_bundles(&dir, &["Visual Studio Code.app", "Vim.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let normalized = |req: &str| processor.query_ranked(req)[0].normalized_score;
+        assert_eq!(normalized("visual studio code"), 1.0);
+        assert_eq!(normalized("vim"), 1.0);
+        let scattered = normalized("vsc");
+        assert!(scattered > 0.0 && scattered < 0.5, "{}", scattered);
+        assert!(normalized("visual") > scattered);
+        assert!(normalized("visual") < 1.0);
+    }
+
+    #[test]
+    fn test_query_json_ranges() {
+        let dir = fixture::temp_dir("query_json_ranges");
+        fixture::create_bundles(
+            &dir,
+            &["Éclair Notes.app", "\u{1112}\u{1161}\u{11AB} Notes.app"],
+        );
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let output = processor
+            .query_json("notes", true)
+            .expect("Failed to serialize");
+        let results: serde_json::Value = serde_json::from_str(&output).expect("Invalid JSON");
+        let ranges = |title: &str| {
+            results
+                .as_array()
+                .expect("Not an array")
+                .iter()
+                .find(|result| result["title"] == title)
+                .map(|result| result["matchRanges"].clone())
+        };
+        assert_eq!(ranges("Éclair Notes"), Some(json!([[7, 12]])));
+        assert_eq!(
+            ranges("\u{1112}\u{1161}\u{11AB} Notes"),
+            Some(json!([[2, 7]]))
+        );
+        let output = processor
+            .query_json("notes", false)
+            .expect("Failed to serialize");
+        let results: serde_json::Value = serde_json::from_str(&output).expect("Invalid JSON");
+        assert!(results[0].get("matchRanges").is_none());
+    }
+
+    #[test]
+    fn test_query_unwritable_cache() {
+        let dir = fixture::temp_dir("query_unwritable_cache");
+        fixture::create_bundles(&dir, &["apps/Books.app"]);
+        std::fs::write(dir.join("cache"), "").unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache/services"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("book").len(), 1);
+        assert!(processor.cache.as_ref().unwrap().is_disabled());
+        let diagnostics = processor.query_diagnosed("boo");
+        assert_eq!(diagnostics.served_from, ResultOrigin::Walk);
+        assert_eq!(diagnostics.services.len(), 1);
+        assert_eq!(processor.rebuilds.load(Ordering::Relaxed), 1);
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(
+            processor.try_query("book").expect("Failed to query").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_query_diagnosed() {
+        let dir = fixture::temp_dir("query_diagnosed");
+        fixture::create_bundles(&dir, &["apps/Books.app"]);
+        let content = |cache_dir: Option<PathBuf>| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir.join("apps")]);
+                value["internal"]["cacheDir"] = json!(cache_dir);
+                value["internal"]["queryCacheSize"] = json!(2);
+            })
+        };
+        let configs = Configs::from(content(Some(dir.join("cache")))).expect("Invalid settings");
+        let diagnostics = QP::with_configs(&configs, Vec::new()).query_diagnosed("book");
+        assert_eq!(diagnostics.served_from, ResultOrigin::Rebuild);
+        assert_eq!(diagnostics.services[0].service.title, "Books");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let diagnostics = processor.query_diagnosed("book");
+        assert_eq!(diagnostics.served_from, ResultOrigin::Cache);
+        assert_eq!(diagnostics.services.len(), 1);
+        let diagnostics = processor.query_diagnosed("book");
+        assert_eq!(diagnostics.served_from, ResultOrigin::RecentResults);
+        let configs = Configs::from(content(None)).expect("Invalid settings");
+        let diagnostics = QP::with_configs(&configs, Vec::new()).query_diagnosed("book");
+        assert_eq!(diagnostics.served_from, ResultOrigin::Walk);
+    }
+
+    #[test]
+    fn test_query_diagnosed_mixed() {
+        let dir = fixture::temp_dir("query_diagnosed_mixed");
+        fixture::create_bundles(&dir, &["apps/Books.app", "apps/Atlas.app", "Bookshelf.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["explicitServices"]["value"] = json!([dir.join("Bookshelf.app")]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let diagnostics = processor.query_diagnosed("book");
+        assert_eq!(diagnostics.served_from, ResultOrigin::Mixed);
+        assert_eq!(diagnostics.services.len(), 2);
+        let diagnostics = processor.query_diagnosed("atlas");
+        assert_eq!(diagnostics.served_from, ResultOrigin::Walk);
+
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["pathFallback"]["value"] = json!(true);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let diagnostics = processor.query_diagnosed("apps");
+        assert_eq!(diagnostics.served_from, ResultOrigin::Mixed);
+        assert_eq!(processor.query_services("apps").len(), 2);
+    }
+
+    #[test]
+    fn test_export_json() {
+        let dir = fixture::temp_dir("query_export_json");
+        fixture::create_bundles(&dir, &["apps/Atlas.app"]);
+        let id = ("CFBundleIdentifier", "com.apple.iBooksX");
+        fixture::create_info_plist(dir.join("apps/Books.app"), &[id]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let exported = dir.join("services.json");
+        processor
+            .export(&exported, ExportFormat::Json)
+            .expect("Failed to export");
+        let bytes = std::fs::read(&exported).expect("Failed to read export");
+        let mut services: Vec<Service> = deserialize_from_bytes(&bytes).expect("Invalid export");
+        services.sort_by(|left, right| left.title.cmp(&right.title));
+        let titles = services
+            .iter()
+            .map(|service| &service.title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Atlas", "Books"]);
+        assert_eq!(services[1].id(), dir.join("apps/Books.app"));
+        assert_eq!(services[1].bundle_id(), Some("com.apple.iBooksX"));
+        assert_eq!(services[0].bundle_id(), None);
+    }
+
+    #[test]
+    fn test_prepare() {
+        let dir = fixture::temp_dir("query_prepare");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app", "Boat.app"]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let index = processor.prepare();
+        assert_eq!(index.len(), 3);
+        fixture::create_bundles(&dir, &["Arrow.app"]);
+        assert_eq!(index.query("a").len(), 2);
+        assert_eq!(index.query("an").len(), 1);
+        assert_eq!(index.query("boat")[0].title, "Boat");
+        assert!(index.query("arrow").is_empty());
+    }
+
+    #[test]
+    fn test_prepare_index_equals_walk() {
+        let dir = fixture::temp_dir("query_prepare_index");
+        let names = [
+            "Activity Monitor.app",
+            "Audio MIDI Setup.app",
+            "Books.app",
+            "Calculator.app",
+            "iBooks Author.app",
+            "Visual Studio Code.app",
+            "微信WeChat.app",
+        ];
+        fixture::create_bundles(&dir, &names);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["abbreviations"]["value"] = json!({"vsc": ["visual studio"]});
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let index = processor.prepare();
+        let titles = |services: Vec<Service>| {
+            let mut titles = services
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>();
+            titles.sort();
+            titles
+        };
+        let requests = [
+            "a", "am", "mon", "book", "act mon", "-books b", "vsc", "wc", "微", "\"oo\"", "x",
+        ];
+        for req in requests {
+            let walked = titles(processor.query_services(req));
+            assert_eq!(titles(index.query(req)), walked, "request {}", req);
+        }
+    }
+
+    #[test]
+    fn test_prepare_index_without_targets() {
+        let dir = fixture::temp_dir("query_prepare_without_targets");
+        fixture::create_bundles(&dir, &["apps/Books.app"]);
+        let notes = ("CFBundleIdentifier", "com.apple.notes");
+        fixture::create_info_plist(dir.join("apps/Notes.app"), &[notes]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["matchFields"]["value"] = json!(["bundleId"]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let index = processor.prepare();
+        let titles = |services: Vec<Service>| {
+            let mut titles = services
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>();
+            titles.sort();
+            titles
+        };
+        assert_eq!(titles(index.query("")), ["Books", "Notes"]);
+        for req in ["", "apps/books", "apps/", "notes", "com.apple", "books"] {
+            let walked = titles(processor.query_services(req));
+            assert_eq!(titles(index.query(req)), walked, "request {}", req);
+        }
+    }
+
+    #[test]
+    fn test_query_hidden_root() {
+        let dir = fixture::temp_dir("query_hidden_root");
+        fixture::create_bundles(&dir, &[".apps/Atlas.app", ".apps/.Anchor.app"]);
+        let content =
+            get_content_with(|value| value["internal"]["paths"] = json!([dir.join(".apps")]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("a");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Atlas");
+    }
+
+    #[test]
+    fn test_query_recently_installed() {
+        let dir = fixture::temp_dir("query_recently_installed");
+        fixture::create_bundles(&dir, &["Notes One.app", "Notes Two.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["recencyWeight"]["value"] = json!(20);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        fn installed(newer: &str, service: &Service) -> Option<SystemTime> {
+            let days = if service.title.ends_with(newer) {
+                1
+            } else {
+                20
+            };
+            Some(SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60))
+        }
+        let mut processor = QP::with_configs(&configs, Vec::new());
+        processor.installed = |service| installed("One", service);
+        assert_eq!(processor.query_grouped("notes")[0].1[0].title, "Notes One");
+        assert_eq!(processor.query_services("notes")[0].title, "Notes One");
+        processor.installed = |service| installed("Two", service);
+        assert_eq!(processor.query_grouped("notes")[0].1[0].title, "Notes Two");
+        assert_eq!(processor.query_services("notes")[0].title, "Notes Two");
+        let ranked = processor.query_ranked("notes");
+        let newer = ranked
+            .iter()
+            .find(|ranked| ranked.service.title == "Notes Two");
+        let older = ranked
+            .iter()
+            .find(|ranked| ranked.service.title == "Notes One");
+        assert!(newer.unwrap().score > older.unwrap().score);
+    }
+
+    #[test]
+    fn test_query_grouped() {
+        let dir = fixture::temp_dir("query_grouped");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "system/Books.app",
+                "system/Notebook.app",
+                "user/Bookmarks.app",
+                "user/Maps.app",
+            ],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("system"), dir.join("user")])
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let groups = processor.query_grouped(r#""book""#);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].0, dir.join("system"));
+        let titles = groups[0]
+            .1
+            .iter()
+            .map(|service| &service.title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Books", "Notebook"]);
+        assert_eq!(groups[1].0, dir.join("user"));
+        assert_eq!(groups[1].1.len(), 1);
+        assert_eq!(groups[1].1[0].title, "Bookmarks");
+        assert_eq!(processor.query_grouped("maps")[0].0, dir.join("user"));
+    }
+
+    #[test]
+    fn test_cancelled_rebuild() {
+        let dir = fixture::temp_dir("query_cancelled_rebuild");
+        fixture::create_bundles(&dir, &["apps/Atlas.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let cancellation = CancellationToken::new();
+        let processor =
+            QP::with_configs(&configs, Vec::new()).with_cancellation(cancellation.clone());
+        processor.rebuild_cache().expect("Failed to rebuild");
+        fixture::create_bundles(&dir, &["apps/Anchor.app"]);
+        cancellation.cancel();
+        let error = processor.rebuild_cache().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+        let cache = processor.cache.as_ref().expect("No cache");
+        let cached = cache.bunch_read().expect("Cache is invalid");
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].title, "Atlas");
+    }
+
+    #[test]
+    fn test_cancelled_walk_not_cached() {
+        let dir = fixture::temp_dir("query_cancelled_walk");
+        fixture::create_bundles(&dir, &["apps/Atlas.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let processor = QP::with_configs(&configs, Vec::new()).with_cancellation(cancellation);
+        assert!(processor.query_services("a").is_empty());
+        assert!(processor
+            .cache
+            .as_ref()
+            .expect("No cache")
+            .bunch_read()
+            .is_err());
+    }
+
+    #[test]
+    fn test_query_transliterated() {
+        let dir = fixture::temp_dir("query_transliterated");
+        fixture::create_bundles(&dir, &["微信.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["transliterateNames"]["value"] = json!(true);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("weixin");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "微信");
+        assert_eq!(processor.query_services("微").len(), 1);
+    }
+
+    #[test]
+    fn test_query_collapse_whitespace() {
+        let dir = fixture::temp_dir("query_collapse_whitespace");
+        fixture::create_bundles(&dir, &["Activity Monitor.app", "Automator.app"]);
+        let content = |collapsed: bool| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["internal"]["cacheDir"] = json!(null);
+                value["configurable"]["collapseWhitespace"]["value"] = json!(collapsed);
+            })
+        };
+        let configs = Configs::from(content(true)).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let ids = |req: &str| {
+            processor
+                .query_services(req)
+                .iter()
+                .map(|service| service.id().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+        let clean = ids("activity monitor");
+        assert_eq!(clean, [dir.join("Activity Monitor.app")]);
+        assert_eq!(ids("  activity   monitor "), clean);
+        assert_eq!(ids(r#""activity   monitor""#), clean);
+        let configs = Configs::from(content(false)).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert!(processor
+            .query_services(r#""activity   monitor""#)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_query_not_transliterated() {
+        let dir = fixture::temp_dir("query_not_transliterated");
+        fixture::create_bundles(&dir, &["微信.app"]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert!(processor.query_services("weixin").is_empty());
+    }
+
+    #[test]
+    fn test_self_check_healthy() {
+        let dir = fixture::temp_dir("query_self_check_healthy");
+        fixture::create_bundles(&dir, &["apps/Atlas.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let report = processor.self_check();
+        assert!(report.is_healthy(), "{:?}", report.warnings);
+        assert_eq!(report.cache_writable, Some(true));
+        assert_eq!(report.cache_readable, None);
+        processor.rebuild_cache().expect("Failed to rebuild");
+        assert_eq!(processor.self_check().cache_readable, Some(true));
+    }
+
+    #[test]
+    fn test_self_check_missing_root() {
+        let dir = fixture::temp_dir("query_self_check_missing_root");
+        fixture::create_bundles(&dir, &["apps/Atlas.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps"), dir.join("missing")])
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let report = processor.self_check();
+        assert!(!report.is_healthy());
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("missing"));
+        assert!(report.roots[0].readable);
+        assert!(!report.roots[1].readable);
+        assert_eq!(report.cache_writable, None);
+    }
+
+    #[test]
+    fn test_query_modified_within() {
+        let dir = fixture::temp_dir("query_modified_within");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app"]);
+        let ten_days = Duration::from_secs(10 * 24 * 60 * 60);
+        File::open(dir.join("Atlas.app"))
+            .and_then(|bundle| bundle.set_modified(SystemTime::now() - ten_days))
+            .expect("Failed to set modified time");
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["modifiedWithinDays"]["value"] = json!(1);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("a");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Anchor");
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        assert_eq!(
+            QP::with_configs(&configs, Vec::new())
+                .query_services("a")
+                .len(),
+            2
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_write_results() {
+        let dir = fixture::temp_dir("query_write_results");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app", "Boat.app"]);
+        let content = |limit: Option<usize>| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["internal"]["maxOutputBytes"] = json!(limit);
+                value["configurable"]["pathDisplay"]["value"] = json!("relative");
+            })
+        };
+        let write = |configs: &Configs| {
+            let processor = QP::with_configs(configs, Vec::new());
+            let mut buffer = futures::io::Cursor::new(Vec::new());
+            futures::executor::block_on(processor.write_results("a", &mut buffer))
+                .expect("Failed to write");
+            let mut output = Vec::new();
+            QP::with_configs(configs, &mut output).query("a");
+            assert_eq!(buffer.get_ref(), &output);
+            buffer
+        };
+        let configs = Configs::from(content(Some(1))).expect("Invalid settings");
+        assert!(write(&configs).get_ref().is_empty());
+        let configs = Configs::from(content(None)).expect("Invalid settings");
+        let buffer = write(&configs);
+        let processor = QP::with_configs(&configs, Vec::new());
+        let written = buffer
+            .into_inner()
+            .split(|byte| *byte == b'\n')
+            .filter(|frame| !frame.is_empty())
+            .map(|frame| serde_json::from_slice::<Service>(frame).expect("Invalid frame"))
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        let expected = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        assert_eq!(written, expected);
+        assert_eq!(written.len(), 2);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_discover_into() {
+        use futures::StreamExt;
+
+        let dir = fixture::temp_dir("query_discover_into");
+        let names = [
+            "Atlas.app",
+            "Anchor.app",
+            "Boat.app",
+            "nested/Clock.app",
+            "nested/Maps.app",
+        ];
+        fixture::create_bundles(&dir, &names);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([{"path": dir, "label": "Apps"}]);
+            value["configurable"]["checkers"]["value"] = json!(["ignore", "symlink", "fragile"]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let root = &configs.get_roots()[0];
+        let discover = |processor: &QP<Vec<u8>>| {
+            let (sender, receiver) = futures::channel::mpsc::channel(1);
+            let (sent, received) = futures::executor::block_on(async {
+                futures::join!(
+                    processor.discover_into(root, sender),
+                    receiver.collect::<Vec<_>>()
+                )
+            });
+            sent.expect("Failed to send");
+            assert!(received
+                .iter()
+                .all(|service| service.label() == Some("Apps")));
+            let mut titles = received
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>();
+            titles.sort();
+            titles
+        };
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(
+            discover(&processor),
+            ["Anchor", "Atlas", "Boat", "Clock", "Maps"]
+        );
+        assert_eq!(processor.truncations.load(Ordering::Relaxed), 0);
+
+        let registry = CheckerRegistry::new().register("fragile", |path: &Path| {
+            if path.ends_with("nested") {
+                panic!("Unexpected path: {}", path.display())
+            }
+            false
+        });
+        let processor = QP::with_configs(&configs, Vec::new()).with_checkers(registry);
+        let titles = discover(&processor);
+        assert!(titles
+            .iter()
+            .all(|title| title != "Clock" && title != "Maps"));
+        assert_eq!(processor.truncations.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_query_progressive() {
+        use futures::StreamExt;
+
+        use crate::query::diff::ProgressiveResult;
+
+        let dir = fixture::temp_dir("query_progressive");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "apps/Boat.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("a").len(), 1);
+        fixture::create_bundles(&dir, &["apps/Anchor.app"]);
+        let results =
+            futures::executor::block_on(processor.query_progressive("a").collect::<Vec<_>>());
+        assert_eq!(results.len(), 2);
+        match &results[0] {
+            ProgressiveResult::Cached(services) => {
+                assert_eq!(services.len(), 1);
+                assert_eq!(services[0].title, "Atlas");
+            }
+            result => panic!("Unexpected first result: {:?}", result),
+        }
+        match &results[1] {
+            ProgressiveResult::Changed(diff) => {
+                assert_eq!(diff.added.len(), 1);
+                assert_eq!(diff.added[0].title, "Anchor");
+                assert!(diff.removed.is_empty());
+            }
+            result => panic!("Unexpected second result: {:?}", result),
+        }
+
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let results =
+            futures::executor::block_on(processor.query_progressive("a").collect::<Vec<_>>());
+        match &results[..] {
+            [ProgressiveResult::Cached(services), ProgressiveResult::Changed(diff)] => {
+                assert_eq!(services.len(), 2);
+                assert!(diff.added.is_empty() && diff.removed.is_empty());
+            }
+            results => panic!("Unexpected results: {:?}", results),
+        }
+        assert_eq!(processor.discovered.load(Ordering::Relaxed), 3);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_enrich_services() {
+        let dir = fixture::temp_dir("query_enrich_services");
+        let names = ["Atlas", "Anchor", "Boat", "Clock", "Maps", "Notes"];
+        for name in names {
+            let id = format!("com.example.{}", name.to_lowercase());
+            let path = dir.join(format!("{}.app", name));
+            fixture::create_info_plist(path, &[("CFBundleIdentifier", id.as_str())]);
+        }
+        let paths = names
+            .iter()
+            .map(|name| dir.join(format!("{}.app", name)))
+            .collect::<Vec<_>>();
+        for concurrency in [1, 4] {
+            let content = get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["internal"]["enrichConcurrency"] = json!(concurrency);
+            });
+            let configs = Configs::from(content).expect("Invalid settings");
+            assert_eq!(configs.get_enrich_concurrency(), concurrency);
+            let processor = QP::with_configs(&configs, Vec::new());
+            let mut services =
+                futures::executor::block_on(processor.enrich_services(paths.clone()));
+            services.sort_by(|left, right| left.title.cmp(&right.title));
+            let bundle_ids = services
+                .iter()
+                .map(|service| service.bundle_id().unwrap_or_default())
+                .collect::<Vec<_>>();
+            let expected = [
+                "com.example.anchor",
+                "com.example.atlas",
+                "com.example.boat",
+                "com.example.clock",
+                "com.example.maps",
+                "com.example.notes",
+            ];
+            assert_eq!(bundle_ids, expected);
+            assert!(services
+                .iter()
+                .all(|service| service.parent() == Some(dir.as_path())));
+        }
+    }
+
+    #[test]
+    fn test_query_with_include_hidden() {
+        let dir = fixture::temp_dir("query_with_include_hidden");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "apps/.Anchor.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("a").len(), 1);
+        let options = QueryOptions {
+            include_hidden: true,
+            ..QueryOptions::default()
+        };
+        assert_eq!(processor.query_with("a", &options).len(), 2);
+        assert_eq!(processor.query_with("a", &QueryOptions::default()).len(), 1);
+        assert_eq!(processor.query_services("a").len(), 1);
+    }
+
+    #[test]
+    fn test_query_with_include_ignored() {
+        let dir = fixture::temp_dir("query_with_include_ignored");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["ignorePaths"]["value"] = json!([dir.join("Anchor.app")]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("a").len(), 1);
+        let options = QueryOptions {
+            include_ignored: true,
+            ..QueryOptions::default()
+        };
+        assert_eq!(processor.query_with("a", &options).len(), 2);
+    }
+
+    #[test]
+    fn test_classify() {
+        let dir = fixture::temp_dir("query_classify");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app", "Tools"]);
+        std::fs::write(dir.join(".Archive"), "").unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["ignorePaths"]["value"] = json!([dir.join("Anchor.app")]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let classify = |name: &str| processor.classify(&dir.join(name));
+        assert_eq!(classify("Atlas.app"), Classification::Service);
+        assert_eq!(classify(".Archive"), Classification::Hidden);
+        assert_eq!(classify("Anchor.app"), Classification::Ignored);
+        assert_eq!(classify("Tools"), Classification::Plain);
+    }
+
+    #[test]
+    fn test_query_extension_suffix() {
+        std::env::set_var("SETTINGS", crate::configurator::get_content());
+        let dir = fixture::temp_dir("query_extension_suffix");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "System Settings.app",
+                "Settings Sync.prefPane",
+                "Sound.prefPane",
+            ],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let ids = |req: &str| {
+            let mut ids = processor
+                .query_services(req)
+                .into_iter()
+                .map(|service| service.id().to_path_buf())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids("settings").len(), 2);
+        assert_eq!(
+            ids("settings .prefpane"),
+            [dir.join("Settings Sync.prefPane")]
+        );
+        assert_eq!(ids("settings .app"), [dir.join("System Settings.app")]);
+        assert_eq!(ids(".prefPane").len(), 2);
+        assert!(ids("settings .txt").is_empty());
+        let ranked = processor.query_ranked("settings .prefpane");
+        assert_eq!(ranked.len(), 1);
+        let options = QueryOptions {
+            bypass_cache: true,
+            ..QueryOptions::default()
+        };
+        for req in ["settings .prefpane", "settings .app"] {
+            let mut streamed = Vec::new();
+            processor.query_streaming(req, |service| streamed.push(service.id().to_path_buf()));
+            streamed.sort();
+            assert_eq!(streamed, ids(req), "{}", req);
+            let mut queried = processor
+                .query_with(req, &options)
+                .into_iter()
+                .map(|service| service.id().to_path_buf())
+                .collect::<Vec<_>>();
+            queried.sort();
+            assert_eq!(queried, ids(req), "{}", req);
+        }
+        let index = processor.prepare();
+        assert_eq!(index.query("settings .app").len(), 1);
+    }
+
+    #[test]
+    fn test_query_bundle_extension_stripped() {
+        std::env::set_var("SETTINGS", crate::configurator::get_content());
+        let dir = fixture::temp_dir("query_bundle_extension_stripped");
+        fixture::create_bundles(&dir, &["Books.app", "Books.prefPane", "Maps.app"]);
+        let processor_titles = |stripped: bool, req: &str| {
+            let content = get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["internal"]["cacheDir"] = json!(null);
+                value["configurable"]["stripBundleExtension"]["value"] = json!(stripped);
+            });
+            let configs = Configs::from(content).expect("Invalid settings");
+            let processor = QP::with_configs(&configs, Vec::new());
+            let titles = |services: Vec<Service>| {
+                let mut titles = services
+                    .into_iter()
+                    .map(|service| service.title)
+                    .collect::<Vec<_>>();
+                titles.sort();
+                titles
+            };
+            let queried = titles(processor.query_services(req));
+            let mut streamed = Vec::new();
+            processor.query_streaming(req, |service| streamed.push(service));
+            assert_eq!(titles(streamed), queried, "{}", req);
+            let options = QueryOptions {
+                bypass_cache: true,
+                ..QueryOptions::default()
+            };
+            assert_eq!(
+                titles(processor.query_with(req, &options)),
+                queried,
+                "{}",
+                req
+            );
+            queried
+        };
+        assert_eq!(processor_titles(true, "books"), ["Books", "Books"]);
+        assert_eq!(processor_titles(true, "books.app"), ["Books"]);
+        assert_eq!(processor_titles(true, "Books.App"), ["Books"]);
+        assert!(processor_titles(true, "books.txt").is_empty());
+        assert_eq!(
+            processor_titles(false, "books"),
+            ["Books.app", "Books.prefPane"]
+        );
+        assert_eq!(processor_titles(false, "books.app"), ["Books.app"]);
+    }
+
+    #[test]
+    fn test_query_with_allowed_extensions() {
+        std::env::set_var("SETTINGS", crate::configurator::get_content());
+        let dir = fixture::temp_dir("query_with_allowed_extensions");
+        fixture::create_bundles(&dir, &["Sound.app", "Sound.prefPane"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("sound").len(), 2);
+        let options = QueryOptions {
+            allowed_extensions: vec![".app".to_owned()],
+            ..QueryOptions::default()
+        };
+        let services = processor.query_with("sound", &options);
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].id(), dir.join("Sound.app"));
+    }
+
+    #[test]
+    fn test_query_with_collapsed_names() {
+        let dir = fixture::temp_dir("query_with_collapsed_names");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "Python.app",
+                "3.11/Python.app",
+                "3.12/Python.app",
+                "Pixelmator.app",
+            ],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("p").len(), 4);
+        let options = QueryOptions {
+            collapse_duplicate_names: true,
+            ..QueryOptions::default()
+        };
+        let services = processor.query_with("p", &options);
+        assert_eq!(services.len(), 2);
+        let python = services
+            .iter()
+            .find(|service| service.title == "Python")
+            .expect("Python is missing");
+        assert_eq!(python.metadata()[COLLAPSED_KEY], "3");
+        let pixelmator = services
+            .iter()
+            .find(|service| service.title == "Pixelmator")
+            .expect("Pixelmator is missing");
+        assert!(pixelmator.metadata().is_empty());
+    }
+
+    #[test]
+    fn test_query_with_app_store_only() {
+        let dir = fixture::temp_dir("query_with_app_store_only");
+        fixture::create_bundles(&dir, &["Keynote.app", "Kindle.app"]);
+        let receipt = dir.join("Keynote.app/Contents/_MASReceipt");
+        std::fs::create_dir_all(&receipt).unwrap();
+        std::fs::write(receipt.join("receipt"), "").unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("k");
+        assert_eq!(services.len(), 2);
+        let app_store = |title: &str| {
+            services
+                .iter()
+                .find(|service| service.title == title)
+                .map(Service::is_app_store)
+        };
+        assert_eq!(app_store("Keynote"), Some(true));
+        assert_eq!(app_store("Kindle"), Some(false));
+        let options = QueryOptions {
+            app_store_only: true,
+            ..QueryOptions::default()
+        };
+        let services = processor.query_with("k", &options);
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Keynote");
+    }
+
+    #[test]
+    fn test_query_with_bypass_cache() {
+        let dir = fixture::temp_dir("query_with_bypass_cache");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "apps/Anchor.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("a").len(), 2);
+        let cache = processor.cache.as_ref().unwrap();
+        let stale = [Service::new(dir.join("apps/Atlas.app"))];
+        cache.bunch_save(&stale).expect("Failed to save");
+        assert_eq!(processor.query_services("a").len(), 1);
+        let options = QueryOptions {
+            bypass_cache: true,
+            ..QueryOptions::default()
+        };
+        let mut titles = processor
+            .query_with("a", &options)
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, ["Anchor", "Atlas"]);
+        assert_eq!(cache.bunch_read().expect("Failed to read").len(), 1);
+    }
+
+    #[test]
+    fn test_query_with_record_depth() {
+        let dir = fixture::temp_dir("query_with_record_depth");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "apps/Tools/Extra/Anchor.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert!(processor
+            .query_services("a")
+            .iter()
+            .all(|service| service.metadata().get(DEPTH_KEY).is_none()));
+        let options = QueryOptions {
+            record_depth: true,
+            ..QueryOptions::default()
+        };
+        let services = processor.query_with("a", &options);
+        let depth = |title: &str| {
+            services
+                .iter()
+                .find(|service| service.title == title)
+                .and_then(|service| service.metadata().get(DEPTH_KEY).cloned())
+        };
+        assert_eq!(depth("Atlas").as_deref(), Some("1"));
+        assert_eq!(depth("Anchor").as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn test_query_filtered() {
+        let dir = fixture::temp_dir("query_filtered");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app", "Archive Utility.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["internal"]["queryCacheSize"] = json!(4);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let short = processor.query_filtered("a", |service| service.title.chars().count() <= 5);
+        assert_eq!(short.len(), 1);
+        assert_eq!(short[0].title, "Atlas");
+        assert_eq!(processor.query_filtered("a", |_| true).len(), 3);
+        assert!(processor.query_filtered("a", |_| false).is_empty());
+    }
+
+    #[test]
+    fn test_query_with_category_filter() {
+        let dir = fixture::temp_dir("query_with_category_filter");
+        let bundles = [
+            ("Activity Monitor.app", "public.app-category.utilities"),
+            ("Archive Utility.app", "public.app-category.utilities"),
+            ("Asteroids.app", "public.app-category.games"),
+        ];
+        for (bundle, category) in bundles {
+            let category = ("LSApplicationCategoryType", category);
+            fixture::create_info_plist(dir.join(bundle), &[category]);
+        }
+        fixture::create_bundles(&dir, &["Atlas.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("a").len(), 4);
+        let filtered = |filter: &str| {
+            let options = QueryOptions {
+                category_filter: Some(filter.to_owned()),
+                ..QueryOptions::default()
+            };
+            let mut titles = processor
+                .query_with("a", &options)
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>();
+            titles.sort();
+            titles
+        };
+        assert_eq!(
+            filtered("utilities"),
+            ["Activity Monitor", "Archive Utility"]
+        );
+        assert_eq!(filtered("public.app-category.games"), ["Asteroids"]);
+        assert!(filtered("music").is_empty());
+    }
+
+    fn stub_spotlight(_: &str) -> Vec<PathBuf> {
+        let found = std::env::temp_dir().join("launch_service/query_spotlight/other");
+        vec![found.join("Books.app"), found.join("Atlas.app")]
+    }
+
+    #[test]
+    fn test_query_spotlight() {
+        let dir = fixture::temp_dir("query_spotlight");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "other/Books.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["configurable"]["spotlightFallback"]["value"] = json!(1);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let mut processor = QP::with_configs(&configs, Vec::new());
+        processor.spotlight = stub_spotlight;
+        let services = processor.query_services("books");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].id(), dir.join("other/Books.app"));
+        let services = processor.query_services("atlas");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].id(), dir.join("apps/Atlas.app"));
+    }
+
+    #[test]
+    fn test_query_spotlight_disabled() {
+        let dir = fixture::temp_dir("query_spotlight_disabled");
+        fixture::create_bundles(&dir, &["apps/Atlas.app"]);
+        let content =
+            get_content_with(|value| value["internal"]["paths"] = json!([dir.join("apps")]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let mut processor = QP::with_configs(&configs, Vec::new());
+        processor.spotlight = |_| panic!("Spotlight searched");
+        assert!(processor.query_services("books").is_empty());
+    }
+
+    #[test]
+    fn test_query_max_entries_per_folder() {
+        let dir = fixture::temp_dir("query_max_entries_per_folder");
+        let names = (0..200)
+            .map(|index| format!("crowded/App{}.app", index))
+            .collect::<Vec<_>>();
+        fixture::create_bundles(&dir, &names.iter().map(String::as_str).collect::<Vec<_>>());
+        fixture::create_bundles(&dir, &["Atlas.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("crowded"), dir.join("Atlas.app")]);
+            value["internal"]["maxEntriesPerFolder"] = json!(10);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("");
+        assert_eq!(services.len(), 11);
+        assert_eq!(processor.truncations.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_query_symlinked_root() {
+        let dir = fixture::temp_dir("query_symlinked_root");
+        fixture::create_bundles(&dir, &["real/Atlas.app", "real/Tools/Anchor.app"]);
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+        std::os::unix::fs::symlink(dir.join("real/Tools"), dir.join("real/Shortcut")).unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("link")]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let mut ids = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.id().to_path_buf())
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(
+            ids,
+            [
+                dir.join("link/Atlas.app"),
+                dir.join("link/Tools/Anchor.app")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_root_priority() {
+        let dir = fixture::temp_dir("query_root_priority");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "system/Books.app",
+                "system/Maps.app",
+                "system/Ma.app",
+                "user/Mail.app",
+            ],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([
+                dir.join("system"),
+                {"path": dir.join("user"), "priority": 1},
+            ]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let titles = |req: &str| {
+            processor
+                .query_services(req)
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles("")[0], "Mail");
+        assert_eq!(titles("").len(), 4);
+        let ranked = processor.query_ranked("ma");
+        assert_eq!(ranked[1].score, ranked[2].score);
+        assert_eq!(titles("ma"), ["Ma", "Mail", "Maps"]);
+    }
+
+    #[test]
+    fn test_query_top_k_dedup() {
+        let dir = fixture::temp_dir("query_top_k_dedup");
+        let (old, middle, new) = (dir.join("old"), dir.join("middle"), dir.join("new"));
+        let version = |version| ("CFBundleShortVersionString", version);
+        let nuts = ("CFBundleIdentifier", "com.nuts");
+        let numbers = ("CFBundleIdentifier", "com.numbers");
+        fixture::create_info_plist(old.join("Nuts Old.app"), &[nuts, version("1.0")]);
+        fixture::create_info_plist(old.join("Numbers.app"), &[numbers, version("5.0")]);
+        fixture::create_bundles(&middle, &["Notes.app", "Notepad.app", "Nova.app"]);
+        fixture::create_info_plist(new.join("Nuts.app"), &[nuts, version("2.0")]);
+        fixture::create_info_plist(new.join("Numbers Legacy.app"), &[numbers, version("1.0")]);
+        let content = |pinned: &[PathBuf]| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([old, middle, new]);
+                value["internal"]["cacheDir"] = json!(null);
+                value["configurable"]["pinnedApps"]["value"] = json!(pinned);
+            })
+        };
+        for pinned in [Vec::new(), vec![middle.join("Nova.app")]] {
+            let configs = Configs::from(content(&pinned)).expect("Invalid settings");
+            let processor = QP::with_configs(&configs, Vec::new());
+            for req in ["", "n", "nu", "nuts", "numbers", "zzz"] {
+                let services = processor.query_services(req);
+                for k in 0..=services.len() + 1 {
+                    let streamed = processor.query_top_k(req, k);
+                    assert_eq!(
+                        streamed.iter().map(Service::id).collect::<Vec<_>>(),
+                        services.iter().take(k).map(Service::id).collect::<Vec<_>>(),
+                        "{} top {}",
+                        req,
+                        k
+                    );
+                }
+            }
+        }
+        let configs = Configs::from(content(&[])).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let top = processor.query_top_k("nu", 1);
+        assert_eq!(top[0].id(), new.join("Nuts.app"));
+        let top = processor.query_top_k("numbers", 1);
+        assert_eq!(top[0].id(), old.join("Numbers.app"));
+    }
+
+    #[test]
+    fn test_query_top_k() {
+        let dir = fixture::temp_dir("query_top_k");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "Notes.app",
+                "Notepad.app",
+                "Nova.app",
+                "Numbers.app",
+                "Pages.app",
+                "Notes Pro.app",
+            ],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        for req in ["", "n", "no", "notes", "zzz"] {
+            let services = processor.query_services(req);
+            for k in 0..=services.len() + 1 {
+                let expected = services.iter().take(k).map(Service::id).collect::<Vec<_>>();
+                let top = processor.query_top_k(req, k);
+                let ids = top.iter().map(Service::id).collect::<Vec<_>>();
+                assert_eq!(ids, expected, "{} top {}", req, k);
+            }
+        }
+    }
+
+    #[test]
+    fn test_query_tie_break_last_used() {
+        let dir = fixture::temp_dir("query_tie_break_last_used");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anvil.app", "Ax.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["tieBreak"]["value"] = json!("lastUsed");
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let set_accessed = |name: &str, days: u64| {
+            let accessed = SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60);
+            File::open(dir.join(name))
+                .and_then(|bundle| bundle.set_times(FileTimes::new().set_accessed(accessed)))
+                .expect("Failed to set accessed time");
+        };
+        set_accessed("Atlas.app", 10);
+        set_accessed("Anvil.app", 1);
+        set_accessed("Ax.app", 30);
+        let processor = QP::with_configs(&configs, Vec::new());
+        let titles = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Ax", "Anvil", "Atlas"]);
+        set_accessed("Atlas.app", 0);
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("a")[1].title, "Atlas");
+    }
+
+    #[test]
+    fn test_query_tie_break_size() {
+        let dir = fixture::temp_dir("query_tie_break_size");
+        fixture::create_bundles(
+            &dir,
+            &["Atlas.app/Contents", "Anvil.app/Contents", "Ax.app"],
+        );
+        std::fs::write(dir.join("Atlas.app/Contents/Atlas"), [0; 16]).unwrap();
+        std::fs::write(dir.join("Anvil.app/Contents/Anvil"), [0; 64]).unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["tieBreak"]["value"] = json!("size");
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let titles = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Ax", "Anvil", "Atlas"]);
+        std::fs::write(dir.join("Atlas.app/Contents/Atlas"), [0; 128]).unwrap();
+        assert_eq!(processor.query_services("at")[0].title, "Atlas");
+        assert_eq!(processor.sizes.lock().unwrap()[&dir.join("Atlas.app")], 16);
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("a")[1].title, "Atlas");
+    }
+
+    #[test]
+    fn test_output_relative_paths() {
+        let dir = fixture::temp_dir("query_output_relative_paths");
+        fixture::create_bundles(&dir, &["apps/Books.app", "apps/Tools/Calculator.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["configurable"]["pathDisplay"]["value"] = json!("relative");
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let mut output = Vec::new();
+        QP::with_configs(&configs, &mut output).query("books");
+        let service: Service = serde_json::from_slice(&output).expect("Invalid output");
+        assert_eq!(service.subtitle(), Path::new("Books.app"));
+        assert_eq!(service.id(), dir.join("apps/Books.app"));
+        let mut output = Vec::new();
+        QP::with_configs(&configs, &mut output).query("calculator");
+        let service: Service = serde_json::from_slice(&output).expect("Invalid output");
+        assert_eq!(service.subtitle(), Path::new("Tools/Calculator.app"));
+    }
+
+    #[test]
+    fn test_output_uids() {
+        let dir = fixture::temp_dir("query_output_uids");
+        fixture::create_bundles(&dir, &["Books.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["emitUids"]["value"] = json!(true);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let mut first = Vec::new();
+        QP::with_configs(&configs, &mut first).query("books");
+        let mut second = Vec::new();
+        QP::with_configs(&configs, &mut second).query("boo");
+        let first: Service = serde_json::from_slice(&first).expect("Invalid output");
+        let second: Service = serde_json::from_slice(&second).expect("Invalid output");
+        assert!(first.uid().is_some());
+        assert_eq!(first.uid(), second.uid());
+        assert_eq!(
+            first.uid(),
+            Service::new(dir.join("Books.app")).with_uid().uid()
+        );
+        let serialized = serde_json::to_string(&first).expect("Unable to serialize");
+        let deserialized: Service = serde_json::from_str(&serialized).expect("Invalid output");
+        assert_eq!(deserialized.uid(), first.uid());
+        let mut output = Vec::new();
+        let configs = Configs::from(get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir])
+        }))
+        .expect("Invalid settings");
+        QP::with_configs(&configs, &mut output).query("books");
+        let service: Service = serde_json::from_slice(&output).expect("Invalid output");
+        assert_eq!(service.uid(), None);
+    }
+
+    #[test]
+    fn test_output_without_signature() {
+        let dir = fixture::temp_dir("query_output_without_signature");
+        fixture::create_bundles(&dir, &["Books.app"]);
+        fixture::create_info_plist(
+            dir.join("Books.app"),
+            &[("CFBundleIdentifier", "com.books")],
+        );
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("books");
+        assert!(services[0].signature().is_some());
+        let mut output = Vec::new();
+        QP::with_configs(&configs, &mut output).query("books");
+        let output = String::from_utf8(output).expect("Invalid output");
+        assert!(output.contains("com.books"));
+        assert!(!output.contains("\"signature\""));
+    }
+
+    #[test]
+    fn test_output_absolute_paths() {
+        let dir = fixture::temp_dir("query_output_absolute_paths");
+        fixture::create_bundles(&dir, &["apps/Books.app"]);
+        let content =
+            get_content_with(|value| value["internal"]["paths"] = json!([dir.join("apps")]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let mut output = Vec::new();
+        QP::with_configs(&configs, &mut output).query("books");
+        let service: Service = serde_json::from_slice(&output).expect("Invalid output");
+        assert_eq!(service.subtitle(), dir.join("apps/Books.app"));
+    }
+
+    #[test]
+    fn test_query_normalizer() {
+        let dir = fixture::temp_dir("query_normalizer");
+        fixture::create_bundles(&dir, &["Books.app", "Open Office.app"]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert!(processor.query_services("open books").is_empty());
+        let processor = processor.with_normalizer(|raw: String| {
+            let raw = raw.trim().to_lowercase();
+            raw.strip_prefix("open ").unwrap_or(&raw).to_owned()
+        });
+        let services = processor.query_services("  Open Books ");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Books");
+    }
+
+    #[test]
+    fn test_query_versions_stripped() {
+        let dir = fixture::temp_dir("query_versions_stripped");
+        fixture::create_bundles(&dir, &["Xcode-14.3.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["stripVersions"]["value"] = json!(true);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("xcode");
+        assert_eq!(services[0].title, "Xcode");
+    }
+
+    #[test]
+    fn test_query_parent() {
+        let dir = fixture::temp_dir("query_parent");
+        fixture::create_bundles(&dir, &["Atlas.app", "Utilities/Anchor.app"]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("a");
+        let atlas = services.iter().find(|s| s.title == "Atlas").unwrap();
+        assert_eq!(atlas.parent(), Some(dir.as_path()));
+        let anchor = services.iter().find(|s| s.title == "Anchor").unwrap();
+        assert_eq!(anchor.parent(), Some(dir.join("Utilities").as_path()));
+    }
+
+    #[test]
+    fn test_query_concurrent_rebuild() {
+        let dir = fixture::temp_dir("query_concurrent_rebuild");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "apps/Boat.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let mut origins = std::thread::scope(|scope| {
+            let first = scope.spawn(|| processor.query_diagnosed("a"));
+            let second = scope.spawn(|| processor.query_diagnosed("b"));
+            [first.join().unwrap(), second.join().unwrap()].map(|diagnostics| {
+                assert_eq!(diagnostics.services.len(), 1);
+                let cached = diagnostics.services[0].service.is_cached();
+                assert_eq!(cached, diagnostics.served_from == ResultOrigin::Cache);
+                diagnostics.served_from
+            })
+        });
+        origins.sort_by_key(|origin| *origin == ResultOrigin::Rebuild);
+        assert_eq!(origins, [ResultOrigin::Cache, ResultOrigin::Rebuild]);
+        assert_eq!(processor.rebuilds.load(Ordering::Relaxed), 1);
+        assert_eq!(processor.cache_hits.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_query_per_root_timeout() {
+        struct SlowFs;
+
+        impl Fs for SlowFs {
+            fn read_dir(&self, path: &Path) -> std::io::Result<Entries<'_>> {
+                if path
+                    .components()
+                    .any(|component| component.as_os_str() == "slow")
+                {
+                    std::thread::sleep(Duration::from_millis(40));
+                }
+                StdFs.read_dir(path)
+            }
+        }
+
+        let dir = fixture::temp_dir("query_per_root_timeout");
+        let slow = (0..10)
+            .map(|index| format!("slow/{}/Anchor{}.app", index, index))
+            .collect::<Vec<_>>();
+        fixture::create_bundles(&dir, &slow.iter().map(String::as_str).collect::<Vec<_>>());
+        fixture::create_bundles(&dir, &["fast/Atlas.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("slow"), dir.join("fast")]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["internal"]["perRootTimeoutMillis"] = json!(100);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new()).with_fs(SlowFs);
+        let services = processor.query_services("a");
+        assert!(services.iter().any(|service| service.title == "Atlas"));
+        assert!(services.len() < 11);
+        assert_eq!(processor.truncations.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_timed_out_walk_not_cached() {
+        struct SlowFs;
+
+        impl Fs for SlowFs {
+            fn read_dir(&self, path: &Path) -> std::io::Result<Entries<'_>> {
+                if path.ends_with("slow") {
+                    std::thread::sleep(Duration::from_millis(40));
+                }
+                StdFs.read_dir(path)
+            }
+        }
+
+        let dir = fixture::temp_dir("timed_out_walk_not_cached");
+        fixture::create_bundles(&dir, &["slow/Anchor.app", "fast/Atlas.app"]);
+        let content = |timeout: Option<u64>| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir.join("slow"), dir.join("fast")]);
+                value["internal"]["cacheDir"] = json!(dir.join("cache"));
+                value["internal"]["perRootTimeoutMillis"] = json!(timeout);
+            })
+        };
+        let configs = Configs::from(content(None)).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        processor.rebuild_cache().expect("Failed to rebuild");
+        let configs = Configs::from(content(Some(10))).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new()).with_fs(SlowFs);
+        let error = processor
+            .rebuild_cache()
+            .expect_err("Timed out walk is cached");
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+        assert_eq!(processor.truncations.load(Ordering::Relaxed), 1);
+        let cache = processor.cache.as_ref().expect("No cache");
+        let cached = cache.bunch_read().expect("Failed to read cache");
+        assert_eq!(cached.len(), 2);
+    }
+
+    #[test]
+    fn test_query_wrapped_bundle() {
+        let dir = fixture::temp_dir("query_wrapped_bundle");
+        let properties = [
+            ("CFBundleIdentifier", "com.example.notes"),
+            ("CFBundleExecutable", "Notes"),
+        ];
+        fixture::create_wrapped_bundle(dir.join("Notes.app"), &properties);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["requireExecutable"]["value"] = json!(true);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("notes");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Notes");
+        assert_eq!(services[0].bundle_id(), Some("com.example.notes"));
+        assert_eq!(services[0].executable(), Some("Notes"));
+    }
+
+    #[test]
+    fn test_query_traversal_order() {
+        struct SortedFs;
+
+        impl Fs for SortedFs {
+            fn read_dir(&self, path: &Path) -> std::io::Result<Entries<'_>> {
+                let mut paths = StdFs.read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+                paths.sort();
+                Ok(Box::new(paths.into_iter().map(Ok)))
+            }
+        }
+
+        let dir = fixture::temp_dir("query_traversal_order");
+        fixture::create_bundles(&dir, &["Deep/Inner/Axe.app", "Deep/Mid.app", "Top.app"]);
+        let streamed = |order: &str| {
+            let content = get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["internal"]["cacheDir"] = json!(null);
+                value["configurable"]["traversalOrder"]["value"] = json!(order);
+            });
+            let configs = Configs::from(content).expect("Invalid settings");
+            let processor = QP::with_configs(&configs, Vec::new()).with_fs(SortedFs);
+            let mut titles = Vec::new();
+            processor.query_streaming("", |service| titles.push(service.title));
+            titles
+        };
+        let depth_first = streamed("depthFirst");
+        let breadth_first = streamed("breadthFirst");
+        assert_eq!(depth_first, ["Axe", "Mid", "Top"]);
+        assert_eq!(breadth_first, ["Top", "Mid", "Axe"]);
+    }
+
+    #[test]
+    fn test_query_launchignore() {
+        let dir = fixture::temp_dir("query_launchignore");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "apps/Atlas.app",
+                "apps/Anchor Beta.app",
+                "apps/nested/Axe Beta.app",
+                "Arc Beta.app",
+            ],
+        );
+        std::fs::write(
+            dir.join("apps").join(IGNORE_FILE),
+            "*Beta.app
+",
+        )
+        .unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let mut titles = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, ["Arc Beta", "Atlas"]);
+    }
+
+    #[test]
+    fn test_query_with_fs() {
+        struct CountedFs<'c>(&'c AtomicUsize);
+
+        impl Fs for CountedFs<'_> {
+            fn read_dir(&self, path: &Path) -> std::io::Result<Entries<'_>> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                StdFs.read_dir(path)
+            }
+        }
+
+        let dir = fixture::temp_dir("query_with_fs");
+        fixture::create_bundles(&dir, &["Atlas.app", "Boat.app", "nested/Anchor.app"]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let reads = AtomicUsize::new(0);
+        let counted = QP::with_configs(&configs, Vec::new()).with_fs(CountedFs(&reads));
+        let processor = QP::with_configs(&configs, Vec::new());
+        for req in ["a", "boat", "nested"] {
+            let expected = processor.query_services(req);
+            let services = counted.query_services(req);
+            let ids = |services: Vec<Service>| {
+                services
+                    .iter()
+                    .map(|service| service.id().to_path_buf())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(ids(services), ids(expected));
+        }
+        assert_eq!(reads.load(Ordering::Relaxed), 6);
+    }
+
+    #[test]
+    fn test_rebuild_skips_empty_folders() {
+        struct RecordedFs<'r>(&'r Mutex<Vec<PathBuf>>);
+
+        impl Fs for RecordedFs<'_> {
+            fn read_dir(&self, path: &Path) -> std::io::Result<Entries<'_>> {
+                self.0.lock().unwrap().push(path.to_path_buf());
+                StdFs.read_dir(path)
+            }
+        }
+
+        let dir = fixture::temp_dir("rebuild_skips_empty_folders");
+        fixture::create_bundles(&dir, &["apps/Atlas.app"]);
+        std::fs::create_dir_all(dir.join("apps/empty/deep")).unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let reads = Mutex::new(Vec::new());
+        let processor = QP::with_configs(&configs, Vec::new()).with_fs(RecordedFs(&reads));
+        let rebuild = || {
+            reads.lock().unwrap().clear();
+            processor.rebuild_cache().expect("Failed to rebuild");
+            reads.lock().unwrap().clone()
+        };
+        let deep = dir.join("apps/empty/deep");
+        assert!(rebuild().contains(&deep));
+        let walked = rebuild();
+        assert!(walked.contains(&dir.join("apps")));
+        assert!(!walked.contains(&dir.join("apps/empty")));
+        assert!(!walked.contains(&deep));
+        assert!(!rebuild().contains(&deep));
+        fixture::create_bundles(&deep, &["Anchor.app"]);
+        assert!(rebuild().contains(&deep));
+        assert_eq!(processor.query_services("anchor")[0].title, "Anchor");
+    }
+
+    #[test]
+    fn test_rebuild_finds_unignored_bundles() {
+        let dir = fixture::temp_dir("rebuild_finds_unignored_bundles");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "apps/old/Anchor.app"]);
+        std::fs::create_dir_all(dir.join("apps/empty")).unwrap();
+        let content = |ignored: &[PathBuf]| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir.join("apps")]);
+                value["internal"]["cacheDir"] = json!(dir.join("cache"));
+                value["configurable"]["ignorePaths"]["value"] = json!(ignored);
+            })
+        };
+        let ignored =
+            Configs::from(content(&[dir.join("apps/old/Anchor.app")])).expect("Invalid settings");
+        let processor = QP::with_configs(&ignored, Vec::new());
+        processor.rebuild_cache().expect("Failed to rebuild");
+        assert!(processor.query_services("anchor").is_empty());
+        let empty = processor
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.read_empty_folders().ok())
+            .expect("No empty folders");
+        assert!(empty.is_unchanged(&dir.join("apps/empty")));
+        assert!(!empty.is_unchanged(&dir.join("apps/old")));
+        let configs = Configs::from(content(&[])).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        processor.rebuild_cache().expect("Failed to rebuild");
+        assert_eq!(processor.query_services("anchor")[0].title, "Anchor");
+    }
+
+    #[test]
+    fn test_query_output_cache() {
+        struct CountedFs<'c>(&'c AtomicUsize);
+
+        impl Fs for CountedFs<'_> {
+            fn read_dir(&self, path: &Path) -> std::io::Result<Entries<'_>> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                StdFs.read_dir(path)
+            }
+        }
+
+        let dir = fixture::temp_dir("query_output_cache");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["internal"]["outputCacheSize"] = json!(2);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let reads = AtomicUsize::new(0);
+        let mut processor = QP::with_configs(&configs, Vec::new()).with_fs(CountedFs(&reads));
+        processor.query("a");
+        let first = std::mem::take(&mut processor.output);
+        let walked = reads.load(Ordering::Relaxed);
+        assert!(walked > 0);
+        processor.query("a");
+        assert_eq!(processor.output, first);
+        assert_eq!(reads.load(Ordering::Relaxed), walked);
+        processor
+            .record_selection(&Service::new(dir.join("Atlas.app")))
+            .expect("Failed to record");
+        processor.query("a");
+        assert!(reads.load(Ordering::Relaxed) > walked);
+    }
+
+    #[test]
+    fn test_query_empty_results() {
+        let dir = fixture::temp_dir("query_empty_results");
+        fixture::create_bundles(&dir, &["Atlas.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let mut processor = QP::with_configs(&configs, Vec::new());
+        processor.query("zzz");
+        assert_eq!(processor.output, Vec::<u8>::new());
+        processor.query("zzz");
+        assert_eq!(processor.output, Vec::<u8>::new());
+        assert_eq!(processor.query_json("zzz", false).unwrap(), "[]");
+        assert_eq!(processor.query_json("zzz", true).unwrap(), "[]");
+        assert_eq!(processor.query_paths_nul("zzz"), Vec::<u8>::new());
+        assert!(processor
+            .try_query("zzz")
+            .expect("Failed to query")
+            .is_empty());
+        processor.query("atlas");
+        assert!(processor.output.ends_with(b"\n"));
+    }
+
+    #[test]
+    fn test_query_max_output_bytes() {
+        let dir = fixture::temp_dir("query_max_output_bytes");
+        let names = (0..20)
+            .map(|index| format!("Anchor {:02} {}.app", index, "Long Name ".repeat(10)))
+            .collect::<Vec<_>>();
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        fixture::create_bundles(&dir, &names);
+        let pinned = dir.join(names[19]);
+        let content = |limit: Option<usize>| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["internal"]["cacheDir"] = json!(null);
+                value["internal"]["maxOutputBytes"] = json!(limit);
+                value["configurable"]["pinnedApps"]["value"] = json!([pinned]);
+            })
+        };
+        let output = |limit: Option<usize>| {
+            let configs = Configs::from(content(limit)).expect("Invalid settings");
+            let mut processor = QP::with_configs(&configs, Vec::new());
+            processor.query("anchor");
+            String::from_utf8(processor.output).expect("Failed to parse")
+        };
+        let full = output(None);
+        let capped = output(Some(1024));
+        assert!(capped.len() <= 1024);
+        assert!(capped.lines().count() > 0);
+        assert!(capped.lines().count() < full.lines().count());
+        assert!(full.starts_with(&capped));
+        assert!(capped.lines().next().unwrap().contains("Anchor 19"));
+    }
+
+    #[test]
+    fn test_rebuild_cache_with_progress() {
+        let dir = fixture::temp_dir("query_rebuild_cache_with_progress");
+        fixture::create_bundles(&dir, &["one/Atlas.app", "two/Anchor.app", "two/Boat.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("one"), dir.join("two")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let mut progress = Vec::new();
+        processor
+            .rebuild_cache_with_progress(|reported| progress.push(reported))
+            .expect("Failed to rebuild");
+        let expected =
+            [(1, 2, 1), (2, 2, 3)].map(|(roots_walked, roots, services)| RebuildProgress {
+                roots_walked,
+                roots,
+                services,
+            });
+        assert_eq!(progress, expected);
+    }
+
+    #[test]
+    fn test_warm_and_verify() {
+        let dir = fixture::temp_dir("query_warm_and_verify");
+        fixture::create_bundles(
+            &dir,
+            &["apps/Atlas.app", "apps/Anchor.app", "apps/Boat.app"],
+        );
+        let content = |canary: Option<&str>| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir.join("apps")]);
+                value["internal"]["cacheDir"] = json!(dir.join("cache"));
+                value["internal"]["startupCanaryQuery"] = json!(canary);
+            })
+        };
+        let configs = Configs::from(content(None)).expect("Invalid settings");
+        assert_eq!(
+            QP::with_configs(&configs, Vec::new()).warm_and_verify(),
+            None
+        );
+        let configs = Configs::from(content(Some("a"))).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.warm_and_verify(), Some(2));
+        assert!(dir.join("cache").exists());
+    }
+
+    #[test]
+    fn test_metrics() {
+        let dir = fixture::temp_dir("query_metrics");
+        fixture::create_bundles(
+            &dir,
+            &["apps/Atlas.app", "apps/Anchor.app", "apps/Boat.app"],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+            value["internal"]["queryCacheSize"] = json!(4);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.metrics(), Metrics::default());
+        processor.query_services("a");
+        let metrics = processor.metrics();
+        assert_eq!(metrics.queries, 1);
+        assert_eq!(metrics.cache_hits, 0);
+        assert_eq!(metrics.cache_rebuilds, 1);
+        assert_eq!(metrics.bundles_discovered, 3);
+        processor.query_services("b");
+        processor.query_services("a");
+        let metrics = processor.metrics();
+        assert_eq!(metrics.queries, 3);
+        assert_eq!(metrics.cache_hits, 2);
+        assert_eq!(metrics.cache_rebuilds, 1);
+        assert_eq!(metrics.bundles_discovered, 3);
+        assert!(metrics.average_latency > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_metrics_checker_terminations() {
+        let dir = fixture::temp_dir("query_metrics_terminations");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "apps/Atlas.app",
+                "apps/.Hidden.app",
+                "apps/.cache/Anchor.app",
+                "apps/Old/Boat.app",
+                "apps/Older.app",
+            ],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["ignorePaths"]["value"] =
+                json!([dir.join("apps/Old"), dir.join("apps/Older.app")]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert!(processor.metrics().checker_terminations.is_empty());
+        assert_eq!(processor.query_services("a").len(), 1);
+        let terminations = processor.metrics().checker_terminations;
+        assert_eq!(terminations.get("hidden"), Some(&2));
+        assert_eq!(terminations.get("ignore"), Some(&2));
+        assert_eq!(terminations.get("symlink"), None);
+    }
+
+    #[test]
+    fn test_query_verify_exists() {
+        let dir = fixture::temp_dir("query_verify_exists");
+        fixture::create_bundles(&dir, &["apps/Atlas.app", "apps/Anchor.app"]);
+        let content = |verified: bool| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir.join("apps")]);
+                value["internal"]["cacheDir"] = json!(dir.join("cache"));
+                value["configurable"]["verifyExists"]["value"] = json!(verified);
+            })
+        };
+        let configs = Configs::from(content(false)).expect("Invalid settings");
+        assert_eq!(
+            QP::with_configs(&configs, Vec::new())
+                .query_services("a")
+                .len(),
+            2
+        );
+        std::fs::remove_dir_all(dir.join("apps/Anchor.app")).unwrap();
+        assert_eq!(
+            QP::with_configs(&configs, Vec::new())
+                .query_services("a")
+                .len(),
+            2
+        );
+        let configs = Configs::from(content(true)).expect("Invalid settings");
+        let services = QP::with_configs(&configs, Vec::new()).query_services("a");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Atlas");
+    }
+
+    #[test]
+    fn test_try_query() {
+        struct DeniedFs;
+
+        impl Fs for DeniedFs {
+            fn read_dir(&self, path: &Path) -> std::io::Result<Entries<'_>> {
+                if path.ends_with("denied") {
+                    return Err(ErrorKind::PermissionDenied.into());
+                }
+                StdFs.read_dir(path)
+            }
+        }
+
+        let dir = fixture::temp_dir("query_try_query");
+        fixture::create_bundles(&dir, &["apps/Atlas.app"]);
+        let content = |paths: Vec<PathBuf>| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!(paths);
+                value["internal"]["cacheDir"] = json!(dir.join("cache"));
+            })
+        };
+        let missing = dir.join("missing");
+        let configs = Configs::from(content(vec![dir.join("apps"), missing.clone()]))
+            .expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        match processor.try_query("a") {
+            Err(QueryError::Io { path, source }) => {
+                assert_eq!(path, missing);
+                assert_eq!(source.kind(), ErrorKind::NotFound);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(processor.query_services("a").len(), 1);
+        let configs = Configs::from(content(vec![dir.join("apps")])).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.try_query("a").expect("Failed to query").len(), 1);
+        std::fs::write(processor.cache.as_ref().unwrap().path(), b"invalid").unwrap();
+        let error = processor.try_query("a").unwrap_err();
+        assert!(matches!(error, QueryError::Deserialize(_)));
+        let configs = Configs::from(content(Vec::new())).expect("Invalid settings");
+        let error = QP::with_configs(&configs, Vec::new()).try_query("a");
+        assert!(matches!(error, Err(QueryError::Config(_))));
+
+        std::fs::create_dir_all(dir.join("denied")).unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps"), dir.join("denied")]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new()).with_fs(DeniedFs);
+        for _ in 0..2 {
+            match processor.try_query("a") {
+                Err(QueryError::Io { path, source }) => {
+                    assert_eq!(path, dir.join("denied"));
+                    assert_eq!(source.kind(), ErrorKind::PermissionDenied);
+                }
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+        assert_eq!(processor.query_services("a").len(), 1);
+    }
+
+    #[test]
+    fn test_query_paths_nul() {
+        let dir = fixture::temp_dir("query_paths_nul");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor Tool.app", "Boat.app"]);
+        let content = get_content_with(|value| value["internal"]["paths"] = json!([dir]));
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let output = processor.query_paths_nul("a");
+        assert_eq!(output.last(), Some(&0));
+        let mut paths = output[..output.len() - 1]
+            .split(|byte| *byte == 0)
+            .map(|path| PathBuf::from(String::from_utf8(path.to_vec()).unwrap()))
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, [dir.join("Anchor Tool.app"), dir.join("Atlas.app")]);
+        assert!(processor.query_paths_nul("x").is_empty());
+    }
+
+    #[test]
+    fn test_query_corrections() {
+        let dir = fixture::temp_dir("query_corrections");
+        fixture::create_bundles(&dir, &["Google Chrome.app", "Calendar.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["corrections"]["value"] = json!({"chorme": "chrome"});
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("Chorme");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Google Chrome");
+        assert_eq!(processor.query_services("google chorme").len(), 1);
+        assert!(processor.query_services("chor").is_empty());
+    }
+
+    #[test]
+    fn test_query_anchored_first_char() {
+        let dir = fixture::temp_dir("query_anchored_first_char");
+        fixture::create_bundles(&dir, &["Books.app", "Audio Books.app"]);
+        let content = |anchored: bool| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["configurable"]["anchoredFirstChar"]["value"] = json!(anchored);
+            })
+        };
+        let configs = Configs::from(content(false)).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert_eq!(processor.query_services("b").len(), 2);
+        let configs = Configs::from(content(true)).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("b");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Books");
+    }
+
+    #[test]
+    fn test_query_path_fallback() {
+        let dir = fixture::temp_dir("query_path_fallback");
+        fixture::create_bundles(&dir, &["System/Atlas.app", "Boat.app"]);
+        let content = |fallback: bool| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["configurable"]["pathFallback"]["value"] = json!(fallback);
+            })
+        };
+        let configs = Configs::from(content(false)).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert!(processor.query_services("system").is_empty());
+        let configs = Configs::from(content(true)).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("system");
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].title, "Atlas");
+        assert_eq!(processor.query_services("boat")[0].title, "Boat");
+    }
+
+    #[test]
+    fn test_query_path_fragments() {
+        let dir = fixture::temp_dir("query_path_fragments");
+        fixture::create_bundles(
+            &dir,
+            &["System/Applications/Books.app", "Applications/Atlas.app"],
+        );
+        let content = |enabled: bool| {
+            get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["configurable"]["pathFragments"]["value"] = json!(enabled);
+            })
+        };
+        let configs = Configs::from(content(true)).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("applications/books");
+        assert_eq!(services.len(), 1);
+        let books = dir.join("System/Applications/Books.app");
+        assert_eq!(services[0].id(), books);
+        let ids = |services: Vec<Service>| {
+            services
+                .iter()
+                .map(|service| service.id().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+        let ranked = processor.query_ranked("applications/books");
+        assert_eq!(
+            ids(ranked.into_iter().map(|ranked| ranked.service).collect()),
+            [books.as_path()]
+        );
+        let top = processor.query_top("applications/books", 5);
+        assert_eq!(
+            ids(top.into_iter().map(|ranked| ranked.service).collect()),
+            [books.as_path()]
+        );
+        assert_eq!(
+            ids(processor.query_top_k("applications/books", 5)),
+            [books.as_path()]
+        );
+        let options = QueryOptions {
+            bypass_cache: true,
+            ..QueryOptions::default()
+        };
+        assert_eq!(
+            ids(processor.query_with("applications/books", &options)),
+            [books.as_path()]
+        );
+        assert_eq!(
+            ids(processor.prepare().query("applications/books")),
+            [books.as_path()]
+        );
+        assert!(processor.query_services("books/applications").is_empty());
+        assert_eq!(processor.query_services("/atlas")[0].title, "Atlas");
+        let configs = Configs::from(content(false)).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        assert!(processor.query_services("applications/books").is_empty());
+    }
+
+    #[test]
+    fn test_query_custom_checker() {
+        let dir = fixture::temp_dir("query_custom_checker");
+        fixture::create_bundles(&dir, &["Atlas.app", "Atlas Beta.app", ".Anchor.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["configurable"]["checkers"]["value"] = json!(["ignore", "symlink", "beta"]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let registry = CheckerRegistry::new()
+            .register("beta", |path: &Path| {
+                path.to_string_lossy().contains("Beta")
+            })
+            .register("unused", |_: &Path| true);
+        let processor = QP::with_configs(&configs, Vec::new()).with_checkers(registry);
+        let mut titles = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, [".Anchor", "Atlas"]);
+    }
+
+    #[test]
+    fn test_query_panicking_checker() {
+        let dir = fixture::temp_dir("query_panicking_checker");
+        let steady = dir.join("Steady");
+        let broken = dir.join("Broken");
+        fixture::create_bundles(&steady, &["Atlas.app"]);
+        fixture::create_bundles(&broken, &["Anchor.app", "Fragile/Arrow.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([broken, steady]);
+            value["configurable"]["checkers"]["value"] = json!(["ignore", "symlink", "fragile"]);
+            value["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let registry = CheckerRegistry::new().register("fragile", |path: &Path| {
+            if path.ends_with("Fragile") {
+                panic!("Unexpected path: {}", path.display())
+            }
+            false
+        });
+        let processor = QP::with_configs(&configs, Vec::new()).with_checkers(registry);
+        let titles = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<std::collections::BTreeSet<_>>();
+        assert!(titles.contains("Atlas"));
+        assert!(!titles.contains("Arrow"));
+        assert_eq!(processor.truncations.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_query_filter_only_checker() {
+        let dir = fixture::temp_dir("query_filter_only_checker");
+        fixture::create_bundles(&dir, &["Suite.app/Atlas.app", "Bundle.app/Anchor.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["checkers"]["value"] = json!(["suite", "bundle"]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let is_named = |name: &'static str| {
+            move |path: &Path| path.file_name().is_some_and(|file_name| file_name == name)
+        };
+        let registry = CheckerRegistry::new()
+            .register_with_mode("suite", CheckerMode::FilterOnly, is_named("Suite.app"))
+            .register("bundle", is_named("Bundle.app"));
+        let processor = QP::with_configs(&configs, Vec::new()).with_checkers(registry);
+        let titles = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Atlas"]);
+        assert_eq!(
+            processor.classify(&dir.join("Suite.app")),
+            Classification::Ignored
+        );
+    }
+
+    #[test]
+    fn test_query_ignore_overrides() {
+        let dir = fixture::temp_dir("query_ignore_overrides");
+        fixture::create_bundles(&dir, &["Atlas.app", "old/Anchor.app", "old/Archive.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["ignorePaths"]["value"] = json!([dir.join("old")]);
+            value["configurable"]["allowOverridesIgnore"]["value"] =
+                json!([dir.join("old/Anchor.app")]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let mut titles = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, ["Anchor", "Atlas"]);
+    }
+
+    #[test]
+    fn test_query_without_enrichment() {
+        let dir = fixture::temp_dir("query_without_enrichment");
+        let properties = [
+            ("CFBundleIdentifier", "com.apple.calculator"),
+            ("CFBundleDisplayName", "Calculatrice"),
+        ];
+        fixture::create_info_plist(dir.join("Calculator.app"), &properties);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let bare = QueryOptions {
+            enrich: false,
+            ..QueryOptions::default()
+        };
+        let services = processor.query_with("calc", &bare);
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].id(), dir.join("Calculator.app"));
+        assert_eq!(services[0].title, "Calculator");
+        assert_eq!(services[0].bundle_id(), None);
+        assert_eq!(services[0].localized_name(), None);
+        let services = processor.query_with("calc", &QueryOptions::default());
+        assert_eq!(services[0].bundle_id(), Some("com.apple.calculator"));
+        assert_eq!(services[0].localized_name(), Some("Calculatrice"));
+    }
+
+    #[test]
+    fn test_query_only_approved() {
+        let dir = fixture::temp_dir("query_only_approved");
+        fixture::create_bundles(&dir, &["Atlas.app", "Anchor.app"]);
+        xattr::set(dir.join("Anchor.app"), QUARANTINE_ATTRIBUTE, b"0081;").unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["hideQuarantined"]["value"] = json!("onlyApproved");
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let titles = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Atlas"]);
+    }
+
+    #[test]
+    fn test_query_strict_bundles() {
+        let dir = fixture::temp_dir("query_strict_bundles");
+        fixture::create_bundles(
+            &dir,
+            &["Real.app/Contents", "Artifact.app/Nested.app/Contents"],
+        );
+        let titles = |strict: bool| {
+            let content = get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["internal"]["cacheDir"] = json!(null);
+                value["configurable"]["strictBundles"]["value"] = json!(strict);
+            });
+            let configs = Configs::from(content).expect("Invalid settings");
+            let mut titles = QP::with_configs(&configs, Vec::new())
+                .query_services("")
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>();
+            titles.sort();
+            titles
+        };
+        assert_eq!(titles(false), ["Artifact", "Real"]);
+        assert_eq!(titles(true), ["Nested", "Real"]);
+    }
+
+    #[test]
+    fn test_query_non_recursive_root() {
+        let dir = fixture::temp_dir("query_non_recursive_root");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "flat/Atlas.app",
+                "flat/sub/Anchor.app",
+                "nested/Arc.app",
+                "nested/sub/Axe.app",
+            ],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([
+                {"path": dir.join("flat"), "recursive": false},
+                dir.join("nested"),
+            ]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let mut titles = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, ["Arc", "Atlas", "Axe"]);
+    }
+
+    #[test]
+    fn test_query_leaf_checkers() {
+        let dir = fixture::temp_dir("query_leaf_checkers");
+        fixture::create_bundles(&dir, &["Atlas.app", "Archive.tool/Contents", "Assets"]);
+        std::fs::write(dir.join("Assistant.desktop"), "").unwrap();
+        std::fs::write(dir.join("Assets/Anchor.txt"), "").unwrap();
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let desktop: LeafChecker =
+            Box::new(|path: &Path| path.extension().is_some_and(|ext| ext == "desktop"));
+        let tool: LeafChecker =
+            Box::new(|path: &Path| path.extension().is_some_and(|ext| ext == "tool"));
+        let processor =
+            QP::with_configs(&configs, Vec::new()).with_leaf_checkers(vec![desktop, tool]);
+        let mut titles = processor
+            .query_services("a")
+            .into_iter()
+            .map(|service| service.title)
+            .collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, ["Archive", "Assistant", "Atlas"]);
+    }
+
+    #[test]
+    fn test_query_synonyms() {
+        let dir = fixture::temp_dir("query_synonyms");
+        fixture::create_bundles(&dir, &["System Settings.app", "System Preferences.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let names = |req: &str| {
+            processor
+                .query_services(req)
+                .iter()
+                .map(|service| service.id().file_name().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert!(names("preferences").contains(&"System Settings.app".into()));
+        assert!(names("settings").contains(&"System Preferences.app".into()));
+    }
+
+    #[test]
+    fn test_query_services_scored() {
+        let dir = fixture::temp_dir("query_services_scored");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "Sticky Notes.app",
+                "Notes Archive.app",
+                "Notepad.app",
+                "Notes.app",
+                "Cool Orange Dark Editor.app",
+                "Code Editor.app",
+            ],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let titles = |req: &str| {
+            processor
+                .query_services(req)
+                .into_iter()
+                .map(|service| service.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles("notes"), ["Notes", "Notes Archive", "Sticky Notes"]);
+        assert_eq!(
+            titles("note"),
+            ["Notes", "Notepad", "Notes Archive", "Sticky Notes"]
+        );
+        assert_eq!(titles("coded"), ["Code Editor", "Cool Orange Dark Editor"]);
+    }
+
+    #[test]
+    fn test_query_typos() {
+        let dir = fixture::temp_dir("query_typos");
+        fixture::create_bundles(
+            &dir,
+            &["Calendar.app", "Calculator.app", "Calender Pro.app"],
+        );
+        let names = |max_edit_distance: usize| {
+            let content = get_content_with(|value| {
+                value["internal"]["paths"] = json!([dir]);
+                value["internal"]["cacheDir"] = json!(null);
+                value["configurable"]["maxEditDistance"]["value"] = json!(max_edit_distance);
+            });
+            let configs = Configs::from(content).expect("Invalid settings");
+            let processor = QP::with_configs(&configs, Vec::new());
+            let titles = processor
+                .query_services("calender")
+                .iter()
+                .map(|service| service.title.clone())
+                .collect::<Vec<_>>();
+            titles
+        };
+        assert_eq!(names(1), ["Calender Pro", "Calendar"]);
+        assert_eq!(names(0), ["Calender Pro"]);
+    }
+
+    #[test]
+    fn test_query_root_labels() {
+        let dir = fixture::temp_dir("query_root_labels");
+        fixture::create_bundles(&dir, &["system/Books.app", "user/Bookmarks.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([
+                {"path": dir.join("system"), "label": "System"},
+                dir.join("user"),
+            ]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let services = processor.query_services("book");
+        let label = |title: &str| {
+            services
+                .iter()
+                .find(|service| service.title == title)
+                .expect("Not found")
+                .label()
+        };
+        assert_eq!(label("Books"), Some("System"));
+        assert_eq!(label("Bookmarks"), None);
+        assert!(processor.query_services("system").is_empty());
+    }
+
+    #[test]
+    fn test_inventory() {
+        let dir = fixture::temp_dir("inventory");
+        fixture::create_bundles(
+            &dir,
+            &["apps/Zoo.app", "apps/Atlas.app", "apps/tools/Books.app"],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps/tools"), dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let inventory = processor.inventory().into_iter().collect::<Vec<_>>();
+        assert_eq!(
+            inventory,
+            [
+                dir.join("apps/Atlas.app"),
+                dir.join("apps/Zoo.app"),
+                dir.join("apps/tools/Books.app"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_top_exact() {
+        let dir = fixture::temp_dir("query_top_exact");
+        fixture::create_bundles(
+            &dir,
+            &[
+                "first/Notes.app",
+                "second/Notes Pro.app",
+                "second/Notes Archive.app",
+            ],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("first"), dir.join("second")]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let titles = |ranked: Vec<RankedService>| {
+            ranked
+                .into_iter()
+                .map(|ranked| ranked.service.title)
+                .collect::<Vec<_>>()
+        };
+        let mut ranked = processor.query_ranked("notes");
+        ranked.sort_by_key(|ranked| Reverse(ranked.score));
+        ranked.truncate(1);
+        let scored = processor.metrics().services_scored;
+        let top = processor.query_top("notes", 1);
+        assert_eq!(titles(top), titles(ranked));
+        // Notes is walked first, so no service after it runs through the matcher
+        assert_eq!(processor.metrics().services_scored - scored, 1);
+        let scored = processor.metrics().services_scored;
+        let top = processor.query_top("note", 2);
+        assert_eq!(titles(top), ["Notes", "Notes Pro"]);
+        assert_eq!(processor.metrics().services_scored - scored, 3);
+        // A pin may move a later exact match ahead, so every service is scored
+        let pinned = dir.join("second/Notes.app");
+        fixture::create_bundles(&dir, &["second/Notes.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("first"), dir.join("second")]);
+            value["internal"]["cacheDir"] = json!(null);
+            value["configurable"]["pinnedApps"]["value"] = json!([pinned]);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        let top = processor.query_top("notes", 1);
+        assert_eq!(top[0].service.id(), pinned);
+        assert_eq!(processor.metrics().services_scored, 4);
+    }
+
+    #[test]
+    fn test_query_localized_names() {
+        let dir = fixture::temp_dir("query_localized_names");
+        let name = ("CFBundleDisplayName", "Calculatrice");
+        fixture::create_info_plist(dir.join("Calculator.app"), &[name]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir]);
+            value["internal"]["cacheDir"] = json!(null);
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        for req in ["calc", "calcul"] {
+            let ranked = processor.query_ranked(req);
+            assert_eq!(ranked.len(), 1, "{}", req);
+            assert_eq!(ranked[0].service.title, "Calculator");
+            assert_eq!(ranked[0].service.localized_name(), Some("Calculatrice"));
+            assert_eq!(ranked[0].matched_field, MatchField::Name);
+        }
+        let ranked = processor.query_ranked("calculatri");
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].matched_field, MatchField::LocalizedName);
+        assert_eq!(ranked[0].ranges, vec![0..10]);
+        let exact = processor.query_ranked("calculatrice");
+        assert_eq!(exact[0].score, EXACT_SCORE);
+        assert_eq!(exact[0].matched_field, MatchField::LocalizedName);
+    }
+
+    #[test]
+    fn test_query_shared_across_threads() {
+        fn assert_shareable<T: Send + Sync>(_: &T) {}
+
+        let dir = fixture::temp_dir("query_shared_across_threads");
+        fixture::create_bundles(
+            &dir,
+            &["apps/Atlas.app", "apps/Anchor.app", "apps/Boat.app"],
+        );
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+            value["internal"]["queryCacheSize"] = json!(2);
+        });
+        let configs: &'static Configs =
+            Box::leak(Box::new(Configs::from(content).expect("Invalid settings")));
+        let processor = std::sync::Arc::new(QP::with_configs(configs, Vec::new()));
+        assert_shareable(&processor);
+        let handles = (0..8)
+            .map(|index| {
+                let processor = std::sync::Arc::clone(&processor);
+                std::thread::spawn(move || {
+                    let req = ["a", "b", "an", "boat"][index % 4];
+                    (0..10)
+                        .map(|_| processor.query_services(req).len())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        for (index, handle) in handles.into_iter().enumerate() {
+            let expected = [2, 1, 1, 1][index % 4];
+            let counts = handle.join().expect("Query panicked");
+            assert!(counts.iter().all(|count| *count == expected));
+        }
+        assert_eq!(processor.rebuilds.load(Ordering::Relaxed), 1);
+        assert_eq!(processor.metrics().queries, 80);
+    }
+
+    #[test]
+    fn test_refresh_cache_changed_only() {
+        let dir = fixture::temp_dir("refresh_cache_changed_only");
+        for name in ["Atlas", "Books"] {
+            let bundle = dir.join(format!("apps/{}.app", name));
+            fixture::create_info_plist(&bundle, &[("CFBundleShortVersionString", "1.0")]);
+        }
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        processor.rebuild_cache().expect("Failed to rebuild");
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        for name in ["Atlas", "Books"] {
+            let bundle = dir.join(format!("apps/{}.app", name));
+            let modified = bundle
+                .join("Contents/Info.plist")
+                .metadata()
+                .unwrap()
+                .modified();
+            fixture::create_info_plist(&bundle, &[("CFBundleShortVersionString", "2.0")]);
+            let modified = match name {
+                "Atlas" => modified.unwrap(),
+                _ => an_hour_ago,
+            };
+            File::options()
+                .write(true)
+                .open(bundle.join("Contents/Info.plist"))
+                .and_then(|plist| plist.set_modified(modified))
+                .expect("Failed to set modified time");
+        }
+        processor.refresh_cache().expect("Failed to refresh");
+        let cached = processor.cache.as_ref().unwrap().bunch_read().unwrap();
+        let version = |title: &str| {
+            let service = cached
+                .iter()
+                .find(|service| service.title == title)
+                .unwrap();
+            service.version().map(str::to_owned)
+        };
+        assert_eq!(version("Atlas").as_deref(), Some("1.0"));
+        assert_eq!(version("Books").as_deref(), Some("2.0"));
+    }
+
+    #[test]
+    fn test_spawn_refresher() {
+        let dir = fixture::temp_dir("spawn_refresher");
+        fixture::create_bundles(&dir, &["apps/Atlas.app"]);
+        let content = get_content_with(|value| {
+            value["internal"]["paths"] = json!([dir.join("apps")]);
+            value["internal"]["cacheDir"] = json!(dir.join("cache"));
+        });
+        let configs = Configs::from(content).expect("Invalid settings");
+        let processor = QP::with_configs(&configs, Vec::new());
+        std::thread::scope(|scope| {
+            let refresher = processor.spawn_refresher(scope, Duration::from_millis(10));
+            let deadline = SystemTime::now() + Duration::from_secs(5);
+            while processor.rebuilds.load(Ordering::Relaxed) == 0 && SystemTime::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            refresher.stop();
+        });
+        assert!(processor.rebuilds.load(Ordering::Relaxed) >= 1);
+        let cached = processor.cache.as_ref().unwrap().bunch_read().unwrap();
+        assert_eq!(cached[0].title, "Atlas");
     }
 }
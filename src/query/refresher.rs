@@ -0,0 +1,21 @@
+use std::sync::mpsc::Sender;
+use std::thread::ScopedJoinHandle;
+
+/// Handle of a background thread refreshing the cache periodically.
+/// The thread stops once the handle is stopped or dropped
+pub struct Refresher<'s> {
+    stop: Sender<()>,
+    handle: ScopedJoinHandle<'s, ()>,
+}
+
+impl<'s> Refresher<'s> {
+    pub(super) fn new(stop: Sender<()>, handle: ScopedJoinHandle<'s, ()>) -> Self {
+        Refresher { stop, handle }
+    }
+
+    /// Stop refreshing, and wait for the ongoing refresh to finish
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.handle.join();
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Checker excluding the paths it returns `true` for
+pub type CustomChecker = Box<dyn Fn(&Path) -> bool + Send + Sync>;
+
+/// Checker recognizing the paths it returns `true` for as services, like bundles,
+/// so they are never walked into
+pub type LeafChecker = Box<dyn Fn(&Path) -> bool + Send + Sync>;
+
+/// Names of the checkers built into `Checker`, which can be disabled by config
+pub const BUILTIN_CHECKERS: [&str; 3] = ["hidden", "ignore", "symlink"];
+
+/// How a custom checker treats the paths it returns `true` for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CheckerMode {
+    /// Exclude the path and never walk into it
+    #[default]
+    Terminate,
+    /// Exclude the path from results, but still walk into it for services below
+    FilterOnly,
+}
+
+/// Custom checkers registered by name, enabled by listing their names in config
+#[derive(Default)]
+pub struct CheckerRegistry {
+    checkers: HashMap<String, (CheckerMode, CustomChecker)>,
+}
+
+impl CheckerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the checker under the name as terminating, replacing any checker
+    /// registered before
+    pub fn register<S: Into<String>, F>(self, name: S, checker: F) -> Self
+    where
+        F: Fn(&Path) -> bool + Send + Sync + 'static,
+    {
+        self.register_with_mode(name, CheckerMode::Terminate, checker)
+    }
+
+    /// Register the checker under the name in the mode, replacing any checker registered before
+    pub fn register_with_mode<S: Into<String>, F>(
+        mut self,
+        name: S,
+        mode: CheckerMode,
+        checker: F,
+    ) -> Self
+    where
+        F: Fn(&Path) -> bool + Send + Sync + 'static,
+    {
+        self.checkers.insert(name.into(), (mode, Box::new(checker)));
+        self
+    }
+
+    /// Take the checkers enabled by the names, in the order they are listed,
+    /// split into terminating and filtering only checkers
+    pub(super) fn into_enabled(
+        mut self,
+        names: &[String],
+    ) -> (Vec<CustomChecker>, Vec<CustomChecker>) {
+        let mut terminating = Vec::new();
+        let mut filtering = Vec::new();
+        for name in names
+            .iter()
+            .filter(|name| !BUILTIN_CHECKERS.contains(&name.as_str()))
+        {
+            match self.checkers.remove(name) {
+                Some((CheckerMode::Terminate, checker)) => terminating.push(checker),
+                Some((CheckerMode::FilterOnly, checker)) => filtering.push(checker),
+                None => eprintln!("Unknown checker: {}", name),
+            }
+        }
+        (terminating, filtering)
+    }
+}
@@ -0,0 +1,59 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Accessibility of a configured root
+#[derive(Debug, Clone)]
+pub struct RootReport {
+    pub path: PathBuf,
+    pub readable: bool,
+}
+
+/// Summary of problems found in the configs, roots and cache
+#[derive(Debug, Clone, Default)]
+pub struct SelfCheckReport {
+    pub roots: Vec<RootReport>,
+    /// If the cache folder is writable, none if caching is disabled
+    pub cache_writable: Option<bool>,
+    /// If the existing cache is valid, none if nothing is cached
+    pub cache_readable: Option<bool>,
+    pub warnings: Vec<String>,
+}
+
+impl SelfCheckReport {
+    /// Check if no problem is found
+    pub fn is_healthy(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// Check if the root exists and its content can be listed
+    pub(super) fn check_root(&mut self, path: &Path) {
+        let readable = match path.is_dir() {
+            true => fs::read_dir(path).is_ok(),
+            false => path.exists(),
+        };
+        if !readable {
+            self.warnings
+                .push(format!("Root is not readable: {}", path.display()));
+        }
+        self.roots.push(RootReport {
+            path: path.to_path_buf(),
+            readable,
+        });
+    }
+
+    /// Check if files can be created in the cache folder
+    pub(super) fn check_cache_dir(&mut self, dir: &Path) {
+        let probe = dir.join(format!(".self-check-{}", std::process::id()));
+        let writable = fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&probe, b""))
+            .and_then(|_| fs::remove_file(&probe));
+        if let Err(error) = &writable {
+            self.warnings.push(format!(
+                "Cache folder is not writable: {}: {}",
+                dir.display(),
+                error
+            ));
+        }
+        self.cache_writable = Some(writable.is_ok());
+    }
+}
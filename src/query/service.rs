@@ -1,9 +1,50 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
 
 use super::matcher;
+use crate::configurator::MatchField;
+use crate::execute;
+use crate::query::bundle_info::{self, BundleInfo};
+use crate::utils::{compare_versions, stable_hash};
+
+/// Markers in paths of copies built only for architectures other than this machine's
+#[cfg(target_arch = "aarch64")]
+const FOREIGN_ARCH_MARKERS: [&str; 3] = ["x86_64", "x86-64", "intel"];
+#[cfg(not(target_arch = "aarch64"))]
+const FOREIGN_ARCH_MARKERS: [&str; 3] = ["arm64", "aarch64", "apple silicon"];
+
+/// Strip a trailing version separated by space, `-` or `_`, e.g. `Xcode-14.3` to `Xcode`
+fn strip_version(name: &str) -> &str {
+    let trimmed = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if !name[trimmed.len()..].starts_with(|c: char| c.is_ascii_digit()) {
+        return name;
+    }
+    let trimmed = trimmed
+        .strip_suffix(|c: char| c == 'v' || c == 'V')
+        .filter(|trimmed| trimmed.ends_with([' ', '-', '_']))
+        .unwrap_or(trimmed);
+    match trimmed.strip_suffix([' ', '-', '_']) {
+        Some(stripped) if !stripped.trim().is_empty() => stripped.trim_end(),
+        _ => name,
+    }
+}
+
+/// Receipt App Store apps carry in their bundles
+const APP_STORE_RECEIPT: &str = "Contents/_MASReceipt/receipt";
+
+/// Metadata key of the label of the root the service is found under
+pub const LABEL_KEY: &str = "label";
+
+/// Metadata key of how deep below its root the service is found, 1 for entries of the root
+pub const DEPTH_KEY: &str = "depth";
 
 fn map_term(name: &str) -> String {
     crate::CONFIG
@@ -13,25 +54,323 @@ fn map_term(name: &str) -> String {
         .unwrap_or(matcher::tokenize(name).join(" "))
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
 pub struct Service {
     pub title: String,
     subtitle: PathBuf,
     id: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    parent: Option<PathBuf>,
+    #[serde(
+        rename = "localizedName",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    localized_name: Option<String>,
+    #[serde(rename = "bundleId", skip_serializing_if = "Option::is_none", default)]
+    bundle_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    executable: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    uid: Option<u64>,
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    metadata: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    signature: Option<u64>,
+    #[serde(
+        rename = "appStore",
+        skip_serializing_if = "std::ops::Not::not",
+        default
+    )]
+    app_store: bool,
+    #[serde(skip)]
+    cached: bool,
 }
 
 impl Service {
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
         let path = path.as_ref();
-        let title = Self::file_name(&path);
-        let subtitle = path.to_path_buf();
+        let service = Self::bare(path);
+        let title = &service.title;
+        let info = BundleInfo::read(path);
+        let localized_name = info
+            .as_ref()
+            .and_then(BundleInfo::display_name)
+            .filter(|name| name != title)
+            .map(String::from);
+        let bundle_id = info
+            .as_ref()
+            .and_then(|info| info.bundle_id().map(String::from));
+        let version = info
+            .as_ref()
+            .and_then(|info| info.version().map(String::from));
+        let category = info
+            .as_ref()
+            .and_then(|info| info.category().map(String::from));
+        let executable = info
+            .as_ref()
+            .and_then(|info| info.executable().map(String::from));
+        let signature = bundle_info::signature(path, executable.as_deref());
+        let app_store = path.join(APP_STORE_RECEIPT).is_file();
         Service {
-            title,
-            subtitle,
+            localized_name,
+            bundle_id,
+            version,
+            category,
+            executable,
+            signature,
+            app_store,
+            ..service
+        }
+    }
+
+    /// New service named after the bundle, without reading its `Info.plist`,
+    /// so it has no bundle id, version or any other property read from it
+    pub fn bare<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        Service {
+            title: Self::file_name(path),
+            subtitle: path.to_path_buf(),
             id: path.to_path_buf(),
+            parent: None,
+            localized_name: None,
+            bundle_id: None,
+            version: None,
+            category: None,
+            executable: None,
+            uid: None,
+            metadata: HashMap::new(),
+            signature: None,
+            app_store: false,
+            cached: false,
+        }
+    }
+
+    /// Set the folder containing this service
+    pub fn with_parent<P: AsRef<Path>>(mut self, parent: Option<P>) -> Self {
+        self.parent = parent.map(|parent| parent.as_ref().to_path_buf());
+        self
+    }
+
+    /// Get the folder this service is found in
+    pub fn parent(&self) -> Option<&Path> {
+        self.parent.as_deref()
+    }
+
+    /// Strip the trailing version from the title
+    pub fn without_version(mut self) -> Self {
+        self.title = strip_version(&self.title).to_owned();
+        self
+    }
+
+    /// Append the extension of the bundle to the title, e.g. `Books` to `Books.app`
+    pub fn with_bundle_extension(mut self) -> Self {
+        if let Some(extension) = self.id.extension().and_then(OsStr::to_str) {
+            self.title = format!("{}.{}", self.title, extension);
+        }
+        self
+    }
+
+    /// Get the displayed path
+    pub fn subtitle(&self) -> &Path {
+        &self.subtitle
+    }
+
+    /// Replace the displayed path
+    pub fn with_subtitle<P: AsRef<Path>>(mut self, subtitle: P) -> Self {
+        self.subtitle = subtitle.as_ref().to_path_buf();
+        self
+    }
+
+    /// Get the path of this service relative to the root, if it is under the root
+    pub fn relative_path<P: AsRef<Path>>(&self, root: P) -> Option<&Path> {
+        self.id.strip_prefix(root).ok()
+    }
+
+    /// Get the path identifying this service
+    pub fn id(&self) -> &Path {
+        &self.id
+    }
+
+    /// Get the value of the field to match queries against
+    pub fn field(&self, field: MatchField) -> Option<Cow<'_, str>> {
+        match field {
+            MatchField::Name => Some(Cow::Borrowed(&self.title)),
+            MatchField::LocalizedName => self.localized_name().map(Cow::Borrowed),
+            MatchField::BundleId => self.bundle_id().map(Cow::Borrowed),
+            MatchField::Executable => self.executable().map(Cow::Borrowed),
+            MatchField::FullPath => Some(self.id.to_string_lossy()),
+        }
+    }
+
+    /// Get the name shown to users if it differs from the title, e.g. `Calculatrice`
+    /// for `Calculator`. Queries match either of them
+    pub fn localized_name(&self) -> Option<&str> {
+        self.localized_name.as_deref()
+    }
+
+    pub fn bundle_id(&self) -> Option<&str> {
+        self.bundle_id.as_deref()
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Get the name of the binary the bundle launches
+    pub fn executable(&self) -> Option<&str> {
+        self.executable.as_deref()
+    }
+
+    /// Get the category type, e.g. `public.app-category.utilities`
+    pub fn category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+
+    /// Check if this service has a newer version than another one.
+    /// A service with a known version is newer than one without
+    pub fn is_newer_than(&self, another: &Service) -> bool {
+        match (&self.version, &another.version) {
+            (Some(version), Some(another)) => {
+                compare_versions(version, another) == Ordering::Greater
+            }
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+
+    /// Check if this service should be kept over another one sharing the dedup key.
+    /// If `native_preferred`, a copy for another architecture gives way to a universal
+    /// or native one regardless of versions, otherwise the newer version is preferred
+    pub fn is_preferred_to(&self, another: &Service, native_preferred: bool) -> bool {
+        if native_preferred {
+            match (self.is_foreign_arch(), another.is_foreign_arch()) {
+                (false, true) => return true,
+                (true, false) => return false,
+                _ => (),
+            }
+        }
+        self.is_newer_than(another)
+    }
+
+    /// Check if the path marks this service as built for another architecture
+    fn is_foreign_arch(&self) -> bool {
+        let path = self.id.to_string_lossy().to_lowercase();
+        FOREIGN_ARCH_MARKERS
+            .iter()
+            .any(|marker| path.contains(marker))
+    }
+
+    /// Get when the bundle was last accessed, or last modified if
+    /// the access time is unavailable
+    pub fn last_used(&self) -> Option<SystemTime> {
+        let metadata = self.id.metadata().ok()?;
+        metadata.accessed().or_else(|_| metadata.modified()).ok()
+    }
+
+    /// Get when the bundle was created, or last modified if the creation time is unavailable
+    pub fn installed(&self) -> Option<SystemTime> {
+        let metadata = self.id.metadata().ok()?;
+        metadata.created().or_else(|_| metadata.modified()).ok()
+    }
+
+    /// Check if the bundle is unchanged since this service is read from it,
+    /// so it needs no reading again. Bundles without signatures are never unchanged
+    pub fn is_unchanged(&self) -> bool {
+        self.signature.is_some()
+            && self.signature == bundle_info::signature(&self.id, self.executable.as_deref())
+    }
+
+    /// Get the signature of the bundle when this service is read from it, if it has one
+    pub fn signature(&self) -> Option<u64> {
+        self.signature
+    }
+
+    /// Drop the signature, so it is not written along with the service
+    pub fn without_signature(mut self) -> Self {
+        self.signature = None;
+        self
+    }
+
+    /// Set if this service is read from the cache, rather than from its bundle just now
+    pub fn with_cached(mut self, cached: bool) -> Self {
+        self.cached = cached;
+        self
+    }
+
+    /// Check if the bundle is installed from the App Store, having its receipt
+    pub fn is_app_store(&self) -> bool {
+        self.app_store
+    }
+
+    /// Check if this service is read from the cache
+    pub fn is_cached(&self) -> bool {
+        self.cached
+    }
+
+    /// Set the id that stays the same across queries, hashed from the dedup key
+    pub fn with_uid(mut self) -> Self {
+        self.uid = Some(stable_hash(self.dedup_key()));
+        self
+    }
+
+    /// Get the id that stays the same across queries, if it is set
+    pub fn uid(&self) -> Option<u64> {
+        self.uid
+    }
+
+    /// Attach the value to this service under the key, replacing the value already attached
+    pub fn with_metadata<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Get the values attached to this service by callers. Discovered services have
+    /// none, except the label of their root under `LABEL_KEY` and their depth under
+    /// `DEPTH_KEY` if recorded
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    /// Get the label of the root this service is found under, if the root has one
+    pub fn label(&self) -> Option<&str> {
+        self.metadata.get(LABEL_KEY).map(String::as_str)
+    }
+
+    /// Get the key identifying the same service, which is the bundle id
+    /// or the canonicalized path if the bundle id is unknown
+    pub fn dedup_key(&self) -> String {
+        match &self.bundle_id {
+            Some(bundle_id) => bundle_id.to_owned(),
+            None => self
+                .id
+                .canonicalize()
+                .unwrap_or_else(|_| self.id.to_path_buf())
+                .to_string_lossy()
+                .into_owned(),
         }
     }
 
+    /// Copy the path, version and source of this service, which decide if it is kept over
+    /// another one sharing its dedup key, without any other property
+    pub fn dedup_summary(&self) -> Service {
+        Service {
+            version: self.version.clone(),
+            cached: self.cached,
+            ..Service::bare(&self.id)
+        }
+    }
+
+    /// Get the command that launches this service
+    pub fn launch_command(&self) -> Command {
+        execute::command(&self.id, false)
+    }
+
     fn file_name<P: AsRef<Path>>(path: P) -> String {
         let path = path.as_ref();
         let path_process = path.file_stem().and_then(OsStr::to_str);
@@ -43,9 +382,26 @@ impl Service {
     }
 }
 
+/// Service matched by a query, with how it is matched
+#[derive(Debug, Clone)]
+pub struct RankedService {
+    pub service: Service,
+    pub score: u32,
+    /// Score scaled to `0.0..=1.0` against the highest score a match short of exact could
+    /// get for the query, comparable across queries: `score / max`, where `max` adds up
+    /// the prefix score and the length bonus of every term, the order bonus for more than
+    /// one term and the recency bonus. Exact matches are 1.0
+    pub normalized_score: f32,
+    /// Field of the service the query matched best
+    pub matched_field: MatchField,
+    /// Byte ranges of the matched parts in the matched field
+    pub ranges: Vec<Range<usize>>,
+}
+
 #[cfg(test)]
 mod service_test {
-    use crate::query::service::Service;
+    use crate::query::service::{strip_version, Service};
+    use crate::utils::fixture;
 
     #[test]
     fn test_serialize() {
@@ -90,4 +446,116 @@ mod service_test {
         ];
         assert_eq!(services, expected);
     }
+
+    #[test]
+    fn test_launch_command() {
+        let service = Service::new("/System/Applications/Book.app");
+        let command = service.launch_command();
+        assert_eq!(command.get_program(), "open");
+        let args = command.get_args().collect::<Vec<_>>();
+        assert_eq!(args, vec!["/System/Applications/Book.app"]);
+    }
+
+    #[test]
+    fn test_dedup_key_bundle_id() {
+        let dir = fixture::temp_dir("service_dedup_key_bundle_id");
+        let bundle = dir.join("Books.app");
+        fixture::create_info_plist(&bundle, &[("CFBundleIdentifier", "com.apple.iBooksX")]);
+        fixture::create_info_plist(
+            dir.join("Copy.app"),
+            &[("CFBundleIdentifier", "com.apple.iBooksX")],
+        );
+        std::os::unix::fs::symlink(&bundle, dir.join("Link.app")).unwrap();
+        let service = Service::new(&bundle);
+        assert_eq!(service.bundle_id(), Some("com.apple.iBooksX"));
+        assert_eq!(
+            service.dedup_key(),
+            Service::new(dir.join("Copy.app")).dedup_key()
+        );
+        assert_eq!(
+            service.dedup_key(),
+            Service::new(dir.join("Link.app")).dedup_key()
+        );
+    }
+
+    #[test]
+    fn test_dedup_key_path() {
+        let dir = fixture::temp_dir("service_dedup_key_path");
+        fixture::create_bundles(&dir, &["Books.app", "Other.app"]);
+        std::os::unix::fs::symlink(dir.join("Books.app"), dir.join("Link.app")).unwrap();
+        let service = Service::new(dir.join("Books.app"));
+        assert_eq!(service.bundle_id(), None);
+        assert_eq!(
+            service.dedup_key(),
+            Service::new(dir.join("Link.app")).dedup_key()
+        );
+        assert_ne!(
+            service.dedup_key(),
+            Service::new(dir.join("Other.app")).dedup_key()
+        );
+    }
+
+    #[test]
+    fn test_without_version() {
+        let service = Service::new("/Applications/Xcode-14.3.app").without_version();
+        assert_eq!(service.title, "Xcode");
+        let service = Service::new("/Applications/Xcode-14.3.app");
+        assert_eq!(service.title, "Xcode-14.3");
+    }
+
+    #[test]
+    fn test_strip_version() {
+        assert_eq!(strip_version("Python 3.11"), "Python");
+        assert_eq!(strip_version("Tool_v2.0"), "Tool");
+        assert_eq!(strip_version("Photos3"), "Photos3");
+        assert_eq!(strip_version("Go2Shell"), "Go2Shell");
+        assert_eq!(strip_version("1.0"), "1.0");
+        assert_eq!(strip_version("Foo-."), "Foo-.");
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let service = Service::new("/System/Applications/Book.app")
+            .with_metadata("shortcut", "⌘B")
+            .with_metadata("color", "red");
+        let serialized = serde_json::to_string(&service).expect("Unable to serialize");
+        let deserialized: Service =
+            serde_json::from_str(&serialized).expect("Unable to deserialize");
+        assert_eq!(deserialized.metadata(), service.metadata());
+        assert_eq!(deserialized.metadata()["shortcut"], "⌘B");
+        assert!(Service::new("/System/Applications/Book.app")
+            .metadata()
+            .is_empty());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_metadata_round_trip_framed() {
+        use crate::utils::serde::{decode_framed, encode_framed};
+
+        let service = Service::new("/System/Applications/Book.app")
+            .with_metadata("shortcut", "⌘B")
+            .with_metadata("color", "red");
+        let mut buffer = Vec::new();
+        encode_framed(&service, &mut buffer).expect("Failed to encode");
+        let decoded = decode_framed::<Service>(&buffer)
+            .next()
+            .expect("No record")
+            .expect("Failed to decode");
+        assert_eq!(decoded.metadata(), service.metadata());
+    }
+
+    #[test]
+    fn test_serialize_parent() {
+        let path = "/System/Applications/Book.app";
+        let service = Service::new(path).with_parent(Some("/System/Applications"));
+        let serialized = serde_json::to_string(&service).expect("Unable to serialize");
+        let expected = r#"{"title":"Book","subtitle":"/System/Applications/Book.app","id":"/System/Applications/Book.app","parent":"/System/Applications"}"#;
+        assert_eq!(serialized, expected);
+        let service: Service = serde_json::from_str(&serialized).expect("Unable to deserialize");
+        assert_eq!(
+            service.parent().and_then(|p| p.to_str()),
+            Some("/System/Applications")
+        );
+    }
 }
@@ -0,0 +1,157 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::query::service::Service;
+
+/// The `k` services with the least keys of a stream, deduplicated by `Service::dedup_key`
+/// as they arrive, equal keys in the order their dedup keys are first offered.
+/// Only the best `k` services are held, along with the dedup summaries of the services
+/// kept for all keys offered, so a duplicate of a dropped service is still decided as
+/// if the dropped one was held
+pub struct TopK<K> {
+    k: usize,
+    heap: BinaryHeap<(K, usize)>,
+    held: HashMap<usize, Service>,
+    kept: HashMap<String, (usize, Service)>,
+}
+
+impl<K: Ord> TopK<K> {
+    pub fn new(k: usize) -> Self {
+        TopK {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+            held: HashMap::with_capacity(k + 1),
+            kept: HashMap::new(),
+        }
+    }
+
+    /// Offer the service keyed by `key`. A duplicate replaces the service kept for its
+    /// dedup key if `preferred` prefers it, taking the position of the first one offered
+    /// whether the kept one is still held or not, and is dropped otherwise
+    pub fn push<P, F>(&mut self, service: Service, preferred: P, key: F)
+    where
+        P: FnOnce(&Service, &Service) -> bool,
+        F: FnOnce(&Service) -> K,
+    {
+        let next = self.kept.len();
+        let position = match self.kept.entry(service.dedup_key()) {
+            Entry::Occupied(mut entry) => {
+                let (position, summary) = entry.get_mut();
+                let kept = self.held.get(position).unwrap_or(summary);
+                if !preferred(&service, kept) {
+                    return;
+                }
+                *summary = service.dedup_summary();
+                let position = *position;
+                if self.held.remove(&position).is_some() {
+                    self.heap.retain(|(_, at)| *at != position);
+                }
+                position
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((next, service.dedup_summary()));
+                next
+            }
+        };
+        self.heap.push((key(&service), position));
+        self.held.insert(position, service);
+        if self.heap.len() > self.k {
+            if let Some((_, dropped)) = self.heap.pop() {
+                self.held.remove(&dropped);
+            }
+        }
+    }
+
+    /// Get the number of services held
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Take the held services, the least keyed first
+    pub fn into_sorted_vec(self) -> Vec<Service> {
+        let mut held = self.held;
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .filter_map(|(_, position)| held.remove(&position))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod top_k_test {
+    use std::cmp::Reverse;
+
+    use crate::query::service::Service;
+    use crate::query::top_k::TopK;
+
+    fn titles(services: &[Service]) -> Vec<&str> {
+        services
+            .iter()
+            .map(|service| service.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_keeps_best_k() {
+        let mut top = TopK::new(2);
+        for (score, name) in [(1, "Atlas"), (3, "Boat"), (2, "Chess"), (3, "Dice")] {
+            let service = Service::new(format!("/{}.app", name));
+            top.push(service, |_, _| false, |_| Reverse(score));
+            assert!(top.len() <= 2);
+        }
+        assert_eq!(titles(&top.into_sorted_vec()), ["Boat", "Dice"]);
+        assert!(TopK::<u32>::new(0).into_sorted_vec().is_empty());
+    }
+
+    #[test]
+    fn test_dedups() {
+        let mut top = TopK::new(2);
+        top.push(Service::new("/Atlas.app"), |_, _| false, |_| Reverse(2));
+        top.push(Service::new("/Boat.app"), |_, _| false, |_| Reverse(3));
+        top.push(Service::new("/Atlas.app"), |_, _| false, |_| Reverse(4));
+        assert_eq!(titles(&top.into_sorted_vec()), ["Boat", "Atlas"]);
+        let mut top = TopK::new(2);
+        top.push(Service::new("/Atlas.app"), |_, _| false, |_| Reverse(2));
+        top.push(Service::new("/Boat.app"), |_, _| false, |_| Reverse(3));
+        top.push(Service::new("/Atlas.app"), |_, _| true, |_| Reverse(4));
+        assert_eq!(titles(&top.into_sorted_vec()), ["Atlas", "Boat"]);
+    }
+
+    #[test]
+    fn test_dedups_dropped() {
+        let newer = |service: &Service, kept: &Service| service.is_cached() && !kept.is_cached();
+        let mut top = TopK::new(1);
+        top.push(Service::new("/Atlas.app"), newer, |_| 2);
+        top.push(Service::new("/Boat.app"), newer, |_| 1);
+        top.push(Service::new("/Atlas.app"), newer, |_| 0);
+        assert_eq!(titles(&top.into_sorted_vec()), ["Boat"]);
+        let mut top = TopK::new(1);
+        top.push(Service::new("/Atlas.app"), newer, |_| 2);
+        top.push(Service::new("/Boat.app"), newer, |_| 1);
+        let cached = Service::new("/Atlas.app").with_cached(true);
+        top.push(cached, newer, |_| 0);
+        top.push(Service::new("/Atlas.app"), newer, |_| 0);
+        let services = top.into_sorted_vec();
+        assert_eq!(titles(&services), ["Atlas"]);
+        assert!(services[0].is_cached());
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut top = TopK::new(5);
+        let mut peak = 0;
+        for index in 0..10_000u32 {
+            let service = Service::new(format!("/Applications/App {}.app", index));
+            top.push(service, |_, _| false, |_| Reverse(index % 97));
+            peak = peak.max(top.len());
+        }
+        assert_eq!(peak, 5);
+        let services = top.into_sorted_vec();
+        assert_eq!(
+            titles(&services),
+            ["App 96", "App 193", "App 290", "App 387", "App 484"]
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::configurator::Profile;
+use crate::query::matcher::Wildcard;
+use crate::query::service::Service;
+
+/// File listing patterns of names to skip in the folder holding it and below
+pub const IGNORE_FILE: &str = ".launchignore";
+
+/// Folders a complete walk found nothing like a bundle under, with when each was last
+/// modified, and the hash of the settings deciding what the walk finds
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmptyFolders {
+    settings: u64,
+    folders: BTreeMap<PathBuf, SystemTime>,
+}
+
+impl EmptyFolders {
+    /// Get the folders walked into without any occupied folder under them
+    pub fn from_walk(walked: &WalkedFolders, settings: u64) -> Self {
+        let holding = walked
+            .occupied
+            .iter()
+            .flat_map(|folder| folder.ancestors())
+            .collect::<HashSet<_>>();
+        let folders = walked
+            .folders
+            .iter()
+            .filter(|folder| !holding.contains(folder.as_path()))
+            .filter_map(|folder| Some((folder.clone(), modified(folder)?)))
+            .collect();
+        EmptyFolders { settings, folders }
+    }
+
+    /// Get the hash of the settings of the walk finding these folders
+    pub fn settings(&self) -> u64 {
+        self.settings
+    }
+
+    /// Check if the folder had nothing like a bundle under it, and neither it nor
+    /// any folder under it is modified since
+    pub fn is_unchanged(&self, folder: &Path) -> bool {
+        self.folders.contains_key(folder)
+            && self
+                .under(folder)
+                .all(|(path, time)| modified(path) == Some(*time))
+    }
+
+    /// Get the folder and the folders under it
+    fn under<'e>(
+        &'e self,
+        folder: &'e Path,
+    ) -> impl Iterator<Item = (&'e PathBuf, &'e SystemTime)> {
+        self.folders
+            .range(folder.to_path_buf()..)
+            .take_while(move |(path, _)| path.starts_with(folder))
+    }
+
+    pub fn len(&self) -> usize {
+        self.folders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.folders.is_empty()
+    }
+}
+
+/// Folders a walk went into or skipped as empty, and the ones among them holding anything
+/// a walk with other settings may find, e.g. ignored bundles or folders not walked into
+#[derive(Default)]
+pub struct WalkedFolders {
+    pub folders: Vec<PathBuf>,
+    pub occupied: HashSet<PathBuf>,
+}
+
+fn modified(folder: &Path) -> Option<SystemTime> {
+    folder
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// What a walk of the roots reuses from the last walk, and records for the next one
+#[derive(Default)]
+pub struct WalkHistory<'w> {
+    /// Services read by the last walk, reused for bundles unchanged since then
+    pub previous: Option<&'w HashMap<PathBuf, Service>>,
+    /// Folders the last walk found no services under, skipped while unchanged
+    pub empty: Option<&'w EmptyFolders>,
+    /// Folders walked into or skipped as empty, if recorded
+    pub walked: Option<RefCell<WalkedFolders>>,
+}
+
+/// Bounds of a walk below a root, passed down to the folders in it
+#[derive(Clone)]
+pub struct WalkScope<'w> {
+    pub profile: &'w Profile,
+    /// Device the walk stays on, if enabled
+    pub device: Option<u64>,
+    /// When the walk of the root stops, if limited
+    pub deadline: Option<Instant>,
+    /// Services read by the last walk, reused for bundles unchanged since then
+    pub previous: Option<&'w HashMap<PathBuf, Service>>,
+    /// Folders the last walk found no services under
+    pub empty: Option<&'w EmptyFolders>,
+    /// Folders walked into, if recorded
+    pub walked: Option<&'w RefCell<WalkedFolders>>,
+    /// Whether folders below the root are walked into
+    pub recursive: bool,
+    /// Whether the `Info.plist` of bundles found is read
+    pub enriched: bool,
+    /// Whether services found carry their depth below the root
+    pub depth_recorded: bool,
+    /// Patterns from the ignore files of the folders walked into
+    ignores: Vec<Wildcard>,
+}
+
+impl<'w> WalkScope<'w> {
+    pub fn new(profile: &'w Profile) -> Self {
+        WalkScope {
+            profile,
+            device: None,
+            deadline: None,
+            previous: None,
+            empty: None,
+            walked: None,
+            recursive: true,
+            enriched: true,
+            depth_recorded: false,
+            ignores: Vec::new(),
+        }
+    }
+
+    pub fn with_device(mut self, device: Option<u64>) -> Self {
+        self.device = device;
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Reuse services and skip empty folders found by the last walk, and record folders
+    /// walked into for the next one
+    pub fn with_history(mut self, history: &'w WalkHistory<'w>) -> Self {
+        self.previous = history.previous;
+        self.empty = history.empty;
+        self.walked = history.walked.as_ref();
+        self
+    }
+
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    pub fn with_enriched(mut self, enriched: bool) -> Self {
+        self.enriched = enriched;
+        self
+    }
+
+    pub fn with_depth_recorded(mut self, depth_recorded: bool) -> Self {
+        self.depth_recorded = depth_recorded;
+        self
+    }
+
+    /// Get the service read by the last walk from the bundle, if the bundle is unchanged
+    pub fn unchanged(&self, bundle: &Path) -> Option<&'w Service> {
+        self.previous?
+            .get(bundle)
+            .filter(|service| service.is_unchanged())
+    }
+
+    /// Check if the last walk found no services under the folder and nothing under it
+    /// changed since, so it is skipped. Skipped folders are recorded as walked into
+    /// along with the folders under them
+    pub fn skips(&self, folder: &Path) -> bool {
+        let empty = match self.empty {
+            Some(empty) if empty.is_unchanged(folder) => empty,
+            _ => return false,
+        };
+        if let Some(walked) = self.walked {
+            let skipped = empty.under(folder).map(|(path, _)| path.clone());
+            walked.borrow_mut().folders.extend(skipped);
+        }
+        true
+    }
+
+    /// Record that the folder is walked into, if recording
+    pub fn record(&self, folder: &Path) {
+        if let Some(walked) = self.walked {
+            walked.borrow_mut().folders.push(folder.to_path_buf());
+        }
+    }
+
+    /// Record that the folder holds something like a bundle, or cannot be read as a whole,
+    /// so it is never skipped as empty, if recording
+    pub fn occupy(&self, folder: &Path) {
+        if let Some(walked) = self.walked {
+            walked.borrow_mut().occupied.insert(folder.to_path_buf());
+        }
+    }
+
+    /// Check if the deadline has passed
+    pub fn is_expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Get the scope inside the folder, if it has an ignore file adding patterns.
+    /// Each line of the file is a wildcard pattern, except empty lines and lines starting with `#`
+    pub fn enter(&self, folder: &Path) -> Option<Self> {
+        let content = fs::read_to_string(folder.join(IGNORE_FILE)).ok()?;
+        let patterns = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Wildcard::parse);
+        Some(WalkScope {
+            ignores: self.ignores.iter().cloned().chain(patterns).collect(),
+            ..self.clone()
+        })
+    }
+
+    /// Check if the name of the path matches any pattern of the ignore files
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        !self.ignores.is_empty()
+            && path
+                .file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(|name| self.ignores.iter().any(|pattern| pattern.matches(name)))
+    }
+}
+
+/// How far a walk below an entry got, with the scopes of the folders entered
+pub struct Walk<'w> {
+    pub entry: PathBuf,
+    /// Scopes of the folders entered, the scope of the entry first
+    pub scopes: Vec<WalkScope<'w>>,
+    /// Paths left to check, with the indices of their scopes and their depths below the entry
+    pub entries: VecDeque<(PathBuf, usize, usize)>,
+    /// Error reading the entry, if it is a folder that cannot be read
+    pub error: Option<io::Error>,
+}
+
+impl<'w> Walk<'w> {
+    pub fn new(entry: &Path, scope: &WalkScope<'w>) -> Self {
+        Walk {
+            entry: entry.to_path_buf(),
+            scopes: vec![scope.clone()],
+            entries: VecDeque::from([(entry.to_path_buf(), 0, 0)]),
+            error: None,
+        }
+    }
+
+    /// Get the scope the walk started with
+    pub fn scope(&self) -> &WalkScope<'w> {
+        &self.scopes[0]
+    }
+}
+
+#[cfg(test)]
+mod walk_test {
+    use std::fs;
+
+    use crate::configurator::Profile;
+    use crate::query::walk::{EmptyFolders, WalkScope, WalkedFolders, IGNORE_FILE};
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_empty_folders() {
+        let dir = fixture::temp_dir("walk_empty_folders");
+        fixture::create_bundles(&dir, &["apps/Atlas.app"]);
+        fs::create_dir_all(dir.join("apps/empty/deep")).unwrap();
+        let walked = WalkedFolders {
+            folders: ["apps", "apps/empty", "apps/empty/deep"]
+                .map(|folder| dir.join(folder))
+                .to_vec(),
+            occupied: [dir.join("apps")].into(),
+        };
+        let empty = EmptyFolders::from_walk(&walked, 1);
+        assert_eq!(empty.len(), 2);
+        assert_eq!(empty.settings(), 1);
+        assert!(!empty.is_unchanged(&dir.join("apps")));
+        assert!(empty.is_unchanged(&dir.join("apps/empty")));
+        fs::write(dir.join("apps/empty/deep/notes.txt"), "").unwrap();
+        assert!(!empty.is_unchanged(&dir.join("apps/empty")));
+    }
+
+    #[test]
+    fn test_enter_ignore_file() {
+        let dir = fixture::temp_dir("walk_enter_ignore_file");
+        fixture::create_bundles(&dir, &["outer/inner"]);
+        fs::write(
+            dir.join("outer").join(IGNORE_FILE),
+            "# Betas\n*Beta.app\n\n",
+        )
+        .unwrap();
+        fs::write(dir.join("outer/inner").join(IGNORE_FILE), "Old?.app").unwrap();
+        let profile = Profile::default();
+        let root = WalkScope::new(&profile);
+        assert!(root.enter(&dir).is_none());
+        let outer = root.enter(&dir.join("outer")).expect("No ignore file");
+        assert!(outer.is_ignored(&dir.join("outer/Safari Beta.app")));
+        assert!(!outer.is_ignored(&dir.join("outer/Old1.app")));
+        assert!(!outer.is_ignored(&dir.join("outer/# Betas")));
+        let inner = outer
+            .enter(&dir.join("outer/inner"))
+            .expect("No ignore file");
+        assert!(inner.is_ignored(&dir.join("outer/inner/Old1.app")));
+        assert!(inner.is_ignored(&dir.join("outer/inner/Xcode Beta.app")));
+        assert!(!root.is_ignored(&dir.join("Safari Beta.app")));
+    }
+}
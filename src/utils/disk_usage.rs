@@ -0,0 +1,40 @@
+use std::fs;
+use std::path::Path;
+
+/// Total size in bytes of the files under the path, without following symlinks.
+/// Entries that cannot be read are skipped
+pub fn disk_usage<P: AsRef<Path>>(path: P) -> u64 {
+    let metadata = match fs::symlink_metadata(path.as_ref()) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| disk_usage(entry.path()))
+                .sum()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod disk_usage_test {
+    use std::fs;
+
+    use crate::utils::disk_usage::disk_usage;
+    use crate::utils::fixture;
+
+    #[test]
+    fn test_disk_usage() {
+        let dir = fixture::temp_dir("disk_usage");
+        fixture::create_bundles(&dir, &["Atlas.app/Contents/MacOS"]);
+        fs::write(dir.join("Atlas.app/Contents/Info.plist"), [0; 10]).unwrap();
+        fs::write(dir.join("Atlas.app/Contents/MacOS/Atlas"), [0; 32]).unwrap();
+        assert_eq!(disk_usage(dir.join("Atlas.app")), 42);
+        assert_eq!(disk_usage(dir.join("Missing.app")), 0);
+    }
+}
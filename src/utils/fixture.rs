@@ -0,0 +1,69 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Create an empty directory under the system temp folder
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("launch_service").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("Failed to create temp dir");
+    dir
+}
+
+/// Create bundle folders with given names under `dir`
+pub fn create_bundles<P: AsRef<Path>>(dir: P, names: &[&str]) {
+    names.iter().for_each(|name| {
+        fs::create_dir_all(dir.as_ref().join(name)).expect("Failed to create bundle");
+    });
+}
+
+/// Create a bundle at `bundle` with a binary `Contents/Info.plist` holding the properties
+pub fn create_binary_info_plist<P: AsRef<Path>>(bundle: P, properties: &[(&str, &str)]) {
+    let contents = bundle.as_ref().join("Contents");
+    fs::create_dir_all(&contents).expect("Failed to create bundle");
+    let dictionary = properties
+        .iter()
+        .map(|(key, value)| (key.to_string(), plist::Value::from(*value)))
+        .collect::<plist::Dictionary>();
+    plist::to_file_binary(contents.join("Info.plist"), &dictionary)
+        .expect("Failed to write Info.plist");
+}
+
+/// Create a bundle at `bundle` with an XML `Contents/Info.plist` holding the properties
+pub fn create_info_plist<P: AsRef<Path>>(bundle: P, properties: &[(&str, &str)]) {
+    let contents = bundle.as_ref().join("Contents");
+    fs::create_dir_all(&contents).expect("Failed to create bundle");
+    let entries = properties
+        .iter()
+        .map(|(key, value)| format!("<key>{}</key><string>{}</string>", key, value))
+        .collect::<String>();
+    let plist = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0"><dict>{}</dict></plist>"#,
+        entries
+    );
+    fs::write(contents.join("Info.plist"), plist).expect("Failed to write Info.plist");
+}
+
+/// Create an iOS app installed on Mac at `bundle`, wrapping a bundle with a binary
+/// `Info.plist` holding the properties at its top, and an empty executable of the bundle name
+pub fn create_wrapped_bundle<P: AsRef<Path>>(bundle: P, properties: &[(&str, &str)]) -> PathBuf {
+    let bundle = bundle.as_ref();
+    let name = bundle.file_name().expect("Bundle has no name");
+    let wrapped = bundle.join("Wrapper").join(name);
+    fs::create_dir_all(&wrapped).expect("Failed to create bundle");
+    let dictionary = properties
+        .iter()
+        .map(|(key, value)| (key.to_string(), plist::Value::from(*value)))
+        .collect::<plist::Dictionary>();
+    plist::to_file_binary(wrapped.join("Info.plist"), &dictionary)
+        .expect("Failed to write Info.plist");
+    let executable = Path::new(name).file_stem().expect("Bundle has no name");
+    fs::write(wrapped.join(executable), b"").expect("Failed to write executable");
+    std::os::unix::fs::symlink(
+        Path::new("Wrapper").join(name),
+        bundle.join("WrappedBundle"),
+    )
+    .expect("Failed to link wrapped bundle");
+    wrapped
+}
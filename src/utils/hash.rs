@@ -0,0 +1,28 @@
+const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0100_0000_01b3;
+
+/// FNV-1a hash of the bytes, which stays the same across runs and builds
+pub fn stable_hash<B: AsRef<[u8]>>(bytes: B) -> u64 {
+    bytes.as_ref().iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod hash_test {
+    use crate::utils::hash::stable_hash;
+
+    #[test]
+    fn test_stable_hash() {
+        assert_eq!(stable_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_stable_hash_differs() {
+        assert_ne!(
+            stable_hash("/Applications"),
+            stable_hash("/System/Applications")
+        );
+    }
+}
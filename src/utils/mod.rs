@@ -1,4 +1,14 @@
+pub use disk_usage::disk_usage;
+pub use hash::stable_hash;
+pub use system::{spotlight_search, system_version};
 pub use tilde_expander::expand_tilde;
+pub use version::compare_versions;
 
+mod disk_usage;
+#[cfg(test)]
+pub mod fixture;
+mod hash;
 pub mod serde;
+mod system;
 mod tilde_expander;
+mod version;
@@ -2,5 +2,37 @@ use serde::de::DeserializeOwned;
 use serde_json::Result;
 
 pub fn deserialize_from_bytes<D: DeserializeOwned>(bytes: &[u8]) -> Result<D> {
-    serde_json::from_slice(bytes)
+    deserialize_from_bytes_with(bytes, false)
+}
+
+/// Deserialize object from the bytes. If `lossy`, invalid UTF-8 sequences are replaced
+/// with `U+FFFD` instead of failing, so slightly corrupted content is still read
+pub fn deserialize_from_bytes_with<D: DeserializeOwned>(bytes: &[u8], lossy: bool) -> Result<D> {
+    match lossy {
+        true => serde_json::from_str(&String::from_utf8_lossy(bytes)),
+        false => serde_json::from_slice(bytes),
+    }
+}
+
+#[cfg(test)]
+mod deserializer_test {
+    use crate::utils::serde::deserializer::deserialize_from_bytes_with;
+
+    #[test]
+    fn test_invalid_utf8() {
+        let bytes = b"\"Bo\xffks\"";
+        assert!(deserialize_from_bytes_with::<String>(bytes, false).is_err());
+        let name: String = deserialize_from_bytes_with(bytes, true).expect("Failed to deserialize");
+        assert_eq!(name, "Bo\u{fffd}ks");
+    }
+
+    #[test]
+    fn test_valid_utf8() {
+        let bytes = "\"Bücher\"".as_bytes();
+        for lossy in [false, true] {
+            let name: String =
+                deserialize_from_bytes_with(bytes, lossy).expect("Failed to deserialize");
+            assert_eq!(name, "Bücher");
+        }
+    }
 }
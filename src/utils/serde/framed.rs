@@ -0,0 +1,100 @@
+use std::convert::TryFrom;
+use std::io::{self, Error, ErrorKind};
+
+use bincode::{Decode, Encode};
+
+use crate::utils::serde::MAX_FRAME_SIZE;
+
+const LENGTH_SIZE: usize = std::mem::size_of::<u32>();
+
+/// Append the object encoded by bincode to the buffer, prefixed by its length
+/// as a little endian `u32`. Fails if the encoded object is larger than `MAX_FRAME_SIZE`
+pub fn encode_framed<E: Encode>(obj: &E, buffer: &mut Vec<u8>) -> io::Result<()> {
+    let bytes = bincode::encode_to_vec(obj, bincode::config::standard())
+        .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+    if bytes.len() > MAX_FRAME_SIZE {
+        let message = format!(
+            "Frame size {} exceeds the maximum {}",
+            bytes.len(),
+            MAX_FRAME_SIZE
+        );
+        return Err(Error::new(ErrorKind::InvalidData, message));
+    }
+    let length =
+        u32::try_from(bytes.len()).map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+    buffer.extend_from_slice(&length.to_le_bytes());
+    buffer.extend_from_slice(&bytes);
+    Ok(())
+}
+
+/// Decode objects one by one from length prefixed records, in the order they were encoded
+pub fn decode_framed<D: Decode<()>>(mut bytes: &[u8]) -> impl Iterator<Item = io::Result<D>> + '_ {
+    std::iter::from_fn(move || {
+        if bytes.is_empty() {
+            return None;
+        }
+        let record = split_record(&mut bytes).and_then(|record| {
+            bincode::decode_from_slice(record, bincode::config::standard())
+                .map(|(obj, _)| obj)
+                .map_err(|error| Error::new(ErrorKind::InvalidData, error))
+        });
+        if record.is_err() {
+            bytes = &[];
+        }
+        Some(record)
+    })
+}
+
+/// Split the first record off the bytes
+fn split_record<'a>(bytes: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    if bytes.len() < LENGTH_SIZE {
+        return Err(Error::from(ErrorKind::UnexpectedEof));
+    }
+    let (length, rest) = bytes.split_at(LENGTH_SIZE);
+    let mut length_bytes = [0; LENGTH_SIZE];
+    length_bytes.copy_from_slice(length);
+    let length = u32::from_le_bytes(length_bytes) as usize;
+    if rest.len() < length {
+        return Err(Error::from(ErrorKind::UnexpectedEof));
+    }
+    let (record, rest) = rest.split_at(length);
+    *bytes = rest;
+    Ok(record)
+}
+
+#[cfg(test)]
+mod framed_test {
+    use std::io::ErrorKind;
+
+    use crate::utils::serde::{decode_framed, encode_framed, MAX_FRAME_SIZE};
+
+    #[test]
+    fn test_round_trip() {
+        let mut buffer = Vec::new();
+        encode_framed(&"Books".to_owned(), &mut buffer).expect("Failed to encode");
+        encode_framed(&"Safari".to_owned(), &mut buffer).expect("Failed to encode");
+        let decoded = decode_framed::<String>(&buffer)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Failed to decode");
+        assert_eq!(decoded, vec!["Books", "Safari"]);
+    }
+
+    #[test]
+    fn test_oversized() {
+        let mut buffer = Vec::new();
+        let error = encode_framed(&"a".repeat(MAX_FRAME_SIZE), &mut buffer).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut buffer = Vec::new();
+        encode_framed(&"Books".to_owned(), &mut buffer).expect("Failed to encode");
+        buffer.pop();
+        let mut decoded = decode_framed::<String>(&buffer);
+        let error = decoded.next().expect("No record").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert!(decoded.next().is_none());
+    }
+}
@@ -1,5 +1,13 @@
 pub use deserializer::deserialize_from_bytes;
+#[cfg(not(feature = "bincode"))]
+pub use deserializer::deserialize_from_bytes_with;
+#[cfg(feature = "bincode")]
+pub use framed::{decode_framed, encode_framed};
 pub use serializer::serialize_to_string;
+#[cfg(any(test, feature = "bincode"))]
+pub use serializer::MAX_FRAME_SIZE;
 
 mod deserializer;
+#[cfg(feature = "bincode")]
+mod framed;
 mod serializer;
@@ -1,7 +1,34 @@
+use serde::ser::Error;
 use serde::Serialize;
 use serde_json::Result;
 
-/// Serialize object to [size;bytes] format
+/// Largest serialized object accepted, so a pathological name or path
+/// never produces an unbounded frame
+pub const MAX_FRAME_SIZE: usize = 1 << 20;
+
+/// Serialize object to [size;bytes] format.
+/// Fails if the serialized object is larger than `MAX_FRAME_SIZE`
 pub fn serialize_to_string<S: Serialize>(obj: &S) -> Result<String> {
-    serde_json::to_string(obj)
+    let serialized = serde_json::to_string(obj)?;
+    if serialized.len() > MAX_FRAME_SIZE {
+        return Err(serde_json::Error::custom(format!(
+            "Serialized size {} exceeds the maximum {}",
+            serialized.len(),
+            MAX_FRAME_SIZE
+        )));
+    }
+    Ok(serialized)
+}
+
+#[cfg(test)]
+mod serializer_test {
+    use crate::utils::serde::serializer::{serialize_to_string, MAX_FRAME_SIZE};
+
+    #[test]
+    fn test_oversized() {
+        let name = "a".repeat(MAX_FRAME_SIZE);
+        assert!(serialize_to_string(&name).is_err());
+        let name = "a".repeat(MAX_FRAME_SIZE - 2);
+        assert_eq!(serialize_to_string(&name).unwrap().len(), MAX_FRAME_SIZE);
+    }
 }
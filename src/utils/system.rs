@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Get the version of the running macOS, e.g. `10.15.7`
+pub fn system_version() -> Option<String> {
+    let output = Command::new("sw_vers")
+        .arg("-productVersion")
+        .output()
+        .ok()?;
+    let version = String::from_utf8(output.stdout).ok()?;
+    Some(version.trim().to_owned()).filter(|version| !version.is_empty())
+}
+
+/// Get paths of application bundles whose names contain the query through Spotlight
+pub fn spotlight_search(query: &str) -> Vec<PathBuf> {
+    let name = query.replace(['\'', '"', '\\', '*'], "");
+    let expression = format!(
+        "kMDItemContentType == 'com.apple.application-bundle' && kMDItemDisplayName == '*{}*'cd",
+        name.trim()
+    );
+    let output = match Command::new("mdfind").arg(expression).output() {
+        Ok(output) => output,
+        Err(_) => return Vec::new(),
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
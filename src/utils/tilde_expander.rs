@@ -1,7 +1,5 @@
 use std::path::PathBuf;
 
-use shellexpand;
-
 pub fn expand_tilde(path: &str) -> PathBuf {
     PathBuf::from(shellexpand::tilde(path).to_string())
 }
@@ -26,4 +24,4 @@ mod utils_test {
         let res = expand_tilde(before);
         assert_eq!(res, PathBuf::from(before));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,46 @@
+use std::cmp::Ordering;
+
+/// Compare dot separated versions numerically, where missing components count as 0.
+/// Falls back to comparing the strings if any component is not a number
+pub fn compare_versions(lhs: &str, rhs: &str) -> Ordering {
+    match (parse(lhs), parse(rhs)) {
+        (Some(mut lhs), Some(mut rhs)) => {
+            let len = lhs.len().max(rhs.len());
+            lhs.resize(len, 0);
+            rhs.resize(len, 0);
+            lhs.cmp(&rhs)
+        }
+        _ => lhs.cmp(rhs),
+    }
+}
+
+fn parse(version: &str) -> Option<Vec<u64>> {
+    version
+        .trim()
+        .split('.')
+        .map(|component| component.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod version_test {
+    use std::cmp::Ordering;
+
+    use crate::utils::version::compare_versions;
+
+    #[test]
+    fn test_compare_numeric() {
+        assert_eq!(compare_versions("10.15", "11.0"), Ordering::Less);
+        assert_eq!(compare_versions("10.9", "10.10"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compare_missing_component() {
+        assert_eq!(compare_versions("11", "11.0.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_compare_fallback() {
+        assert_eq!(compare_versions("1.0b", "1.0a"), Ordering::Greater);
+    }
+}